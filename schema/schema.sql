@@ -30,7 +30,9 @@ CREATE TABLE section (
   suser uuid references ruser (id),
   created_time timestamp not null default current_timestamp,
   status smallint not null default 0,
-  weight double precision not null default 0.0                 -- new
+  weight double precision not null default 0.0,                -- new
+  -- its articles are questions which can get an accepted answer
+  is_qa boolean not null default false                          -- new
 );
 
 CREATE TABLE article (
@@ -45,7 +47,10 @@ CREATE TABLE article (
   stype INTEGER NOT NULL,
   created_time timestamp not null default current_timestamp,
   status smallint not null default 0,
-  updated_time timestamp default now()::timestamp + '-1 year'
+  updated_time timestamp default now()::timestamp + '-1 year',
+  deleted_by uuid references ruser (id),                        -- new
+  deleted_time timestamp,                                       -- new
+  score integer not null default 0                              -- new
 );
 
 CREATE TABLE comment (
@@ -55,9 +60,18 @@ CREATE TABLE comment (
   article_id uuid references article (id) not null,
   author_id uuid references ruser (id) not null,
   created_time timestamp not null default current_timestamp,
-  status smallint not null default 0
+  status smallint not null default 0,
+  parent_id uuid references comment (id) on delete set null,     -- new
+  deleted_by uuid references ruser (id),                        -- new
+  deleted_time timestamp,                                       -- new
+  score integer not null default 0                              -- new
 );
 
+create index comment_article_id on comment (article_id);
+
+-- comment is created after article, so the accepted answer is added here
+alter table article add column accepted_comment_id uuid references comment (id) on delete set null;   -- new
+
 CREATE TABLE articleweight (
   id uuid primary key default gen_random_uuid(),
   section_id uuid references section (id) not null,
@@ -66,3 +80,165 @@ CREATE TABLE articleweight (
   created_time timestamp not null default current_timestamp
 );
 
+
+CREATE TABLE user_totp (
+  user_id uuid primary key references ruser (id),
+  secret VARCHAR NOT NULL,
+  enabled boolean not null default false,
+  recovery_codes VARCHAR NOT NULL default '',
+  created_time timestamp not null default current_timestamp
+);
+
+CREATE TABLE api_token (
+  id uuid primary key default gen_random_uuid(),
+  user_id uuid references ruser (id) not null,
+  name VARCHAR NOT NULL,
+  token_hash VARCHAR unique NOT NULL,
+  scopes VARCHAR NOT NULL,
+  created_time timestamp not null default current_timestamp,
+  last_used_time timestamp
+);
+
+CREATE TABLE notification (
+  id uuid primary key default gen_random_uuid(),
+  user_id uuid references ruser (id) not null,
+  ntype smallint not null,
+  actor_id uuid references ruser (id) not null,
+  article_id uuid references article (id) on delete cascade not null,
+  comment_id uuid references comment (id) on delete cascade,
+  is_read boolean not null default false,
+  created_time timestamp not null default current_timestamp,
+  detail VARCHAR NOT NULL default ''                            -- new
+);
+
+create index notification_user_id on notification (user_id, created_time);
+
+-- a row only for the types a user turned off
+CREATE TABLE notification_pref (
+  user_id uuid references ruser (id) on delete cascade not null,
+  ntype smallint not null,
+  enabled boolean not null default true,
+  primary key (user_id, ntype)
+);
+
+CREATE TABLE report (
+  id uuid primary key default gen_random_uuid(),
+  target_kind VARCHAR NOT NULL,
+  target_id uuid not null,
+  article_id uuid references article (id) on delete cascade not null,
+  reporter_id uuid references ruser (id) not null,
+  reason VARCHAR NOT NULL,
+  status smallint not null default 0,
+  created_time timestamp not null default current_timestamp,
+  resolved_by uuid references ruser (id),
+  resolved_time timestamp
+);
+
+create index report_target_id on report (target_id, status);
+
+CREATE TABLE article_revision (
+  id uuid primary key default gen_random_uuid(),
+  article_id uuid references article (id) on delete cascade not null,
+  editor_id uuid references ruser (id) not null,
+  title VARCHAR NOT NULL,
+  tags VARCHAR NOT NULL,
+  raw_content VARCHAR NOT NULL,
+  created_time timestamp not null default current_timestamp
+);
+
+create index article_revision_article_id on article_revision (article_id, created_time);
+
+CREATE TABLE tag (
+  id uuid primary key default gen_random_uuid(),
+  name VARCHAR NOT NULL unique,
+  created_time timestamp not null default current_timestamp
+);
+
+CREATE TABLE article_tag (
+  article_id uuid references article (id) on delete cascade not null,
+  tag_id uuid references tag (id) on delete cascade not null,
+  primary key (article_id, tag_id)
+);
+
+create index article_tag_tag_id on article_tag (tag_id);
+
+-- email is opt-in, no row means no email
+CREATE TABLE email_pref (
+  user_id uuid primary key references ruser (id) on delete cascade,
+  email_replies boolean not null default false,
+  email_mentions boolean not null default false,
+  digest smallint not null default 0,
+  unsubscribe_token VARCHAR NOT NULL unique,
+  last_digest_time timestamp not null default current_timestamp,
+  -- only mailed once the link sent to it was followed
+  email VARCHAR NOT NULL default '',
+  email_confirmed boolean not null default false,
+  confirm_token VARCHAR unique
+);
+
+CREATE TABLE section_follow (
+  user_id uuid references ruser (id) on delete cascade not null,
+  section_id uuid references section (id) on delete cascade not null,
+  created_time timestamp not null default current_timestamp,
+  primary key (user_id, section_id)
+);
+
+CREATE TABLE webhook (
+  id uuid primary key default gen_random_uuid(),
+  url VARCHAR NOT NULL,
+  secret VARCHAR NOT NULL,
+  events VARCHAR NOT NULL,
+  is_active boolean not null default true,
+  created_time timestamp not null default current_timestamp
+);
+
+-- one row per event and hook, kept as the delivery log
+CREATE TABLE webhook_delivery (
+  id uuid primary key default gen_random_uuid(),
+  webhook_id uuid references webhook (id) on delete cascade not null,
+  event VARCHAR NOT NULL,
+  payload VARCHAR NOT NULL,
+  status smallint not null default 0,
+  attempts integer not null default 0,
+  response_status integer,
+  response_body VARCHAR NOT NULL default '',
+  error VARCHAR NOT NULL default '',
+  next_attempt_time timestamp not null default current_timestamp,
+  created_time timestamp not null default current_timestamp,
+  delivered_time timestamp
+);
+
+create index webhook_delivery_webhook_id on webhook_delivery (webhook_id, created_time);
+create index webhook_delivery_due on webhook_delivery (status, next_attempt_time);
+
+-- one vote per user and target, the target keeps the sum in its score column
+CREATE TABLE vote (
+  user_id uuid references ruser (id) on delete cascade not null,
+  target_kind VARCHAR NOT NULL,
+  target_id uuid not null,
+  value smallint not null,
+  created_time timestamp not null default current_timestamp,
+  primary key (user_id, target_id)
+);
+
+create index vote_target_id on vote (target_id);
+create index article_section_score on article (section_id, score);
+
+CREATE TABLE bookmark (
+  user_id uuid references ruser (id) on delete cascade not null,
+  article_id uuid references article (id) on delete cascade not null,
+  notify boolean not null default false,
+  created_time timestamp not null default current_timestamp,
+  primary key (user_id, article_id)
+);
+
+create index bookmark_article_id on bookmark (article_id);
+
+CREATE TABLE user_follow (
+  follower_id uuid references ruser (id) on delete cascade not null,
+  followee_id uuid references ruser (id) on delete cascade not null,
+  created_time timestamp not null default current_timestamp,
+  primary key (follower_id, followee_id)
+);
+
+create index user_follow_followee_id on user_follow (followee_id);
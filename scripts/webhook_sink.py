@@ -0,0 +1,40 @@
+#!/usr/bin/env python3
+# A local receiver for trying out webhooks: prints every delivery and checks
+# its signature. Add http://127.0.0.1:9000/ as a webhook with the same secret.
+#
+#   ./scripts/webhook_sink.py [port] [secret]
+#
+# Answer with a 500 for a while to watch the retries: FAIL=1 ./scripts/webhook_sink.py
+import hashlib
+import hmac
+import os
+import sys
+from http.server import BaseHTTPRequestHandler, HTTPServer
+
+PORT = int(sys.argv[1]) if len(sys.argv) > 1 else 9000
+SECRET = sys.argv[2] if len(sys.argv) > 2 else "changeme"
+
+
+class Sink(BaseHTTPRequestHandler):
+    def do_POST(self):
+        body = self.rfile.read(int(self.headers.get("Content-Length", 0)))
+        expected = "sha256=" + hmac.new(SECRET.encode(), body, hashlib.sha256).hexdigest()
+        signature = self.headers.get("X-Forustm-Signature-256", "")
+        valid = hmac.compare_digest(expected, signature)
+
+        print("--", self.headers.get("X-Forustm-Event"), self.headers.get("X-Forustm-Delivery"))
+        print("signature", "ok" if valid else "MISMATCH")
+        print(body.decode("utf-8", "replace"))
+
+        code = 500 if os.environ.get("FAIL") else (200 if valid else 401)
+        self.send_response(code)
+        self.end_headers()
+        self.wfile.write(b"ok" if code == 200 else b"no")
+
+    def log_message(self, format, *args):
+        pass
+
+
+if __name__ == "__main__":
+    print("webhook sink on http://127.0.0.1:%d/" % PORT)
+    HTTPServer(("127.0.0.1", PORT), Sink).serve_forever()
@@ -0,0 +1,255 @@
+// response compression: brotli or gzip, as the client's Accept-Encoding asks
+//
+// Pages and feeds are encoded on the way out, static files once at startup,
+// and page cache entries are kept gzipped, so a hit is sent as stored.
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::collections::HashMap;
+use log::{info, warn};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use sapper::{
+    status,
+    Request,
+    Response
+};
+
+use crate::StaticAssets;
+use crate::conditional::header_value;
+
+// below this a body isn't worth the cpu, and can even grow
+const MIN_SIZE: usize = 1024;
+// fast enough to run on every page
+const BROTLI_QUALITY: u32 = 5;
+// static files are encoded once, so as small as it gets
+const STATIC_BROTLI_QUALITY: u32 = 11;
+const BROTLI_WINDOW: u32 = 22;
+
+const STATIC_EXTENSIONS: [&str; 8] = ["css", "js", "svg", "html", "json", "xml", "txt", "ico"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+// a static file, encoded ahead
+pub struct Precompressed {
+    pub brotli: Option<Vec<u8>>,
+    pub gzip: Option<Vec<u8>>,
+}
+
+impl Precompressed {
+    fn get(&self, encoding: Encoding) -> Option<&Vec<u8>> {
+        match encoding {
+            Encoding::Brotli => self.brotli.as_ref(),
+            Encoding::Gzip => self.gzip.as_ref(),
+        }
+    }
+}
+
+pub fn gzip(body: &[u8]) -> Option<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body).ok()?;
+    encoder.finish().ok()
+}
+
+pub fn gunzip(body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    GzDecoder::new(body).read_to_end(&mut decoded).ok()?;
+    Some(decoded)
+}
+
+fn brotli(body: &[u8], quality: u32) -> Option<Vec<u8>> {
+    let mut writer = brotli::CompressorWriter::new(Vec::new(), 4096, quality, BROTLI_WINDOW);
+    writer.write_all(body).ok()?;
+    Some(writer.into_inner())
+}
+
+fn encode(encoding: Encoding, body: &[u8], brotli_quality: u32) -> Option<Vec<u8>> {
+    match encoding {
+        Encoding::Brotli => brotli(body, brotli_quality),
+        Encoding::Gzip => gzip(body),
+    }
+}
+
+// the q of a coding in Accept-Encoding, "*" standing for the unnamed ones
+fn accepted_q(accept_encoding: &str, name: &str) -> f32 {
+    let mut wildcard = 0.0;
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = parts
+            .filter_map(|param| param.trim().trim_start_matches("q=").parse::<f32>().ok())
+            .next()
+            .unwrap_or(1.0);
+
+        if coding == name {
+            return q;
+        }
+        if coding == "*" {
+            wildcard = q;
+        }
+    }
+
+    wildcard
+}
+
+// brotli when it's liked at least as much as gzip
+pub fn negotiate(req: &Request) -> Option<Encoding> {
+    let accept_encoding = header_value(req, "Accept-Encoding")?;
+    let brotli = accepted_q(&accept_encoding, Encoding::Brotli.name());
+    let gzip = accepted_q(&accept_encoding, Encoding::Gzip.name());
+
+    if brotli > 0.0 && brotli >= gzip {
+        Some(Encoding::Brotli)
+    }
+    else if gzip > 0.0 {
+        Some(Encoding::Gzip)
+    }
+    else {
+        None
+    }
+}
+
+fn is_compressible(res: &Response) -> bool {
+    let content_type = res.headers().get_raw("Content-Type")
+        .and_then(|v| v.first())
+        .map(|v| String::from_utf8_lossy(v).to_ascii_lowercase())
+        .unwrap_or_default();
+
+    content_type.starts_with("text/")
+        || content_type.contains("json")
+        || content_type.contains("xml")
+        || content_type.contains("javascript")
+}
+
+fn collect_static(root: &Path, dir: &Path, assets: &mut HashMap<String, Precompressed>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("precompress {} error: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        if path.is_dir() {
+            collect_static(root, &path, assets);
+            continue;
+        }
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        if !STATIC_EXTENSIONS.contains(&extension) {
+            continue;
+        }
+        let body = match fs::read(&path) {
+            Ok(body) => body,
+            Err(_) => continue,
+        };
+        if body.len() < MIN_SIZE {
+            continue;
+        }
+
+        // only kept when smaller than the file
+        let precompressed = Precompressed {
+            brotli: brotli(&body, STATIC_BROTLI_QUALITY).filter(|encoded| encoded.len() < body.len()),
+            gzip: gzip(&body).filter(|encoded| encoded.len() < body.len()),
+        };
+        if let Ok(relative) = path.strip_prefix(root) {
+            let url_path = format!("/{}", relative.to_string_lossy().replace('\\', "/"));
+            assets.insert(url_path, precompressed);
+        }
+    }
+}
+
+// the files `static_file_service` serves, keyed by their url path; a
+// changed file goes out uncompressed until the next restart
+pub fn precompress_static(dir: &str) -> HashMap<String, Precompressed> {
+    let mut assets = HashMap::new();
+    collect_static(Path::new(dir), Path::new(dir), &mut assets);
+    info!("precompressed {} static files", assets.len());
+
+    assets
+}
+
+fn set_encoded(res: &mut Response, encoding: Encoding, body: Vec<u8>) {
+    res.headers_mut().set_raw("Content-Encoding", vec![encoding.name().as_bytes().to_vec()]);
+    res.write_raw_body(body);
+}
+
+// a body from the page cache, stored gzipped
+pub fn write_gzipped(res: &mut Response, body: Vec<u8>) {
+    set_encoded(res, Encoding::Gzip, body);
+}
+
+fn set_vary(res: &mut Response) {
+    res.headers_mut().set_raw("Vary", vec![b"Accept-Encoding".to_vec()]);
+}
+
+// for the armor, before the validators are computed so every encoding
+// gets its own ETag
+pub fn finish(req: &Request, res: &mut Response) {
+    if res.status() != status::Ok || res.body().is_none() {
+        return;
+    }
+    let encoding = negotiate(req);
+
+    // from the page cache, sent as stored to clients taking gzip
+    if res.headers().get_raw("Content-Encoding").is_some() {
+        set_vary(res);
+        if encoding == Some(Encoding::Gzip) {
+            return;
+        }
+        let decoded = res.body().as_ref().and_then(|body| gunzip(body));
+        match decoded {
+            Some(decoded) => {
+                res.headers_mut().remove_raw("Content-Encoding");
+                res.write_raw_body(decoded);
+            },
+            None => {
+                warn!("page cache body is not gzip");
+                return;
+            }
+        }
+    }
+
+    let (path, _) = req.uri();
+    if let Some(precompressed) = req.ext().get::<StaticAssets>().and_then(|assets| assets.get(&path)) {
+        set_vary(res);
+        if let Some(encoding) = encoding {
+            if let Some(encoded) = precompressed.get(encoding) {
+                set_encoded(res, encoding, encoded.clone());
+            }
+        }
+        return;
+    }
+
+    if !is_compressible(res) {
+        return;
+    }
+    set_vary(res);
+    let encoding = match encoding {
+        Some(encoding) => encoding,
+        None => return,
+    };
+    let encoded = match res.body() {
+        Some(body) if body.len() >= MIN_SIZE => {
+            encode(encoding, body, BROTLI_QUALITY).filter(|encoded| encoded.len() < body.len())
+        },
+        _ => None,
+    };
+    if let Some(encoded) = encoded {
+        set_encoded(res, encoding, encoded);
+    }
+}
@@ -0,0 +1,121 @@
+// HTTP conditional requests: validators on every page, and 304s when the
+// client already has what it would get
+use chrono::{DateTime, Utc};
+use sha2::{Sha256, Digest};
+use sapper::{
+    status,
+    header::ContentType,
+    Request,
+    Response,
+    Result as SapperResult
+};
+use sapper_std::*;
+
+use crate::AppUser;
+use crate::compress;
+use crate::feed::FeedFormat;
+
+// a client may keep a feed this long without asking again
+const FEED_MAX_AGE: u32 = 300;
+const STATIC_MAX_AGE: u32 = 86400;
+
+fn http_date(time: &DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn is_static(path: &str) -> bool {
+    path.starts_with("/css/")
+        || path.starts_with("/js/")
+        || path.starts_with("/img/")
+        || path == "/favicon.ico"
+}
+
+pub fn set_last_modified(res: &mut Response, time: Option<DateTime<Utc>>) {
+    if let Some(time) = time {
+        res.headers_mut().set_raw("Last-Modified", vec![http_date(&time).into_bytes()]);
+    }
+}
+
+// a page from the page cache, gzipped, with its Last-Modified
+pub fn res_cached_html(body: Vec<u8>, last_modified: Option<DateTime<Utc>>) -> SapperResult<Response> {
+    let mut response = Response::new();
+    response.headers_mut().set(ContentType::html());
+    set_last_modified(&mut response, last_modified);
+    compress::write_gzipped(&mut response, body);
+
+    Ok(response)
+}
+
+fn etag_of(body: &[u8]) -> String {
+    let hex: String = Sha256::digest(body).iter().take(16).map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+pub fn header_value(req: &Request, name: &str) -> Option<String> {
+    req.headers().get_raw(name)
+        .and_then(|v| v.first())
+        .map(|v| String::from_utf8_lossy(v).into_owned())
+}
+
+// If-None-Match wins over If-Modified-Since when both are sent
+fn is_not_modified(req: &Request, etag: &str, last_modified: Option<String>) -> bool {
+    if let Some(if_none_match) = header_value(req, "If-None-Match") {
+        return if_none_match.split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == etag || tag == "*");
+    }
+
+    let since = header_value(req, "If-Modified-Since")
+        .and_then(|since| DateTime::parse_from_rfc2822(&since).ok());
+    let modified = last_modified
+        .and_then(|modified| DateTime::parse_from_rfc2822(&modified).ok());
+    match (since, modified) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+fn cache_control(req: &Request, res: &Response) -> String {
+    let (path, _) = req.uri();
+    // pages of a logged in user, or setting a cookie, are theirs only
+    if get_ext!(req, AppUser).is_some() || res.headers().get_raw("Set-Cookie").is_some() {
+        "private, no-cache".to_owned()
+    }
+    else if is_static(&path) {
+        format!("public, max-age={}", STATIC_MAX_AGE)
+    }
+    else if FeedFormat::from_path(&path).is_some() {
+        format!("public, max-age={}", FEED_MAX_AGE)
+    }
+    // shared, but asked again each time, a 304 is cheap
+    else {
+        "public, no-cache".to_owned()
+    }
+}
+
+// for the armor, after every module: ETag and Cache-Control on successful
+// reads, and a 304 instead of the body if the client has it already
+pub fn finish(req: &Request, res: &mut Response) {
+    let method = req.method().as_ref().to_owned();
+    if (method != "GET" && method != "HEAD") || res.status() != status::Ok {
+        return;
+    }
+
+    let etag = match res.body() {
+        Some(body) => etag_of(body),
+        None => return,
+    };
+    if res.headers().get_raw("Cache-Control").is_none() {
+        let cache_control = cache_control(req, res);
+        res.headers_mut().set_raw("Cache-Control", vec![cache_control.into_bytes()]);
+    }
+    res.headers_mut().set_raw("ETag", vec![etag.clone().into_bytes()]);
+
+    let last_modified = res.headers().get_raw("Last-Modified")
+        .and_then(|v| v.first())
+        .map(|v| String::from_utf8_lossy(v).into_owned());
+    if is_not_modified(req, &etag, last_modified) {
+        res.set_status(status::NotModified);
+        res.write_body(String::new());
+    }
+}
@@ -0,0 +1,368 @@
+// syndication feeds, built from our own queries so deleted content never leaks
+use chrono::{DateTime, Utc, SecondsFormat};
+use serde_json::json;
+
+use crate::envconfig;
+use crate::model::feed_article::{
+    FeedArticle,
+    FeedComment
+};
+
+// how much of the markdown goes into a feed entry without `full=1`
+const SUMMARY_CHARS: usize = 300;
+
+// the formats every feed is offered in, by the last segment of its path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    pub fn from_path(path: &str) -> Option<FeedFormat> {
+        match path.rsplit('/').next() {
+            Some("rss") => Some(FeedFormat::Rss),
+            Some("atom") => Some(FeedFormat::Atom),
+            Some("feed.json") => Some(FeedFormat::Json),
+            _ => None,
+        }
+    }
+
+    // the path segment, also used in the cache keys
+    pub fn name(&self) -> &'static str {
+        match *self {
+            FeedFormat::Rss => "rss",
+            FeedFormat::Atom => "atom",
+            FeedFormat::Json => "feed.json",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match *self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Json => "application/feed+json; charset=utf-8",
+        }
+    }
+}
+
+// one entry of a feed, whatever it is made of
+pub struct FeedItem {
+    pub title: String,
+    pub url: String,
+    pub author_name: String,
+    // plain text
+    pub summary: String,
+    // rendered html, only in full feeds
+    pub content: Option<String>,
+    pub published: DateTime<Utc>,
+    // the last edit, or the publication
+    pub updated: DateTime<Utc>,
+}
+
+// updated_time defaults to a year back in the schema, so it can be older
+// than the creation of a never edited article
+fn last_modified(created_time: DateTime<Utc>, updated_time: Option<DateTime<Utc>>) -> DateTime<Utc> {
+    updated_time.map_or(created_time, |updated_time| updated_time.max(created_time))
+}
+
+fn summarize(raw: &str) -> String {
+    let mut summary: String = raw.chars().take(SUMMARY_CHARS).collect();
+    if raw.chars().count() > SUMMARY_CHARS {
+        summary.push_str("...");
+    }
+
+    summary
+}
+
+impl FeedItem {
+    pub fn from_article(article: &FeedArticle, full: bool) -> FeedItem {
+        let host = envconfig::get_str_item("HOST_DOMAIN");
+        FeedItem {
+            title: article.title.clone(),
+            url: format!("{}/article?id={}", host, article.id),
+            author_name: article.author_name.clone(),
+            summary: summarize(&article.raw_content),
+            content: if full { Some(article.content.clone()) } else { None },
+            published: article.created_time,
+            updated: last_modified(article.created_time, article.updated_time),
+        }
+    }
+
+    pub fn from_comment(comment: &FeedComment, full: bool) -> FeedItem {
+        let host = envconfig::get_str_item("HOST_DOMAIN");
+        FeedItem {
+            title: format!("{} on {}", comment.author_name, comment.article_title),
+            url: format!("{}/article?id={}#comment-{}", host, comment.article_id, comment.id),
+            author_name: comment.author_name.clone(),
+            summary: summarize(&comment.raw_content),
+            content: if full { Some(comment.content.clone()) } else { None },
+            published: comment.created_time,
+            updated: comment.created_time,
+        }
+    }
+}
+
+pub fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn rfc3339(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+// `link` is the page the feed follows, `feed_link` the feed itself
+pub fn make_feed(format: FeedFormat, title: &str, link: &str, feed_link: &str, items: &[FeedItem]) -> String {
+    match format {
+        FeedFormat::Rss => make_rss_feed(title, link, items),
+        FeedFormat::Atom => make_atom_feed(title, link, feed_link, items),
+        FeedFormat::Json => make_json_feed(title, link, feed_link, items),
+    }
+}
+
+pub fn make_rss_feed(title: &str, link: &str, items: &[FeedItem]) -> String {
+    let host = envconfig::get_str_item("HOST_DOMAIN");
+
+    let mut entries = String::new();
+    for item in items {
+        entries.push_str(&format!(
+            "<item><title>{}</title><link>{}</link><guid>{}</guid><author>{}</author><pubDate>{}</pubDate><description>{}</description></item>",
+            xml_escape(&item.title),
+            xml_escape(&item.url),
+            xml_escape(&item.url),
+            xml_escape(&item.author_name),
+            item.published.to_rfc2822(),
+            xml_escape(item.content.as_ref().unwrap_or(&item.summary)),
+        ));
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?><rss version=\"2.0\"><channel><title>{}</title><link>{}{}</link><description>{}</description>{}</channel></rss>",
+        xml_escape(title),
+        xml_escape(&host),
+        xml_escape(link),
+        xml_escape(title),
+        entries
+    )
+}
+
+pub fn make_atom_feed(title: &str, link: &str, feed_link: &str, items: &[FeedItem]) -> String {
+    atom_feed(&envconfig::get_str_item("HOST_DOMAIN"), title, link, feed_link, items)
+}
+
+fn atom_feed(host: &str, title: &str, link: &str, feed_link: &str, items: &[FeedItem]) -> String {
+    let feed_url = format!("{}{}", host, feed_link);
+    // the latest change of any entry, an empty feed is as old as now
+    let updated = items.iter().map(|item| item.updated.max(item.published)).max().unwrap_or_else(Utc::now);
+
+    let mut entries = String::new();
+    for item in items {
+        let content = match item.content {
+            Some(ref content) => format!("<content type=\"html\">{}</content>", xml_escape(content)),
+            None => String::new(),
+        };
+        entries.push_str(&format!(
+            "<entry><title>{}</title><link rel=\"alternate\" href=\"{}\"/><id>{}</id><published>{}</published><updated>{}</updated><author><name>{}</name></author><summary>{}</summary>{}</entry>",
+            xml_escape(&item.title),
+            xml_escape(&item.url),
+            xml_escape(&item.url),
+            rfc3339(&item.published),
+            rfc3339(&item.updated),
+            xml_escape(&item.author_name),
+            xml_escape(&item.summary),
+            content,
+        ));
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?><feed xmlns=\"http://www.w3.org/2005/Atom\"><title>{}</title><link rel=\"alternate\" href=\"{}{}\"/><link rel=\"self\" href=\"{}\"/><id>{}</id><updated>{}</updated>{}</feed>",
+        xml_escape(title),
+        xml_escape(host),
+        xml_escape(link),
+        xml_escape(&feed_url),
+        xml_escape(&feed_url),
+        rfc3339(&updated),
+        entries
+    )
+}
+
+// JSON Feed 1.1, every item needs either content_html or content_text
+pub fn make_json_feed(title: &str, link: &str, feed_link: &str, items: &[FeedItem]) -> String {
+    json_feed(&envconfig::get_str_item("HOST_DOMAIN"), title, link, feed_link, items)
+}
+
+fn json_feed(host: &str, title: &str, link: &str, feed_link: &str, items: &[FeedItem]) -> String {
+
+    let items: Vec<serde_json::Value> = items.iter().map(|item| {
+        let mut entry = json!({
+            "id": item.url,
+            "url": item.url,
+            "title": item.title,
+            "summary": item.summary,
+            "date_published": rfc3339(&item.published),
+            "date_modified": rfc3339(&item.updated),
+            "authors": [{ "name": item.author_name }],
+        });
+        match item.content {
+            Some(ref content) => entry["content_html"] = json!(content),
+            None => entry["content_text"] = json!(item.summary),
+        }
+
+        entry
+    }).collect();
+
+    json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": title,
+        "home_page_url": format!("{}{}", host, link),
+        "feed_url": format!("{}{}", host, feed_link),
+        "items": items,
+    }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const HOST: &str = "https://forum.example";
+
+    fn item(title: &str, content: Option<&str>) -> FeedItem {
+        FeedItem {
+            title: title.to_string(),
+            url: format!("{}/article?id=1", HOST),
+            author_name: "alice".to_string(),
+            summary: "a summary".to_string(),
+            content: content.map(|c| c.to_string()),
+            published: Utc.ymd(2020, 3, 1).and_hms(8, 0, 0),
+            updated: Utc.ymd(2020, 3, 2).and_hms(9, 30, 0),
+        }
+    }
+
+    fn child<'a, 'i>(node: roxmltree::Node<'a, 'i>, name: &str) -> Option<roxmltree::Node<'a, 'i>> {
+        node.children().find(|n| n.tag_name().name() == name)
+    }
+
+    fn child_text(node: roxmltree::Node, name: &str) -> String {
+        child(node, name).and_then(|n| n.text()).unwrap_or("").to_string()
+    }
+
+    fn assert_rfc3339(s: &str) {
+        assert!(DateTime::parse_from_rfc3339(s).is_ok(), "not rfc 3339: {:?}", s);
+    }
+
+    #[test]
+    fn last_modified_is_never_before_the_creation() {
+        let created = Utc.ymd(2020, 3, 1).and_hms(8, 0, 0);
+        let year_back = Utc.ymd(2019, 3, 1).and_hms(8, 0, 0);
+        let edited = Utc.ymd(2020, 4, 1).and_hms(8, 0, 0);
+
+        assert_eq!(last_modified(created, None), created);
+        assert_eq!(last_modified(created, Some(year_back)), created);
+        assert_eq!(last_modified(created, Some(edited)), edited);
+    }
+
+    #[test]
+    fn atom_has_the_required_elements() {
+        let xml = atom_feed(HOST, "Forum", "/", "/feed/atom", &[item("first", Some("<p>hi</p>"))]);
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+        let feed = doc.root_element();
+
+        assert_eq!(feed.tag_name().name(), "feed");
+        assert_eq!(feed.tag_name().namespace(), Some("http://www.w3.org/2005/Atom"));
+        assert_eq!(child_text(feed, "id"), "https://forum.example/feed/atom");
+        assert_eq!(child_text(feed, "title"), "Forum");
+        assert_eq!(child_text(feed, "updated"), "2020-03-02T09:30:00Z");
+
+        let entries: Vec<_> = feed.children().filter(|n| n.tag_name().name() == "entry").collect();
+        assert_eq!(entries.len(), 1);
+        let entry = entries[0];
+        assert_eq!(child_text(entry, "id"), "https://forum.example/article?id=1");
+        assert_eq!(child_text(entry, "title"), "first");
+        assert_eq!(child_text(entry, "published"), "2020-03-01T08:00:00Z");
+        assert_eq!(child_text(entry, "updated"), "2020-03-02T09:30:00Z");
+        assert_eq!(child_text(child(entry, "author").unwrap(), "name"), "alice");
+        // the html is escaped once, so the parsed text is the html itself
+        assert_eq!(child_text(entry, "content"), "<p>hi</p>");
+        assert_eq!(child(entry, "content").unwrap().attribute("type"), Some("html"));
+
+        for node in doc.descendants().filter(|n| ["updated", "published"].contains(&n.tag_name().name())) {
+            assert_rfc3339(node.text().unwrap());
+        }
+    }
+
+    #[test]
+    fn atom_escapes_titles() {
+        let title = "Tom & Jerry <b>\"quoted\"</b> it's";
+        let xml = atom_feed(HOST, title, "/?a=1&b=2", "/feed/atom", &[item(title, None)]);
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+        let feed = doc.root_element();
+
+        assert_eq!(child_text(feed, "title"), title);
+        let entry = child(feed, "entry").unwrap();
+        assert_eq!(child_text(entry, "title"), title);
+        assert!(child(entry, "content").is_none());
+        let alternate = feed.children()
+            .find(|n| n.tag_name().name() == "link" && n.attribute("rel") == Some("alternate"))
+            .unwrap();
+        assert_eq!(alternate.attribute("href"), Some("https://forum.example/?a=1&b=2"));
+    }
+
+    #[test]
+    fn atom_empty_feed() {
+        let xml = atom_feed(HOST, "Forum", "/", "/feed/atom", &[]);
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+        let feed = doc.root_element();
+
+        assert_eq!(child_text(feed, "id"), "https://forum.example/feed/atom");
+        assert_eq!(child_text(feed, "title"), "Forum");
+        assert_rfc3339(&child_text(feed, "updated"));
+        assert!(child(feed, "entry").is_none());
+    }
+
+    #[test]
+    fn json_feed_has_the_required_members() {
+        let items = [item("full", Some("<p>hi</p>")), item("short", None)];
+        let feed: serde_json::Value = serde_json::from_str(&json_feed(HOST, "Forum", "/", "/feed/feed.json", &items)).unwrap();
+
+        assert_eq!(feed["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(feed["title"], "Forum");
+        assert_eq!(feed["home_page_url"], "https://forum.example/");
+        assert_eq!(feed["feed_url"], "https://forum.example/feed/feed.json");
+
+        let entries = feed["items"].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        for entry in entries {
+            assert_eq!(entry["id"], "https://forum.example/article?id=1");
+            assert!(entry["content_html"].is_string() || entry["content_text"].is_string());
+            assert_rfc3339(entry["date_published"].as_str().unwrap());
+            assert_rfc3339(entry["date_modified"].as_str().unwrap());
+        }
+        assert_eq!(entries[0]["content_html"], "<p>hi</p>");
+        assert!(entries[0].get("content_text").is_none());
+        assert_eq!(entries[1]["content_text"], "a summary");
+        assert!(entries[1].get("content_html").is_none());
+    }
+
+    #[test]
+    fn json_feed_keeps_titles_as_is() {
+        let title = "Tom & Jerry <b>\"quoted\"</b>";
+        let feed: serde_json::Value = serde_json::from_str(&json_feed(HOST, title, "/", "/feed/feed.json", &[item(title, None)])).unwrap();
+
+        assert_eq!(feed["title"], title);
+        assert_eq!(feed["items"][0]["title"], title);
+    }
+
+    #[test]
+    fn json_empty_feed() {
+        let feed: serde_json::Value = serde_json::from_str(&json_feed(HOST, "Forum", "/", "/feed/feed.json", &[])).unwrap();
+
+        assert_eq!(feed["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(feed["items"], serde_json::json!([]));
+    }
+}
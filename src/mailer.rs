@@ -0,0 +1,285 @@
+// email for notifications and digests, sent from a worker thread so a slow
+// smtp server never holds up a request
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+use crossbeam::channel::{Receiver, RecvTimeoutError};
+use lettre::{SmtpClient, SmtpTransport, FileTransport, Transport, SendableEmail};
+use lettre::smtp::authentication::Credentials;
+use lettre_email::EmailBuilder;
+use log::{info, warn};
+use sapper::Request;
+use sapper_std::*;
+use uuid::Uuid;
+
+use crate::envconfig;
+use crate::MailTx;
+use crate::dataservice::user::Ruser;
+use crate::model::notification::{
+    Notification,
+    NotificationInfo,
+    NOTIFY_REPLY,
+    NOTIFY_COMMENT,
+    NOTIFY_MENTION
+};
+use crate::model::email_pref::{
+    EmailPref,
+    DigestArticle
+};
+
+// how often the worker looks for due digests
+const DIGEST_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const DIGEST_ARTICLES_PER_SECTION: i64 = 5;
+
+pub enum MailJob {
+    // a notification was just made, mailed if its user opted in
+    Notification {
+        id: Uuid,
+        user_id: Uuid,
+        ntype: i16,
+    },
+    // a new address was given, gets the link that confirms it
+    ConfirmEmail {
+        user_id: Uuid,
+    },
+}
+
+enum MailTransport {
+    Smtp(SmtpTransport),
+    // writes every mail as a file, for testing without a mail server
+    File(FileTransport),
+}
+
+impl MailTransport {
+    fn from_env() -> Option<MailTransport> {
+        match envconfig::get_str_item("MAIL_TRANSPORT").as_str() {
+            "smtp" => {
+                let host = envconfig::get_str_item("SMTP_HOST");
+                let client = match SmtpClient::new_simple(&host) {
+                    Ok(client) => client,
+                    Err(e) => {
+                        warn!("smtp host {} is not usable: {:?}", host, e);
+                        return None;
+                    }
+                };
+                let user = envconfig::get_str_item("SMTP_USER");
+                let client = if user != "" {
+                    client.credentials(Credentials::new(user, envconfig::get_str_item("SMTP_PASSWORD")))
+                }
+                else {
+                    client
+                };
+
+                Some(MailTransport::Smtp(client.transport()))
+            },
+            "file" => {
+                let dir = envconfig::get_str_item("MAIL_DROP_DIR");
+                let _ = fs::create_dir_all(&dir);
+
+                Some(MailTransport::File(FileTransport::new(dir)))
+            },
+            _ => None,
+        }
+    }
+
+    fn send(&mut self, email: SendableEmail) -> Result<(), String> {
+        match self {
+            MailTransport::Smtp(transport) => transport.send(email).map(|_| ()).map_err(|e| format!("{:?}", e)),
+            MailTransport::File(transport) => transport.send(email).map_err(|e| format!("{:?}", e)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DigestSection {
+    section_id: Uuid,
+    section_title: String,
+    articles: Vec<DigestArticle>,
+}
+
+/// Hand a fresh notification to the mailer.
+pub fn queue_notification(req: &Request, notification: &Notification) {
+    if let Some(mail_tx) = get_ext!(req, MailTx) {
+        let _ = mail_tx.send(MailJob::Notification {
+            id: notification.id,
+            user_id: notification.user_id,
+            ntype: notification.ntype,
+        });
+    }
+}
+
+/// Mail the link confirming the address the user just gave.
+pub fn queue_email_confirmation(req: &Request, user_id: Uuid) {
+    if let Some(mail_tx) = get_ext!(req, MailTx) {
+        let _ = mail_tx.send(MailJob::ConfirmEmail { user_id });
+    }
+}
+
+// the account name is typed in at signup and never checked, so only the
+// address its owner confirmed from the mailbox is used
+fn email_address_of(pref: &EmailPref) -> Option<String> {
+    let to = pref.confirmed_email()?;
+    let user = Ruser::get_user_by_id(pref.user_id).ok()?;
+    if user.status == 0 {
+        Some(to.to_string())
+    }
+    else {
+        None
+    }
+}
+
+fn unsubscribe_url(pref: &EmailPref) -> String {
+    format!("{}/unsubscribe?token={}", envconfig::get_str_item("HOST_DOMAIN"), pref.unsubscribe_token)
+}
+
+// renders email/{template}.html and .txt with the site fields added to `context`
+fn build_email(to: String, subject: String, template: &str, pref: &EmailPref, mut context: WebContext) -> Result<SendableEmail, String> {
+    let unsubscribe_url = unsubscribe_url(pref);
+    context.insert("site_title", &envconfig::get_str_item("SITE_TITLE"));
+    context.insert("host", &envconfig::get_str_item("HOST_DOMAIN"));
+    context.insert("unsubscribe_url", &unsubscribe_url);
+
+    let html = render(&format!("email/{}.html", template), context.clone());
+    let text = render(&format!("email/{}.txt", template), context);
+
+    EmailBuilder::new()
+        .to(to)
+        .from(envconfig::get_str_item("MAIL_FROM"))
+        .subject(subject)
+        .alternative(html, text)
+        // lets mail clients show their own unsubscribe button
+        .header(("List-Unsubscribe", format!("<{}>", unsubscribe_url)))
+        .header(("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"))
+        .build()
+        .map(|email| email.into())
+        .map_err(|e| format!("{:?}", e))
+}
+
+fn notification_subject(info: &NotificationInfo) -> String {
+    match info.ntype {
+        NOTIFY_REPLY => format!("{} replied to your comment in \"{}\"", info.actor_nickname, info.article_title),
+        NOTIFY_COMMENT => format!("{} commented on \"{}\"", info.actor_nickname, info.article_title),
+        NOTIFY_MENTION => format!("{} mentioned you in \"{}\"", info.actor_nickname, info.article_title),
+        _ => format!("News about \"{}\"", info.article_title),
+    }
+}
+
+fn send_notification(transport: &mut MailTransport, id: Uuid, user_id: Uuid, ntype: i16) -> Result<(), String> {
+    let pref = match EmailPref::get_by_user(user_id) {
+        Ok(pref) => pref,
+        // never opted in
+        Err(_) => return Ok(()),
+    };
+    if !pref.wants(ntype) {
+        return Ok(());
+    }
+    let to = match email_address_of(&pref) {
+        Some(to) => to,
+        None => return Ok(()),
+    };
+    let info = Notification::get_info_by_id(id)?;
+
+    let mut context = WebContext::new();
+    context.insert("n", &info);
+    let email = build_email(to, notification_subject(&info), "notification", &pref, context)?;
+
+    transport.send(email)
+}
+
+// the one mail that goes to an unconfirmed address
+fn send_confirmation(transport: &mut MailTransport, user_id: Uuid) -> Result<(), String> {
+    let pref = EmailPref::get_by_user(user_id)?;
+    let (to, token) = match pref.confirm_token {
+        Some(ref token) if !pref.email_confirmed && pref.email != "" => (pref.email.clone(), token.clone()),
+        _ => return Ok(()),
+    };
+
+    let mut context = WebContext::new();
+    context.insert("confirm_url", &format!("{}/confirm_email?token={}", envconfig::get_str_item("HOST_DOMAIN"), token));
+    let subject = format!("Confirm your email for {}", envconfig::get_str_item("SITE_TITLE"));
+    let email = build_email(to, subject, "confirm", &pref, context)?;
+
+    transport.send(email)
+}
+
+fn send_digest(transport: &mut MailTransport, pref: &EmailPref) -> Result<(), String> {
+    let to = match email_address_of(pref) {
+        Some(to) => to,
+        None => return Ok(()),
+    };
+    let articles = DigestArticle::top_new_in_followed(pref.user_id, pref.last_digest_time, DIGEST_ARTICLES_PER_SECTION);
+    if articles.is_empty() {
+        return Ok(());
+    }
+
+    // the articles come ordered by section
+    let mut sections: Vec<DigestSection> = Vec::new();
+    for article in articles {
+        let same_section = sections.last().map(|s| s.section_id == article.section_id).unwrap_or(false);
+        if !same_section {
+            sections.push(DigestSection {
+                section_id: article.section_id,
+                section_title: article.section_title.clone(),
+                articles: Vec::new(),
+            });
+        }
+        sections.last_mut().unwrap().articles.push(article);
+    }
+
+    let mut context = WebContext::new();
+    context.insert("sections", &sections);
+    let subject = format!("New on {}", envconfig::get_str_item("SITE_TITLE"));
+    let email = build_email(to, subject, "digest", pref, context)?;
+
+    transport.send(email)
+}
+
+fn send_due_digests(transport: &mut MailTransport) {
+    for pref in EmailPref::due_digests() {
+        if let Err(e) = send_digest(transport, &pref) {
+            warn!("digest to user {} failed: {}", pref.user_id, e);
+        }
+        // the next digest covers the time from now on, sent or not
+        EmailPref::mark_digest_sent(pref.user_id);
+    }
+}
+
+pub fn run_mailer(mail_rx: Receiver<MailJob>) {
+
+    let mut transport = MailTransport::from_env();
+    if transport.is_none() {
+        info!("MAIL_TRANSPORT is not set, no email will be sent");
+    }
+
+    thread::spawn(move || {
+	let mut last_digest_check = Instant::now();
+	loop {
+	    match mail_rx.recv_timeout(DIGEST_CHECK_INTERVAL) {
+		Ok(MailJob::Notification { id, user_id, ntype }) => {
+		    if let Some(ref mut transport) = transport {
+			if let Err(e) = send_notification(transport, id, user_id, ntype) {
+			    warn!("notification mail {} failed: {}", id, e);
+			}
+		    }
+		},
+		Ok(MailJob::ConfirmEmail { user_id }) => {
+		    if let Some(ref mut transport) = transport {
+			if let Err(e) = send_confirmation(transport, user_id) {
+			    warn!("confirmation mail to user {} failed: {}", user_id, e);
+			}
+		    }
+		},
+		Err(RecvTimeoutError::Timeout) => {},
+		Err(RecvTimeoutError::Disconnected) => break,
+	    }
+
+	    if last_digest_check.elapsed() >= DIGEST_CHECK_INTERVAL {
+		if let Some(ref mut transport) = transport {
+		    send_due_digests(transport);
+		}
+		last_digest_check = Instant::now();
+	    }
+	}
+    });
+
+}
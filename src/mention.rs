@@ -0,0 +1,214 @@
+// @nickname mentions in markdown, resolved to users at save time
+use std::collections::HashSet;
+
+use crate::util::markdown_render;
+use crate::model::mention::MentionedUser;
+
+// more than this in one text is not a conversation any more
+pub const MAX_MENTIONS: usize = 20;
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+// mentions in a piece of text which is known not to be code
+fn replace_in_text<F>(text: &str, f: &mut F, out: &mut String)
+    where F: FnMut(&str) -> Option<String>
+{
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        // not an email address, nor a@b in the middle of a word
+        let starts = c == '@' && (i == 0 || !(is_name_char(chars[i - 1]) || chars[i - 1] == '@'));
+        if starts {
+            let mut j = i + 1;
+            while j < chars.len() && is_name_char(chars[j]) {
+                j += 1;
+            }
+            // a sentence may end right after the name
+            while j > i + 1 && (chars[j - 1] == '.' || chars[j - 1] == '-') {
+                j -= 1;
+            }
+            if j > i + 1 {
+                let name: String = chars[i + 1..j].iter().collect();
+                if let Some(replacement) = f(&name) {
+                    out.push_str(&replacement);
+                    i = j;
+                    continue;
+                }
+            }
+        }
+        out.push(c);
+        i += 1;
+    }
+}
+
+// split a line on inline code spans, a run of n backticks is closed by the next run of n
+fn replace_in_line<F>(line: &str, f: &mut F, out: &mut String)
+    where F: FnMut(&str) -> Option<String>
+{
+    let mut rest = line;
+    while let Some(start) = rest.find('`') {
+        replace_in_text(&rest[..start], f, out);
+        let ticks = rest[start..].chars().take_while(|c| *c == '`').count();
+        let fence = "`".repeat(ticks);
+        let after_open = &rest[start + ticks..];
+        match after_open.find(&fence) {
+            Some(end) => {
+                out.push_str(&rest[start..start + ticks + end + ticks]);
+                rest = &after_open[end + ticks..];
+            },
+            None => {
+                // unclosed, the backticks are literal
+                out.push_str(&rest[start..start + ticks]);
+                rest = after_open;
+            }
+        }
+    }
+    replace_in_text(rest, f, out);
+}
+
+/// Rewrite every `@name` outside code blocks and inline code with what `f`
+/// returns for it, names `f` returns None for are left as they are.
+pub fn replace_mentions<F>(raw: &str, mut f: F) -> String
+    where F: FnMut(&str) -> Option<String>
+{
+    let mut out = String::with_capacity(raw.len());
+    let mut fence: Option<String> = None;
+    let mut prev_blank = true;
+    let mut in_indented = false;
+
+    for (n, line) in raw.split('\n').enumerate() {
+        if n > 0 {
+            out.push('\n');
+        }
+        let trimmed = line.trim_start();
+
+        if let Some(marker) = fence.clone() {
+            out.push_str(line);
+            if trimmed.starts_with(marker.as_str()) && trimmed.trim_end().chars().all(|c| c == marker.chars().next().unwrap()) {
+                fence = None;
+            }
+            prev_blank = false;
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            let marker: String = trimmed.chars().take_while(|c| *c == trimmed.chars().next().unwrap()).collect();
+            fence = Some(marker);
+            out.push_str(line);
+            prev_blank = false;
+            continue;
+        }
+
+        // indented code starts after a blank line
+        let indented = line.starts_with("    ") || line.starts_with('\t');
+        in_indented = indented && (prev_blank || in_indented);
+        if in_indented {
+            out.push_str(line);
+        }
+        else {
+            replace_in_line(line, &mut f, &mut out);
+        }
+        prev_blank = trimmed.is_empty();
+    }
+
+    out
+}
+
+fn escape_markdown(s: &str) -> String {
+    s.replace('_', "\\_")
+}
+
+/// markdown_render, with the mentions of existing users turned into links
+/// to their blog. Returns the html and the users mentioned.
+pub fn render_with_mentions(raw: &str) -> (String, Vec<MentionedUser>) {
+    let mut mentioned: Vec<MentionedUser> = Vec::new();
+    let mut unknown: HashSet<String> = HashSet::new();
+
+    let linked = replace_mentions(raw, |name| {
+        let user = match mentioned.iter().find(|u| u.nickname == name) {
+            Some(user) => user.clone(),
+            None => {
+                if unknown.contains(name) || mentioned.len() >= MAX_MENTIONS {
+                    return None;
+                }
+                match MentionedUser::get_by_nickname(name) {
+                    Ok(user) => {
+                        mentioned.push(user.clone());
+                        user
+                    },
+                    Err(_) => {
+                        unknown.insert(name.to_string());
+                        return None;
+                    }
+                }
+            }
+        };
+
+        Some(format!("[@{}](/blog_with_author?author_id={})", escape_markdown(&user.nickname), user.id))
+    });
+
+    (markdown_render(&linked), mentioned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mark(raw: &str) -> String {
+        replace_mentions(raw, |name| Some(format!("<{}>", name)))
+    }
+
+    #[test]
+    fn mention_at_start_and_end() {
+        assert_eq!(mark("@alice"), "<alice>");
+        assert_eq!(mark("@alice hi"), "<alice> hi");
+        assert_eq!(mark("hi @alice"), "hi <alice>");
+        assert_eq!(mark("thanks @alice."), "thanks <alice>.");
+        assert_eq!(mark("@alice\n@bob"), "<alice>\n<bob>");
+    }
+
+    #[test]
+    fn email_is_not_a_mention() {
+        assert_eq!(mark("write to a@b.com"), "write to a@b.com");
+        assert_eq!(mark("a@@b"), "a@@b");
+        assert_eq!(mark("@"), "@");
+    }
+
+    #[test]
+    fn unknown_names_are_kept() {
+        let out = replace_mentions("@alice and @bob", |name| if name == "bob" { Some("B".to_string()) } else { None });
+        assert_eq!(out, "@alice and B");
+    }
+
+    #[test]
+    fn fenced_block_is_skipped() {
+        let raw = "@alice\n```\n@bob\n```\n@carol";
+        assert_eq!(mark(raw), "<alice>\n```\n@bob\n```\n<carol>");
+
+        let raw = "~~~~\n@bob\n~~~\n~~~~\n@carol";
+        assert_eq!(mark(raw), "~~~~\n@bob\n~~~\n~~~~\n<carol>");
+    }
+
+    #[test]
+    fn unterminated_fence_runs_to_the_end() {
+        let raw = "@alice\n```rust\n@bob\n\n@carol";
+        assert_eq!(mark(raw), "<alice>\n```rust\n@bob\n\n@carol");
+    }
+
+    #[test]
+    fn backtick_spans_are_skipped() {
+        assert_eq!(mark("`@bob` @alice"), "`@bob` <alice>");
+        assert_eq!(mark("``a ` @bob`` @alice"), "``a ` @bob`` <alice>");
+        // an unclosed run is literal, the text after it is not code
+        assert_eq!(mark("`` @alice"), "`` <alice>");
+    }
+
+    #[test]
+    fn indented_code_is_skipped() {
+        assert_eq!(mark("text\n\n    @bob\n@alice"), "text\n\n    @bob\n<alice>");
+        // no blank line before, so it's a continuation of the paragraph
+        assert_eq!(mark("text\n    @alice"), "text\n    <alice>");
+    }
+}
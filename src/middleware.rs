@@ -5,15 +5,29 @@ use sapper::{
 use sapper_std::*;
 use crate::AppUser;
 use crate::envconfig;
+use crate::dataservice::user::Ruser;
+use crate::model::user_totp::UserTotp;
+
+// users of a 2fa required role can only go to the enrollment pages,
+// whichever gate the route is behind
+fn need_totp_enrolled(path: &str, user: &Ruser) -> Result<(), SapperError> {
+    if totp_required(user)
+	&& !path.starts_with("/p/user/totp")
+	&& !path.starts_with("/s/user/totp/")
+	&& !UserTotp::is_enabled_for(user.id) {
+	return res_400!("No permissions: need two factor authentication, enable it at /p/user/totp".to_string());
+    }
+
+    Ok(())
+}
 
 pub fn permission_need_login(req: &mut Request) -> Result<(), SapperError> {
     let (path, _) = req.uri();
     if path.starts_with("/s/") || path.starts_with("/p/")
     {
 	match get_ext!(req, AppUser) {
-	    Some(ref _user) => {
-		// pass, nothing need to do here
-		return Ok(());
+	    Some(ref user) => {
+		return need_totp_enrolled(&path, user);
 	    },
 	    None => {
 		return res_400!("No permissions: need login.".to_string());
@@ -32,9 +46,7 @@ pub fn permission_need_be_admin(req: &mut Request) -> Result<(), SapperError> {
 	match get_ext!(req, AppUser) {
 	    Some(user) => {
 		if user.role >= 9 {
-		    // pass, nothing need to do here
-		    return Ok(());
-
+		    return need_totp_enrolled(&path, user);
 		}
 		else {
 		    return res_400!("No permissions: need be admin.".to_string());
@@ -50,6 +62,34 @@ pub fn permission_need_be_admin(req: &mut Request) -> Result<(), SapperError> {
     }
 }
 
+pub fn permission_need_be_moderator(req: &mut Request) -> Result<(), SapperError> {
+    let (path, _) = req.uri();
+    match get_ext!(req, AppUser) {
+	Some(user) => {
+	    if user.role >= 5 {
+		need_totp_enrolled(&path, user)
+	    }
+	    else {
+		res_400!("No permissions: need be moderator.".to_string())
+	    }
+	},
+	None => {
+	    res_400!("No permissions: need login.".to_string())
+	}
+    }
+}
+
+pub fn is_moderator(req: &mut Request) -> bool {
+    match get_ext!(req, AppUser) {
+	Some(user) => {
+	    user.role >= 5
+	},
+	None => {
+	    false
+	}
+    }
+}
+
 pub fn is_admin(req: &mut Request) -> bool {
     match get_ext!(req, AppUser) {
 	Some(user) => {
@@ -66,11 +106,18 @@ pub fn is_admin(req: &mut Request) -> bool {
     }
 }
 
-pub fn check_cache_switch(req: &mut Request) -> bool {
-    if envconfig::get_int_item("CACHE") == 1 && !is_admin(req) {
-	true
-    }
-    else {
-	false
-    }
+// TOTP_REQUIRED_MIN_ROLE = 0 means 2fa is optional for everybody
+pub fn totp_required(user: &Ruser) -> bool {
+    let min_role = envconfig::get_int_item("TOTP_REQUIRED_MIN_ROLE");
+    min_role > 0 && user.role as i64 >= min_role
+}
+
+pub fn cache_on() -> bool {
+    envconfig::get_int_item("CACHE") == 1
+}
+
+// pages rendered for a logged in user carry their name, their buttons and
+// what they may edit, so only anonymous visitors read and fill the cache
+pub fn cache_switch(cache_on: bool, logged_in: bool) -> bool {
+    cache_on && !logged_in
 }
@@ -0,0 +1,21 @@
+// models owned by forustm itself, beside the ones in rusoda::dataservice
+pub mod user_totp;
+pub mod api_token;
+pub mod comment_thread;
+pub mod notification;
+pub mod trash;
+pub mod feed_article;
+pub mod report;
+pub mod draft;
+pub mod article_revision;
+pub mod tag;
+pub mod mention;
+pub mod email_pref;
+pub mod follow;
+pub mod webhook;
+pub mod vote;
+pub mod qa;
+pub mod bookmark;
+pub mod sitemap;
+pub mod activity;
+pub mod listing;
@@ -0,0 +1,47 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames};
+
+use crate::db;
+use crate::model::trash::STATUS_NORMAL;
+
+#[derive(Debug, FromDao, ToColumnNames)]
+struct TimeRow {
+    time: Option<DateTime<Utc>>,
+}
+
+// the last time something visible happened, for Last-Modified
+pub struct Activity;
+
+impl Activity {
+    // written, edited or commented on
+    pub fn of_article(article_id: Uuid) -> Option<DateTime<Utc>> {
+        let em = db::get_db();
+        let sql = "SELECT greatest(a.created_time, a.updated_time, \
+                   (SELECT max(c.created_time) FROM comment c WHERE c.article_id = a.id)) AS time \
+                   FROM article a WHERE a.id = $1";
+
+        em.execute_sql_with_one_return::<TimeRow>(sql, &[&article_id])
+            .ok()
+            .and_then(|row| row.time)
+    }
+
+    pub fn of_section(section_id: Uuid) -> Option<DateTime<Utc>> {
+        let em = db::get_db();
+        let sql = "SELECT max(greatest(created_time, updated_time)) AS time FROM article \
+                   WHERE section_id = $1 AND status = $2";
+
+        em.execute_sql_with_one_return::<TimeRow>(sql, &[&section_id, &STATUS_NORMAL])
+            .ok()
+            .and_then(|row| row.time)
+    }
+
+    pub fn of_site() -> Option<DateTime<Utc>> {
+        let em = db::get_db();
+        let sql = "SELECT max(greatest(created_time, updated_time)) AS time FROM article WHERE status = $1";
+
+        em.execute_sql_with_one_return::<TimeRow>(sql, &[&STATUS_NORMAL])
+            .ok()
+            .and_then(|row| row.time)
+    }
+}
@@ -0,0 +1,94 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames, ToTableName};
+
+use crate::db;
+use crate::util::make_pwd_encode;
+
+pub const SCOPE_READ: &str = "read";
+pub const SCOPE_POST: &str = "post";
+pub const SCOPE_MODERATE: &str = "moderate";
+pub const ALL_SCOPES: [&str; 3] = [SCOPE_READ, SCOPE_POST, SCOPE_MODERATE];
+
+const TOKEN_PREFIX: &str = "fst_";
+const TOKEN_SALT: &str = "forustm_api_token";
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames, ToTableName)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    // only the hash is kept, the raw token is shown once at creation
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    // separated by ','
+    pub scopes: String,
+    pub created_time: DateTime<Utc>,
+    pub last_used_time: Option<DateTime<Utc>>,
+}
+
+pub fn hash_token(raw: &str) -> String {
+    make_pwd_encode(raw, TOKEN_SALT)
+}
+
+impl ApiToken {
+    pub fn get_by_raw_token(raw: &str) -> Result<ApiToken, String> {
+        if !raw.starts_with(TOKEN_PREFIX) {
+            return Err("invalid api token".to_string());
+        }
+
+        let em = db::get_db();
+        let sql = "UPDATE api_token SET last_used_time = now() WHERE token_hash = $1 RETURNING *";
+
+        em.execute_sql_with_one_return::<ApiToken>(sql, &[&hash_token(raw)])
+            .map_err(|_| "invalid api token".to_string())
+    }
+
+    pub fn get_tokens_by_user(user_id: Uuid) -> Vec<ApiToken> {
+        let em = db::get_db();
+        let sql = "SELECT * FROM api_token WHERE user_id = $1 ORDER BY created_time DESC";
+
+        em.execute_sql_with_return::<ApiToken>(sql, &[&user_id])
+            .unwrap_or_default()
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.split(',').any(|s| s == scope)
+    }
+
+    pub fn revoke(id: Uuid, user_id: Uuid) -> Result<ApiToken, String> {
+        let em = db::get_db();
+        let sql = "DELETE FROM api_token WHERE id = $1 AND user_id = $2 RETURNING *";
+
+        em.execute_sql_with_one_return::<ApiToken>(sql, &[&id, &user_id])
+            .map_err(|_| format!("no this api token: {}", id))
+    }
+}
+
+pub struct ApiTokenCreate {
+    pub user_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+impl ApiTokenCreate {
+    /// Return the stored token together with the raw one.
+    pub fn insert(&self) -> Result<(ApiToken, String), String> {
+        let scopes: Vec<&str> = self.scopes.iter()
+            .map(|s| s.as_str())
+            .filter(|s| ALL_SCOPES.contains(s))
+            .collect();
+        if scopes.is_empty() {
+            return Err("need at least one scope.".to_string());
+        }
+
+        let raw = format!("{}{}{}", TOKEN_PREFIX, Uuid::new_v4().simple(), Uuid::new_v4().simple());
+
+        let em = db::get_db();
+        let sql = "INSERT INTO api_token (user_id, name, token_hash, scopes) VALUES ($1, $2, $3, $4) RETURNING *";
+
+        em.execute_sql_with_one_return::<ApiToken>(sql, &[&self.user_id, &self.name, &hash_token(&raw), &scopes.join(",")])
+            .map(|token| (token, raw))
+            .map_err(|_| "api token create error.".to_string())
+    }
+}
@@ -0,0 +1,95 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames, ToTableName};
+
+use crate::db;
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames, ToTableName)]
+pub struct ArticleRevision {
+    pub id: Uuid,
+    pub article_id: Uuid,
+    pub editor_id: Uuid,
+    pub title: String,
+    pub tags: String,
+    pub raw_content: String,
+    pub created_time: DateTime<Utc>,
+}
+
+// a line of the history page
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct RevisionInfo {
+    pub id: Uuid,
+    pub article_id: Uuid,
+    pub editor_id: Uuid,
+    pub editor_nickname: String,
+    pub title: String,
+    pub created_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct IdRow {
+    id: Uuid,
+}
+
+impl ArticleRevision {
+    pub fn get_by_id(id: Uuid) -> Result<ArticleRevision, String> {
+        let em = db::get_db();
+        let sql = "SELECT * FROM article_revision WHERE id = $1";
+
+        em.execute_sql_with_one_return::<ArticleRevision>(sql, &[&id])
+            .map_err(|_| format!("no this revision: {}", id))
+    }
+
+    /// Newest first.
+    pub fn get_revisions_of(article_id: Uuid) -> Vec<RevisionInfo> {
+        let em = db::get_db();
+        let sql = "SELECT r.id, r.article_id, r.editor_id, u.nickname AS editor_nickname, r.title, r.created_time \
+                   FROM article_revision r JOIN ruser u ON u.id = r.editor_id \
+                   WHERE r.article_id = $1 ORDER BY r.created_time DESC";
+
+        em.execute_sql_with_return::<RevisionInfo>(sql, &[&article_id])
+            .unwrap_or_default()
+    }
+
+    /// The latest edit, None when the article was never changed after posting.
+    pub fn last_edit_of(article_id: Uuid) -> Option<RevisionInfo> {
+        let revisions = ArticleRevision::get_revisions_of(article_id);
+        if revisions.len() > 1 {
+            revisions.into_iter().next()
+        }
+        else {
+            None
+        }
+    }
+
+    /// Articles posted before the history existed get their current text
+    /// as first revision, call this before overwriting it.
+    pub fn ensure_baseline(article_id: Uuid) {
+        let em = db::get_db();
+        let sql = "INSERT INTO article_revision (article_id, editor_id, title, tags, raw_content, created_time) \
+                   SELECT id, author_id, title, tags, raw_content, created_time FROM article \
+                   WHERE id = $1 AND NOT EXISTS (SELECT 1 FROM article_revision WHERE article_id = $1) \
+                   RETURNING id";
+
+        let _ = em.execute_sql_with_return::<IdRow>(sql, &[&article_id]);
+    }
+}
+
+pub struct ArticleRevisionCreate {
+    pub article_id: Uuid,
+    pub editor_id: Uuid,
+    pub title: String,
+    pub tags: String,
+    pub raw_content: String,
+}
+
+impl ArticleRevisionCreate {
+    pub fn insert(&self) -> Result<ArticleRevision, String> {
+        let em = db::get_db();
+        let sql = "INSERT INTO article_revision (article_id, editor_id, title, tags, raw_content) \
+                   VALUES ($1, $2, $3, $4, $5) RETURNING *";
+
+        em.execute_sql_with_one_return::<ArticleRevision>(sql, &[&self.article_id, &self.editor_id, &self.title, &self.tags, &self.raw_content])
+            .map_err(|_| format!("save revision of article {} error", self.article_id))
+    }
+}
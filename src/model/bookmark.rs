@@ -0,0 +1,138 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames, ToTableName};
+
+use crate::db;
+use crate::model::trash::STATUS_NORMAL;
+use crate::model::qa::qa_columns;
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames, ToTableName)]
+pub struct Bookmark {
+    pub user_id: Uuid,
+    pub article_id: Uuid,
+    // tell the user about new comments
+    pub notify: bool,
+    pub created_time: DateTime<Utc>,
+}
+
+// for the bookmarks page, shaped like the other article listings
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct BookmarkedArticle {
+    pub id: Uuid,
+    pub title: String,
+    pub section_title: String,
+    pub author_name: String,
+    pub created_time: DateTime<Utc>,
+    pub notify: bool,
+    pub score: i32,
+    pub question: bool,
+    pub solved: bool,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct CountRow {
+    count: i64,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct UserIdRow {
+    user_id: Uuid,
+}
+
+impl Bookmark {
+    pub fn get(user_id: Uuid, article_id: Uuid) -> Option<Bookmark> {
+        let em = db::get_db();
+        let sql = "SELECT * FROM bookmark WHERE user_id = $1 AND article_id = $2";
+
+        em.execute_sql_with_one_return::<Bookmark>(sql, &[&user_id, &article_id]).ok()
+    }
+
+    pub fn count_of(article_id: Uuid) -> i64 {
+        let em = db::get_db();
+        let sql = "SELECT count(*) AS count FROM bookmark WHERE article_id = $1";
+
+        em.execute_sql_with_one_return::<CountRow>(sql, &[&article_id])
+            .map(|r| r.count)
+            .unwrap_or(0)
+    }
+
+    // deleted and hidden articles are left out, until they come back
+    pub fn count_by_user(user_id: Uuid) -> i64 {
+        let em = db::get_db();
+        let sql = "SELECT count(*) AS count FROM bookmark b JOIN article a ON a.id = b.article_id \
+                   WHERE b.user_id = $1 AND a.status = $2";
+
+        em.execute_sql_with_one_return::<CountRow>(sql, &[&user_id, &STATUS_NORMAL])
+            .map(|r| r.count)
+            .unwrap_or(0)
+    }
+
+    /// The latest bookmarked first.
+    pub fn get_paging_by_user(user_id: Uuid, current_page: i64, per_page: i64) -> Vec<BookmarkedArticle> {
+        let em = db::get_db();
+        let offset = (current_page - 1).max(0) * per_page;
+        let sql = format!("SELECT a.id, a.title, s.title AS section_title, u.nickname AS author_name, a.created_time, b.notify, a.score, {} \
+                           FROM bookmark b \
+                           JOIN article a ON a.id = b.article_id \
+                           JOIN section s ON s.id = a.section_id \
+                           JOIN ruser u ON u.id = a.author_id \
+                           WHERE b.user_id = $1 AND a.status = $2 \
+                           ORDER BY b.created_time DESC LIMIT $3 OFFSET $4", qa_columns());
+
+        em.execute_sql_with_return::<BookmarkedArticle>(&sql, &[&user_id, &STATUS_NORMAL, &per_page, &offset])
+            .unwrap_or_default()
+    }
+
+    /// The users who asked to hear about new comments of the article.
+    pub fn notified_users_of(article_id: Uuid) -> Vec<Uuid> {
+        let em = db::get_db();
+        let sql = "SELECT user_id FROM bookmark WHERE article_id = $1 AND notify = true";
+
+        em.execute_sql_with_return::<UserIdRow>(sql, &[&article_id])
+            .unwrap_or_default()
+            .into_iter()
+            .map(|r| r.user_id)
+            .collect()
+    }
+}
+
+pub struct BookmarkToggle {
+    pub user_id: Uuid,
+    pub article_id: Uuid,
+}
+
+impl BookmarkToggle {
+    /// Bookmark the article, or remove the bookmark if it is there.
+    /// Returns whether it is bookmarked now.
+    pub fn toggle(&self) -> Result<bool, String> {
+        let em = db::get_db();
+        let sql = "DELETE FROM bookmark WHERE user_id = $1 AND article_id = $2 RETURNING *";
+        let removed = em.execute_sql_with_return::<Bookmark>(sql, &[&self.user_id, &self.article_id])
+            .map_err(|_| "bookmark error.".to_string())?;
+        if !removed.is_empty() {
+            return Ok(false);
+        }
+
+        let sql = "INSERT INTO bookmark (user_id, article_id) SELECT $1, id FROM article WHERE id = $2 AND status = $3 \
+                   ON CONFLICT DO NOTHING RETURNING *";
+        em.execute_sql_with_one_return::<Bookmark>(sql, &[&self.user_id, &self.article_id, &STATUS_NORMAL])
+            .map(|_| true)
+            .map_err(|_| format!("no this article: {}", self.article_id))
+    }
+}
+
+pub struct BookmarkNotify {
+    pub user_id: Uuid,
+    pub article_id: Uuid,
+    pub notify: bool,
+}
+
+impl BookmarkNotify {
+    pub fn update(&self) -> Result<Bookmark, String> {
+        let em = db::get_db();
+        let sql = "UPDATE bookmark SET notify = $3 WHERE user_id = $1 AND article_id = $2 RETURNING *";
+
+        em.execute_sql_with_one_return::<Bookmark>(sql, &[&self.user_id, &self.article_id, &self.notify])
+            .map_err(|_| "bookmark the article first.".to_string())
+    }
+}
@@ -0,0 +1,108 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::FromDao;
+use rustorm::ToColumnNames;
+
+use crate::db;
+
+// A comment page lists every comment of the article, deleted and hidden ones
+// as placeholders, by (created_time, id). The parent positions count in the
+// same order, so a link to a parent lands on the page showing it.
+
+// one comment of a page
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct ThreadComment {
+    pub id: Uuid,
+    pub article_id: Uuid,
+    pub author_id: Uuid,
+    pub nickname: String,
+    pub content: String,
+    pub raw_content: String,
+    pub created_time: DateTime<Utc>,
+    pub status: i16,
+}
+
+#[derive(Debug, FromDao, ToColumnNames)]
+struct CountRow {
+    count: i64,
+}
+
+pub struct CommentThread;
+
+impl CommentThread {
+    pub fn count_in_article(article_id: Uuid) -> i64 {
+        let em = db::get_db();
+        let sql = "SELECT count(*) AS count FROM comment WHERE article_id = $1";
+
+        em.execute_sql_with_one_return::<CountRow>(sql, &[&article_id])
+            .map(|row| row.count)
+            .unwrap_or(0)
+    }
+
+    pub fn page_in_article(article_id: Uuid, current_page: i64, per_page: i64) -> Vec<ThreadComment> {
+        let em = db::get_db();
+        let offset = (current_page.max(1) - 1) * per_page;
+        let sql = "SELECT c.id, c.article_id, c.author_id, u.nickname, c.content, c.raw_content, c.created_time, c.status \
+                   FROM comment c JOIN ruser u ON u.id = c.author_id \
+                   WHERE c.article_id = $1 ORDER BY c.created_time, c.id LIMIT $2 OFFSET $3";
+
+        em.execute_sql_with_return::<ThreadComment>(sql, &[&article_id, &per_page, &offset])
+            .unwrap_or_default()
+    }
+}
+
+// the parent of one reply, with what we need to link back to it
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct CommentParent {
+    pub id: Uuid,
+    pub parent_id: Uuid,
+    pub parent_author_id: Uuid,
+    pub parent_nickname: String,
+    // 0 based position of the parent in the article's comment list
+    pub parent_position: i64,
+}
+
+impl CommentParent {
+    pub fn get_parents_in_article(article_id: Uuid) -> Vec<CommentParent> {
+        let em = db::get_db();
+        let sql = "SELECT c.id, p.id AS parent_id, p.author_id AS parent_author_id, u.nickname AS parent_nickname, \
+                   (SELECT count(*) FROM comment x WHERE x.article_id = p.article_id \
+                   AND (x.created_time, x.id) < (p.created_time, p.id)) AS parent_position \
+                   FROM comment c \
+                   JOIN comment p ON p.id = c.parent_id \
+                   JOIN ruser u ON u.id = p.author_id \
+                   WHERE c.article_id = $1";
+
+        em.execute_sql_with_return::<CommentParent>(sql, &[&article_id])
+            .unwrap_or_default()
+    }
+
+    /// Which comment page the parent sits on.
+    pub fn parent_page(&self, per_page: i64) -> i64 {
+        self.parent_position / per_page + 1
+    }
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+pub struct CommentParentId {
+    pub id: Uuid,
+    pub parent_id: Option<Uuid>,
+}
+
+pub struct UpdateCommentParent {
+    pub id: Uuid,
+    pub parent_id: Uuid,
+}
+
+impl UpdateCommentParent {
+    // the parent must be a comment of the same article
+    pub fn update(&self) -> Result<CommentParentId, String> {
+        let em = db::get_db();
+        let sql = "UPDATE comment SET parent_id = $2 WHERE id = $1 \
+                   AND article_id = (SELECT article_id FROM comment WHERE id = $2) \
+                   RETURNING id, parent_id";
+
+        em.execute_sql_with_one_return::<CommentParentId>(sql, &[&self.id, &self.parent_id])
+            .map_err(|_| format!("set parent of comment {} error", self.id))
+    }
+}
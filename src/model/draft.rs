@@ -0,0 +1,103 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames};
+
+use crate::db;
+use crate::model::trash::{STATUS_NORMAL, STATUS_DRAFT};
+
+// drafts are article rows with STATUS_DRAFT, only their author sees them
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct Draft {
+    pub id: Uuid,
+    pub title: String,
+    pub raw_content: String,
+    pub section_id: Uuid,
+    pub author_id: Uuid,
+    pub tags: String,
+    pub extlink: String,
+    pub stype: i32,
+    pub created_time: DateTime<Utc>,
+    pub updated_time: Option<DateTime<Utc>>,
+}
+
+const DRAFT_COLUMNS: &str = "id, title, raw_content, section_id, author_id, tags, extlink, stype, created_time, updated_time";
+
+impl Draft {
+    pub fn get_by_id(id: Uuid, author_id: Uuid) -> Result<Draft, String> {
+        let em = db::get_db();
+        let sql = format!("SELECT {} FROM article WHERE id = $1 AND author_id = $2 AND status = $3", DRAFT_COLUMNS);
+
+        em.execute_sql_with_one_return::<Draft>(&sql, &[&id, &author_id, &STATUS_DRAFT])
+            .map_err(|_| format!("no this draft: {}", id))
+    }
+
+    pub fn get_drafts_by_user(author_id: Uuid) -> Vec<Draft> {
+        let em = db::get_db();
+        let sql = format!("SELECT {} FROM article WHERE author_id = $1 AND status = $2 ORDER BY updated_time DESC", DRAFT_COLUMNS);
+
+        em.execute_sql_with_return::<Draft>(&sql, &[&author_id, &STATUS_DRAFT])
+            .unwrap_or_default()
+    }
+
+    pub fn delete(id: Uuid, author_id: Uuid) -> Result<Draft, String> {
+        let em = db::get_db();
+        let sql = format!("DELETE FROM article WHERE id = $1 AND author_id = $2 AND status = $3 RETURNING {}", DRAFT_COLUMNS);
+
+        em.execute_sql_with_one_return::<Draft>(&sql, &[&id, &author_id, &STATUS_DRAFT])
+            .map_err(|_| format!("delete draft {} error", id))
+    }
+}
+
+pub struct DraftSave {
+    // None creates a new draft
+    pub id: Option<Uuid>,
+    pub title: String,
+    pub tags: String,
+    pub extlink: String,
+    pub section_id: Uuid,
+    pub author_id: Uuid,
+    pub raw_content: String,
+    pub content: String,
+    pub stype: i32,
+}
+
+impl DraftSave {
+    pub fn save(&self) -> Result<Draft, String> {
+        let em = db::get_db();
+        match self.id {
+            Some(id) => {
+                // only the author's own, still unpublished draft
+                let sql = format!("UPDATE article SET title = $3, tags = $4, extlink = $5, section_id = $6, \
+                                   raw_content = $7, content = $8, updated_time = now() \
+                                   WHERE id = $1 AND author_id = $2 AND status = $9 RETURNING {}", DRAFT_COLUMNS);
+
+                em.execute_sql_with_one_return::<Draft>(&sql, &[&id, &self.author_id, &self.title, &self.tags, &self.extlink, &self.section_id, &self.raw_content, &self.content, &STATUS_DRAFT])
+                    .map_err(|_| format!("save draft {} error", id))
+            },
+            None => {
+                let sql = format!("INSERT INTO article (title, tags, extlink, section_id, author_id, raw_content, content, stype, status, updated_time) \
+                                   VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, now()) RETURNING {}", DRAFT_COLUMNS);
+
+                em.execute_sql_with_one_return::<Draft>(&sql, &[&self.title, &self.tags, &self.extlink, &self.section_id, &self.author_id, &self.raw_content, &self.content, &self.stype, &STATUS_DRAFT])
+                    .map_err(|_| "save draft error".to_string())
+            }
+        }
+    }
+}
+
+pub struct PublishDraft {
+    pub id: Uuid,
+    pub author_id: Uuid,
+}
+
+impl PublishDraft {
+    // the article is born when it's published, not when the draft was started
+    pub fn update(&self) -> Result<Draft, String> {
+        let em = db::get_db();
+        let sql = format!("UPDATE article SET status = $3, created_time = now(), updated_time = now() \
+                           WHERE id = $1 AND author_id = $2 AND status = $4 RETURNING {}", DRAFT_COLUMNS);
+
+        em.execute_sql_with_one_return::<Draft>(&sql, &[&self.id, &self.author_id, &STATUS_NORMAL, &STATUS_DRAFT])
+            .map_err(|_| format!("publish draft {} error", self.id))
+    }
+}
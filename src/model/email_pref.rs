@@ -0,0 +1,188 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames, ToTableName};
+
+use crate::db;
+use crate::model::notification::{
+    NOTIFY_REPLY,
+    NOTIFY_COMMENT,
+    NOTIFY_MENTION
+};
+
+pub const DIGEST_OFF: i16 = 0;
+pub const DIGEST_DAILY: i16 = 1;
+pub const DIGEST_WEEKLY: i16 = 2;
+
+// email is opt-in, no row means no email at all
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames, ToTableName)]
+pub struct EmailPref {
+    pub user_id: Uuid,
+    pub email_replies: bool,
+    pub email_mentions: bool,
+    pub digest: i16,
+    // put in every mail, lets the reader turn all email off without logging in
+    pub unsubscribe_token: String,
+    pub last_digest_time: DateTime<Utc>,
+    // given on the account page, no mail goes there before it is confirmed
+    pub email: String,
+    pub email_confirmed: bool,
+    #[serde(skip_serializing)]
+    pub confirm_token: Option<String>,
+}
+
+// one line of a digest
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct DigestArticle {
+    pub id: Uuid,
+    pub title: String,
+    pub section_id: Uuid,
+    pub section_title: String,
+    pub author_nickname: String,
+    pub comment_count: i64,
+    pub created_time: DateTime<Utc>,
+}
+
+impl EmailPref {
+    pub fn get_by_user(user_id: Uuid) -> Result<EmailPref, String> {
+        let em = db::get_db();
+        let sql = "SELECT * FROM email_pref WHERE user_id = $1";
+
+        em.execute_sql_with_one_return::<EmailPref>(sql, &[&user_id])
+            .map_err(|_| format!("no email preferences of user: {}", user_id))
+    }
+
+    pub fn get_by_token(token: &str) -> Result<EmailPref, String> {
+        let em = db::get_db();
+        let sql = "SELECT * FROM email_pref WHERE unsubscribe_token = $1";
+
+        em.execute_sql_with_one_return::<EmailPref>(sql, &[&token])
+            .map_err(|_| "no this unsubscribe link.".to_string())
+    }
+
+    /// Whether a notification of this type should also go out by email.
+    pub fn wants(&self, ntype: i16) -> bool {
+        match ntype {
+            NOTIFY_REPLY | NOTIFY_COMMENT => self.email_replies,
+            NOTIFY_MENTION => self.email_mentions,
+            _ => false,
+        }
+    }
+
+    /// The address to mail, once its owner confirmed it.
+    pub fn confirmed_email(&self) -> Option<&str> {
+        if self.email_confirmed && self.email != "" {
+            Some(&self.email)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Follow the link of a confirmation mail, the token is used up.
+    pub fn confirm(token: &str) -> Result<EmailPref, String> {
+        let em = db::get_db();
+        let sql = "UPDATE email_pref SET email_confirmed = true, confirm_token = NULL \
+                   WHERE confirm_token = $1 RETURNING *";
+
+        em.execute_sql_with_one_return::<EmailPref>(sql, &[&token])
+            .map_err(|_| "no this confirmation link.".to_string())
+    }
+
+    /// Turn every email off, the token stays valid so the link can be clicked twice.
+    pub fn unsubscribe(token: &str) -> Result<EmailPref, String> {
+        let em = db::get_db();
+        let sql = "UPDATE email_pref SET email_replies = false, email_mentions = false, digest = $2 \
+                   WHERE unsubscribe_token = $1 RETURNING *";
+
+        em.execute_sql_with_one_return::<EmailPref>(sql, &[&token, &DIGEST_OFF])
+            .map_err(|_| "no this unsubscribe link.".to_string())
+    }
+
+    /// The users whose daily or weekly digest is due.
+    pub fn due_digests() -> Vec<EmailPref> {
+        let em = db::get_db();
+        let sql = "SELECT * FROM email_pref \
+                   WHERE (digest = $1 AND last_digest_time < now() - interval '1 day') \
+                   OR (digest = $2 AND last_digest_time < now() - interval '7 days')";
+
+        em.execute_sql_with_return::<EmailPref>(sql, &[&DIGEST_DAILY, &DIGEST_WEEKLY])
+            .unwrap_or_default()
+    }
+
+    pub fn mark_digest_sent(user_id: Uuid) {
+        let em = db::get_db();
+        let sql = "UPDATE email_pref SET last_digest_time = now() WHERE user_id = $1 RETURNING *";
+
+        let _ = em.execute_sql_with_one_return::<EmailPref>(sql, &[&user_id]);
+    }
+}
+
+impl DigestArticle {
+    /// The most commented new articles of each section the user follows.
+    pub fn top_new_in_followed(user_id: Uuid, since: DateTime<Utc>, per_section: i64) -> Vec<DigestArticle> {
+        let em = db::get_db();
+        let sql = "SELECT id, title, section_id, section_title, author_nickname, comment_count, created_time FROM ( \
+                   SELECT a.id, a.title, a.section_id, s.title AS section_title, u.nickname AS author_nickname, \
+                   count(c.id) AS comment_count, a.created_time, \
+                   row_number() OVER (PARTITION BY a.section_id ORDER BY count(c.id) DESC, a.created_time DESC) AS rank \
+                   FROM article a \
+                   JOIN section_follow f ON f.section_id = a.section_id AND f.user_id = $1 \
+                   JOIN section s ON s.id = a.section_id \
+                   JOIN ruser u ON u.id = a.author_id \
+                   LEFT JOIN comment c ON c.article_id = a.id AND c.status = 0 \
+                   WHERE a.status = 0 AND a.created_time > $2 \
+                   GROUP BY a.id, s.title, u.nickname \
+                   ) t WHERE rank <= $3 ORDER BY section_title, rank";
+
+        em.execute_sql_with_return::<DigestArticle>(sql, &[&user_id, &since, &per_section])
+            .unwrap_or_default()
+    }
+}
+
+pub struct EmailPrefUpdate {
+    pub user_id: Uuid,
+    pub email_replies: bool,
+    pub email_mentions: bool,
+    pub digest: i16,
+}
+
+impl EmailPrefUpdate {
+    pub fn update(&self) -> Result<EmailPref, String> {
+        let digest = if [DIGEST_OFF, DIGEST_DAILY, DIGEST_WEEKLY].contains(&self.digest) { self.digest } else { DIGEST_OFF };
+        // only used when the row is new
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+
+        let em = db::get_db();
+        let sql = "INSERT INTO email_pref (user_id, email_replies, email_mentions, digest, unsubscribe_token) \
+                   VALUES ($1, $2, $3, $4, $5) \
+                   ON CONFLICT (user_id) DO UPDATE SET email_replies = EXCLUDED.email_replies, \
+                   email_mentions = EXCLUDED.email_mentions, digest = EXCLUDED.digest RETURNING *";
+
+        em.execute_sql_with_one_return::<EmailPref>(sql, &[&self.user_id, &self.email_replies, &self.email_mentions, &digest, &token])
+            .map_err(|_| "update email preferences error.".to_string())
+    }
+}
+
+// a new address is unconfirmed, and gets a fresh token to confirm it with
+pub struct EmailAddressUpdate {
+    pub user_id: Uuid,
+    pub email: String,
+}
+
+impl EmailAddressUpdate {
+    pub fn update(&self) -> Result<EmailPref, String> {
+        let token = if self.email != "" {
+            Some(format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()))
+        }
+        else {
+            None
+        };
+
+        let em = db::get_db();
+        let sql = "UPDATE email_pref SET email = $2, email_confirmed = false, confirm_token = $3 \
+                   WHERE user_id = $1 RETURNING *";
+
+        em.execute_sql_with_one_return::<EmailPref>(sql, &[&self.user_id, &self.email, &token])
+            .map_err(|_| "update email address error.".to_string())
+    }
+}
@@ -0,0 +1,78 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames};
+
+use crate::db;
+use crate::model::trash::STATUS_NORMAL;
+
+// an article as the syndication feeds see it
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct FeedArticle {
+    pub id: Uuid,
+    pub title: String,
+    pub raw_content: String,
+    pub content: String,
+    pub section_id: Uuid,
+    pub author_id: Uuid,
+    pub author_name: String,
+    pub created_time: DateTime<Utc>,
+    pub updated_time: Option<DateTime<Utc>>,
+}
+
+// a comment as the comments feed of an article sees it
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct FeedComment {
+    pub id: Uuid,
+    pub article_id: Uuid,
+    pub article_title: String,
+    pub raw_content: String,
+    pub content: String,
+    pub author_name: String,
+    pub created_time: DateTime<Utc>,
+}
+
+const FEED_ARTICLE_SELECT: &str = "SELECT a.id, a.title, a.raw_content, a.content, a.section_id, a.author_id, \
+                                   u.nickname AS author_name, a.created_time, a.updated_time \
+                                   FROM article a JOIN ruser u ON u.id = a.author_id";
+
+impl FeedArticle {
+    pub fn get_latest(limit: i64) -> Vec<FeedArticle> {
+        let em = db::get_db();
+        let sql = format!("{} WHERE a.status = $1 ORDER BY a.created_time DESC LIMIT $2", FEED_ARTICLE_SELECT);
+
+        em.execute_sql_with_return::<FeedArticle>(&sql, &[&STATUS_NORMAL, &limit])
+            .unwrap_or_default()
+    }
+
+    // a forum section, or the blog of a user
+    pub fn get_latest_in_section(section_id: Uuid, limit: i64) -> Vec<FeedArticle> {
+        let em = db::get_db();
+        let sql = format!("{} WHERE a.section_id = $1 AND a.status = $2 ORDER BY a.created_time DESC LIMIT $3", FEED_ARTICLE_SELECT);
+
+        em.execute_sql_with_return::<FeedArticle>(&sql, &[&section_id, &STATUS_NORMAL, &limit])
+            .unwrap_or_default()
+    }
+
+    pub fn get_latest_by_tag(tag_id: Uuid, limit: i64) -> Vec<FeedArticle> {
+        let em = db::get_db();
+        let sql = format!("{} JOIN article_tag t ON t.article_id = a.id \
+                           WHERE t.tag_id = $1 AND a.status = $2 ORDER BY a.created_time DESC LIMIT $3", FEED_ARTICLE_SELECT);
+
+        em.execute_sql_with_return::<FeedArticle>(&sql, &[&tag_id, &STATUS_NORMAL, &limit])
+            .unwrap_or_default()
+    }
+}
+
+impl FeedComment {
+    pub fn get_latest_in_article(article_id: Uuid, limit: i64) -> Vec<FeedComment> {
+        let em = db::get_db();
+        let sql = "SELECT c.id, c.article_id, a.title AS article_title, c.raw_content, c.content, \
+                   u.nickname AS author_name, c.created_time \
+                   FROM comment c JOIN article a ON a.id = c.article_id JOIN ruser u ON u.id = c.author_id \
+                   WHERE c.article_id = $1 AND c.status = $2 AND a.status = $2 \
+                   ORDER BY c.created_time DESC LIMIT $3";
+
+        em.execute_sql_with_return::<FeedComment>(sql, &[&article_id, &STATUS_NORMAL, &limit])
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,180 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames};
+
+use crate::db;
+use crate::model::trash::STATUS_NORMAL;
+use crate::model::qa::qa_columns;
+
+// one line of the "Following" feed, shaped like the other article listings
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct FollowedArticle {
+    pub id: Uuid,
+    pub title: String,
+    pub section_title: String,
+    pub author_name: String,
+    pub created_time: DateTime<Utc>,
+    // the article, or its latest comment
+    pub activity_time: DateTime<Utc>,
+    pub score: i32,
+    pub question: bool,
+    pub solved: bool,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct SectionIdRow {
+    section_id: Uuid,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct FolloweeIdRow {
+    followee_id: Uuid,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct CountRow {
+    count: i64,
+}
+
+pub struct SectionFollow;
+
+impl SectionFollow {
+    pub fn sections_of(user_id: Uuid) -> Vec<Uuid> {
+        let em = db::get_db();
+        let sql = "SELECT section_id FROM section_follow WHERE user_id = $1";
+
+        em.execute_sql_with_return::<SectionIdRow>(sql, &[&user_id])
+            .unwrap_or_default()
+            .into_iter()
+            .map(|r| r.section_id)
+            .collect()
+    }
+
+    pub fn is_following(user_id: Uuid, section_id: Uuid) -> bool {
+        let em = db::get_db();
+        let sql = "SELECT section_id FROM section_follow WHERE user_id = $1 AND section_id = $2";
+
+        em.execute_sql_with_one_return::<SectionIdRow>(sql, &[&user_id, &section_id]).is_ok()
+    }
+
+    pub fn followers_count(section_id: Uuid) -> i64 {
+        let em = db::get_db();
+        let sql = "SELECT count(*) AS count FROM section_follow WHERE section_id = $1";
+
+        em.execute_sql_with_one_return::<CountRow>(sql, &[&section_id])
+            .map(|r| r.count)
+            .unwrap_or(0)
+    }
+
+    /// Follow the section, or unfollow it if followed. Returns whether it is followed now.
+    pub fn toggle(user_id: Uuid, section_id: Uuid) -> Result<bool, String> {
+        let em = db::get_db();
+        let sql = "DELETE FROM section_follow WHERE user_id = $1 AND section_id = $2 RETURNING section_id";
+        let removed = em.execute_sql_with_return::<SectionIdRow>(sql, &[&user_id, &section_id])
+            .map_err(|_| "follow error.".to_string())?;
+        if !removed.is_empty() {
+            return Ok(false);
+        }
+
+        let sql = "INSERT INTO section_follow (user_id, section_id) VALUES ($1, $2) \
+                   ON CONFLICT DO NOTHING RETURNING section_id";
+        em.execute_sql_with_one_return::<SectionIdRow>(sql, &[&user_id, &section_id])
+            .map(|_| true)
+            .map_err(|_| format!("no this section: {}", section_id))
+    }
+}
+
+pub struct SectionFollowUpdate {
+    pub user_id: Uuid,
+    // the whole set of followed sections, the others are unfollowed
+    pub section_ids: Vec<Uuid>,
+}
+
+impl SectionFollowUpdate {
+    pub fn update(&self) -> Result<(), String> {
+        let em = db::get_db();
+        let sql = "DELETE FROM section_follow WHERE user_id = $1 RETURNING section_id";
+        em.execute_sql_with_return::<SectionIdRow>(sql, &[&self.user_id])
+            .map_err(|_| "update followed sections error.".to_string())?;
+
+        for section_id in self.section_ids.iter() {
+            let sql = "INSERT INTO section_follow (user_id, section_id) VALUES ($1, $2) \
+                       ON CONFLICT DO NOTHING RETURNING section_id";
+            let _ = em.execute_sql_with_return::<SectionIdRow>(sql, &[&self.user_id, section_id]);
+        }
+
+        Ok(())
+    }
+}
+
+pub struct UserFollow;
+
+impl UserFollow {
+    pub fn is_following(follower_id: Uuid, followee_id: Uuid) -> bool {
+        let em = db::get_db();
+        let sql = "SELECT followee_id FROM user_follow WHERE follower_id = $1 AND followee_id = $2";
+
+        em.execute_sql_with_one_return::<FolloweeIdRow>(sql, &[&follower_id, &followee_id]).is_ok()
+    }
+
+    pub fn followers_count(user_id: Uuid) -> i64 {
+        let em = db::get_db();
+        let sql = "SELECT count(*) AS count FROM user_follow WHERE followee_id = $1";
+
+        em.execute_sql_with_one_return::<CountRow>(sql, &[&user_id])
+            .map(|r| r.count)
+            .unwrap_or(0)
+    }
+
+    /// Follow the user, or unfollow them if followed. Returns whether they are followed now.
+    pub fn toggle(follower_id: Uuid, followee_id: Uuid) -> Result<bool, String> {
+        if follower_id == followee_id {
+            return Err("can't follow yourself.".to_string());
+        }
+        let em = db::get_db();
+        let sql = "DELETE FROM user_follow WHERE follower_id = $1 AND followee_id = $2 RETURNING followee_id";
+        let removed = em.execute_sql_with_return::<FolloweeIdRow>(sql, &[&follower_id, &followee_id])
+            .map_err(|_| "follow error.".to_string())?;
+        if !removed.is_empty() {
+            return Ok(false);
+        }
+
+        let sql = "INSERT INTO user_follow (follower_id, followee_id) VALUES ($1, $2) \
+                   ON CONFLICT DO NOTHING RETURNING followee_id";
+        em.execute_sql_with_one_return::<FolloweeIdRow>(sql, &[&follower_id, &followee_id])
+            .map(|_| true)
+            .map_err(|_| format!("no this user: {}", followee_id))
+    }
+}
+
+// articles of the followed authors and sections, the latest active first
+const FOLLOWED_WHERE: &str = "WHERE a.status = $2 AND (\
+                              a.author_id IN (SELECT followee_id FROM user_follow WHERE follower_id = $1) \
+                              OR a.section_id IN (SELECT section_id FROM section_follow WHERE user_id = $1))";
+
+pub struct FollowingFeed;
+
+impl FollowingFeed {
+    pub fn get_paging(user_id: Uuid, current_page: i64, per_page: i64) -> Vec<FollowedArticle> {
+        let em = db::get_db();
+        let offset = (current_page - 1).max(0) * per_page;
+        let sql = format!("SELECT a.id, a.title, s.title AS section_title, u.nickname AS author_name, a.created_time, \
+                           greatest(a.created_time, a.updated_time) AS activity_time, a.score, {} \
+                           FROM article a \
+                           JOIN section s ON s.id = a.section_id \
+                           JOIN ruser u ON u.id = a.author_id \
+                           {} ORDER BY activity_time DESC LIMIT $3 OFFSET $4", qa_columns(), FOLLOWED_WHERE);
+
+        em.execute_sql_with_return::<FollowedArticle>(&sql, &[&user_id, &STATUS_NORMAL, &per_page, &offset])
+            .unwrap_or_default()
+    }
+
+    pub fn count(user_id: Uuid) -> i64 {
+        let em = db::get_db();
+        let sql = format!("SELECT count(*) AS count FROM article a {}", FOLLOWED_WHERE);
+
+        em.execute_sql_with_one_return::<CountRow>(&sql, &[&user_id, &STATUS_NORMAL])
+            .map(|r| r.count)
+            .unwrap_or(0)
+    }
+}
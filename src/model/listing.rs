@@ -0,0 +1,155 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames};
+
+use crate::db;
+use crate::dataservice::article::Article;
+use crate::model::trash::STATUS_NORMAL;
+use crate::model::qa::qa_columns;
+
+// one line of the latest, section, blog and author listings
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct ListedArticle {
+    pub id: Uuid,
+    pub title: String,
+    pub tags: String,
+    pub section_id: Uuid,
+    pub section_title: String,
+    pub author_id: Uuid,
+    pub author_name: String,
+    pub created_time: DateTime<Utc>,
+    pub comment_count: i64,
+    pub score: i32,
+    pub question: bool,
+    pub solved: bool,
+}
+
+// a line of a section listing, which shows how often it was read
+#[derive(Debug, Clone, Serialize)]
+pub struct ListedArticleWithStats {
+    pub article: ListedArticle,
+    pub viewtimes: i64,
+}
+
+// what the search index keeps of an article
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+pub struct SearchableArticle {
+    pub id: Uuid,
+    pub title: String,
+    pub raw_content: String,
+    pub created_time: DateTime<Utc>,
+}
+
+#[derive(Debug, FromDao, ToColumnNames)]
+struct CountRow {
+    count: i64,
+}
+
+// Deleted, hidden and draft articles are left out by the paging and the
+// counts alike, so every page is full and the page count is right.
+const LISTED_COLUMNS: &str = "a.id, a.title, a.tags, a.section_id, s.title AS section_title, \
+                              a.author_id, u.nickname AS author_name, a.created_time, \
+                              (SELECT count(*) FROM comment c WHERE c.article_id = a.id AND c.status = $1) AS comment_count, \
+                              a.score";
+const LISTED_FROM: &str = "FROM article a \
+                           JOIN section s ON s.id = a.section_id \
+                           JOIN ruser u ON u.id = a.author_id";
+
+// forum articles have stype 0, blog articles 1
+const FORUM_WHERE: &str = "WHERE a.status = $1 AND a.stype = 0";
+const BLOG_WHERE: &str = "WHERE a.status = $1 AND a.stype = 1";
+const SECTION_WHERE: &str = "WHERE a.status = $1 AND a.section_id = $2";
+const AUTHOR_WHERE: &str = "WHERE a.status = $1 AND a.author_id = $2";
+
+const LATEST_ORDER: &str = "ORDER BY a.created_time DESC, a.id";
+// by the latest visible comment, the ones without any last
+const REPLY_ORDER: &str = "ORDER BY (SELECT max(c.created_time) FROM comment c WHERE c.article_id = a.id AND c.status = $1) \
+                           DESC NULLS LAST, a.created_time DESC, a.id";
+
+fn offset_of(current_page: i64, per_page: i64) -> i64 {
+    (current_page - 1).max(0) * per_page
+}
+
+fn paging(where_sql: &str, order_sql: &str, current_page: i64, per_page: i64) -> Vec<ListedArticle> {
+    let em = db::get_db();
+    let offset = offset_of(current_page, per_page);
+    let sql = format!("SELECT {}, {} {} {} {} LIMIT $2 OFFSET $3", LISTED_COLUMNS, qa_columns(), LISTED_FROM, where_sql, order_sql);
+
+    em.execute_sql_with_return::<ListedArticle>(&sql, &[&STATUS_NORMAL, &per_page, &offset])
+        .unwrap_or_default()
+}
+
+fn paging_of(where_sql: &str, id: Uuid, current_page: i64, per_page: i64) -> Vec<ListedArticle> {
+    let em = db::get_db();
+    let offset = offset_of(current_page, per_page);
+    let sql = format!("SELECT {}, {} {} {} {} LIMIT $3 OFFSET $4", LISTED_COLUMNS, qa_columns(), LISTED_FROM, where_sql, LATEST_ORDER);
+
+    em.execute_sql_with_return::<ListedArticle>(&sql, &[&STATUS_NORMAL, &id, &per_page, &offset])
+        .unwrap_or_default()
+}
+
+fn count(where_sql: &str, id: Option<Uuid>) -> i64 {
+    let em = db::get_db();
+    let sql = format!("SELECT count(*) AS count FROM article a {}", where_sql);
+    let row = match id {
+        Some(id) => em.execute_sql_with_one_return::<CountRow>(&sql, &[&STATUS_NORMAL, &id]),
+        None => em.execute_sql_with_one_return::<CountRow>(&sql, &[&STATUS_NORMAL]),
+    };
+
+    row.map(|r| r.count).unwrap_or(0)
+}
+
+pub struct Listing;
+
+impl Listing {
+    pub fn latest_forum(current_page: i64, per_page: i64) -> Vec<ListedArticle> {
+        paging(FORUM_WHERE, LATEST_ORDER, current_page, per_page)
+    }
+
+    pub fn latest_reply(current_page: i64, per_page: i64) -> Vec<ListedArticle> {
+        paging(FORUM_WHERE, REPLY_ORDER, current_page, per_page)
+    }
+
+    pub fn forum_count() -> i64 {
+        count(FORUM_WHERE, None)
+    }
+
+    pub fn latest_blog(current_page: i64, per_page: i64) -> Vec<ListedArticle> {
+        paging(BLOG_WHERE, LATEST_ORDER, current_page, per_page)
+    }
+
+    pub fn blog_count() -> i64 {
+        count(BLOG_WHERE, None)
+    }
+
+    pub fn in_section(section_id: Uuid, current_page: i64, per_page: i64) -> Vec<ListedArticleWithStats> {
+        paging_of(SECTION_WHERE, section_id, current_page, per_page)
+            .into_iter()
+            .map(|article| ListedArticleWithStats {
+                viewtimes: Article::get_viewtimes(article.id),
+                article,
+            })
+            .collect()
+    }
+
+    pub fn section_count(section_id: Uuid) -> i64 {
+        count(SECTION_WHERE, Some(section_id))
+    }
+
+    pub fn by_author(author_id: Uuid, current_page: i64, per_page: i64) -> Vec<ListedArticle> {
+        paging_of(AUTHOR_WHERE, author_id, current_page, per_page)
+    }
+
+    pub fn author_count(author_id: Uuid) -> i64 {
+        count(AUTHOR_WHERE, Some(author_id))
+    }
+
+    // every article search may find, for rebuilding its index
+    pub fn searchable() -> Vec<SearchableArticle> {
+        let em = db::get_db();
+        let sql = "SELECT id, title, raw_content, created_time FROM article WHERE status = $1";
+
+        em.execute_sql_with_return::<SearchableArticle>(sql, &[&STATUS_NORMAL])
+            .unwrap_or_default()
+    }
+}
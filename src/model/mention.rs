@@ -0,0 +1,32 @@
+use uuid::Uuid;
+use rustorm::{FromDao, ToColumnNames};
+
+use crate::db;
+
+// the little of a user a mention needs
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct MentionedUser {
+    pub id: Uuid,
+    pub nickname: String,
+}
+
+impl MentionedUser {
+    pub fn get_by_nickname(nickname: &str) -> Result<MentionedUser, String> {
+        let em = db::get_db();
+        let sql = "SELECT id, nickname FROM ruser WHERE nickname = $1 AND status = 0 LIMIT 1";
+
+        em.execute_sql_with_one_return::<MentionedUser>(sql, &[&nickname])
+            .map_err(|_| format!("no this user: {}", nickname))
+    }
+
+    /// Users whose nickname starts with `prefix`, for the editor autocomplete.
+    pub fn search_by_prefix(prefix: &str, limit: i64) -> Vec<MentionedUser> {
+        let em = db::get_db();
+        let pattern = format!("{}%", prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        let sql = "SELECT id, nickname FROM ruser WHERE nickname ILIKE $1 AND status = 0 \
+                   ORDER BY length(nickname), nickname LIMIT $2";
+
+        em.execute_sql_with_return::<MentionedUser>(sql, &[&pattern, &limit])
+            .unwrap_or_default()
+    }
+}
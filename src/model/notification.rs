@@ -0,0 +1,234 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames, ToTableName};
+
+use crate::db;
+
+// someone replied to my comment
+pub const NOTIFY_REPLY: i16 = 1;
+// someone commented on my article
+pub const NOTIFY_COMMENT: i16 = 2;
+// someone @mentioned me
+pub const NOTIFY_MENTION: i16 = 3;
+// a moderator hid, deleted or restored my content, see `detail`
+pub const NOTIFY_MODERATION: i16 = 4;
+// someone commented on an article I bookmarked, with notify on
+pub const NOTIFY_BOOKMARK: i16 = 5;
+
+// for the preferences form
+pub const ALL_NOTIFY_TYPES: [(i16, &str); 5] = [
+    (NOTIFY_REPLY, "Replies to my comments"),
+    (NOTIFY_COMMENT, "Comments on my articles"),
+    (NOTIFY_MENTION, "Mentions of me"),
+    (NOTIFY_MODERATION, "Moderation of my content"),
+    (NOTIFY_BOOKMARK, "Comments on my bookmarked articles"),
+];
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames, ToTableName)]
+pub struct Notification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub ntype: i16,
+    pub actor_id: Uuid,
+    pub article_id: Uuid,
+    pub comment_id: Option<Uuid>,
+    pub is_read: bool,
+    pub created_time: DateTime<Utc>,
+    pub detail: String,
+}
+
+// for listing, with names instead of ids
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct NotificationInfo {
+    pub id: Uuid,
+    pub ntype: i16,
+    pub actor_id: Uuid,
+    pub actor_nickname: String,
+    pub article_id: Uuid,
+    pub article_title: String,
+    pub comment_id: Option<Uuid>,
+    pub is_read: bool,
+    pub created_time: DateTime<Utc>,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NotificationPrefItem {
+    pub ntype: i16,
+    pub name: &'static str,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct CountRow {
+    count: i64,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct NtypeRow {
+    ntype: i16,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct IdRow {
+    id: Uuid,
+}
+
+const INFO_SELECT: &str = "SELECT n.id, n.ntype, n.actor_id, u.nickname AS actor_nickname, n.article_id, a.title AS article_title, \
+                           n.comment_id, n.is_read, n.created_time, n.detail \
+                           FROM notification n \
+                           JOIN ruser u ON u.id = n.actor_id \
+                           JOIN article a ON a.id = n.article_id";
+
+impl Notification {
+    pub fn get_paging_by_user(user_id: Uuid, current_page: i64, per_page: i64) -> Vec<NotificationInfo> {
+        let em = db::get_db();
+        let offset = (current_page - 1).max(0) * per_page;
+        let sql = format!("{} WHERE n.user_id = $1 ORDER BY n.created_time DESC LIMIT $2 OFFSET $3", INFO_SELECT);
+
+        em.execute_sql_with_return::<NotificationInfo>(&sql, &[&user_id, &per_page, &offset])
+            .unwrap_or_default()
+    }
+
+    pub fn get_info_by_id(id: Uuid) -> Result<NotificationInfo, String> {
+        let em = db::get_db();
+        let sql = format!("{} WHERE n.id = $1", INFO_SELECT);
+
+        em.execute_sql_with_one_return::<NotificationInfo>(&sql, &[&id])
+            .map_err(|_| format!("no this notification: {}", id))
+    }
+
+    pub fn count_by_user(user_id: Uuid) -> i64 {
+        let em = db::get_db();
+        let sql = "SELECT count(*) AS count FROM notification WHERE user_id = $1";
+
+        em.execute_sql_with_one_return::<CountRow>(sql, &[&user_id])
+            .map(|r| r.count)
+            .unwrap_or(0)
+    }
+
+    pub fn unread_count(user_id: Uuid) -> i64 {
+        let em = db::get_db();
+        let sql = "SELECT count(*) AS count FROM notification WHERE user_id = $1 AND is_read = false";
+
+        em.execute_sql_with_one_return::<CountRow>(sql, &[&user_id])
+            .map(|r| r.count)
+            .unwrap_or(0)
+    }
+
+    pub fn mark_read(id: Uuid, user_id: Uuid) -> Result<Notification, String> {
+        let em = db::get_db();
+        let sql = "UPDATE notification SET is_read = true WHERE id = $1 AND user_id = $2 RETURNING *";
+
+        em.execute_sql_with_one_return::<Notification>(sql, &[&id, &user_id])
+            .map_err(|_| format!("no this notification: {}", id))
+    }
+
+    pub fn mark_all_read(user_id: Uuid) {
+        let em = db::get_db();
+        let sql = "UPDATE notification SET is_read = true WHERE user_id = $1 AND is_read = false RETURNING id";
+
+        let _ = em.execute_sql_with_return::<IdRow>(sql, &[&user_id]);
+    }
+}
+
+pub struct NotificationPref;
+
+impl NotificationPref {
+    pub fn is_enabled(user_id: Uuid, ntype: i16) -> bool {
+        !NotificationPref::disabled_types(user_id).contains(&ntype)
+    }
+
+    pub fn disabled_types(user_id: Uuid) -> Vec<i16> {
+        let em = db::get_db();
+        let sql = "SELECT ntype FROM notification_pref WHERE user_id = $1 AND enabled = false";
+
+        em.execute_sql_with_return::<NtypeRow>(sql, &[&user_id])
+            .unwrap_or_default()
+            .into_iter()
+            .map(|r| r.ntype)
+            .collect()
+    }
+
+    pub fn get_by_user(user_id: Uuid) -> Vec<NotificationPrefItem> {
+        let disabled = NotificationPref::disabled_types(user_id);
+
+        ALL_NOTIFY_TYPES.iter()
+            .map(|&(ntype, name)| NotificationPrefItem {
+                ntype,
+                name,
+                enabled: !disabled.contains(&ntype),
+            })
+            .collect()
+    }
+}
+
+pub struct NotificationPrefUpdate {
+    pub user_id: Uuid,
+    // the types left checked, the others are turned off
+    pub enabled: Vec<i16>,
+}
+
+impl NotificationPrefUpdate {
+    pub fn update(&self) -> Result<(), String> {
+        let em = db::get_db();
+        for &(ntype, _) in ALL_NOTIFY_TYPES.iter() {
+            let enabled = self.enabled.contains(&ntype);
+            let sql = "INSERT INTO notification_pref (user_id, ntype, enabled) VALUES ($1, $2, $3) \
+                       ON CONFLICT (user_id, ntype) DO UPDATE SET enabled = EXCLUDED.enabled RETURNING ntype";
+            em.execute_sql_with_one_return::<NtypeRow>(sql, &[&self.user_id, &ntype, &enabled])
+                .map_err(|_| "update notification preferences error.".to_string())?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct NotificationCreate {
+    pub user_id: Uuid,
+    pub ntype: i16,
+    pub actor_id: Uuid,
+    pub article_id: Uuid,
+    pub comment_id: Option<Uuid>,
+    pub detail: String,
+}
+
+impl NotificationCreate {
+    fn check_wanted(&self) -> Result<(), String> {
+        // nobody needs to hear about their own actions
+        if self.user_id == self.actor_id {
+            return Err("no self notification.".to_string());
+        }
+        if !NotificationPref::is_enabled(self.user_id, self.ntype) {
+            return Err("notification type turned off.".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn insert(&self) -> Result<Notification, String> {
+        self.check_wanted()?;
+
+        let em = db::get_db();
+        let sql = "INSERT INTO notification (user_id, ntype, actor_id, article_id, comment_id, detail) VALUES ($1, $2, $3, $4, $5, $6) RETURNING *";
+
+        em.execute_sql_with_one_return::<Notification>(sql, &[&self.user_id, &self.ntype, &self.actor_id, &self.article_id, &self.comment_id, &self.detail])
+            .map_err(|_| "notification create error.".to_string())
+    }
+
+    /// Like `insert`, but only once per user and target, so editing a post
+    /// doesn't ping the same people again.
+    pub fn insert_once(&self) -> Result<Notification, String> {
+        self.check_wanted()?;
+
+        let em = db::get_db();
+        let sql = "INSERT INTO notification (user_id, ntype, actor_id, article_id, comment_id, detail) \
+                   SELECT $1, $2, $3, $4, $5, $6 \
+                   WHERE NOT EXISTS (SELECT 1 FROM notification WHERE user_id = $1 AND ntype = $2 \
+                   AND article_id = $4 AND comment_id IS NOT DISTINCT FROM $5) \
+                   RETURNING *";
+
+        em.execute_sql_with_one_return::<Notification>(sql, &[&self.user_id, &self.ntype, &self.actor_id, &self.article_id, &self.comment_id, &self.detail])
+            .map_err(|_| "already notified.".to_string())
+    }
+}
@@ -0,0 +1,131 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames};
+
+use crate::db;
+use crate::model::trash::STATUS_NORMAL;
+
+// the pinned answer of a question
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct AcceptedAnswer {
+    pub id: Uuid,
+    pub content: String,
+    pub author_id: Uuid,
+    pub nickname: String,
+    pub created_time: DateTime<Utc>,
+    pub score: i32,
+}
+
+// one line of the "unanswered" listing of a section
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct Question {
+    pub id: Uuid,
+    pub title: String,
+    pub tags: String,
+    pub author_id: Uuid,
+    pub author_name: String,
+    pub created_time: DateTime<Utc>,
+    pub score: i32,
+    pub question: bool,
+    pub solved: bool,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct QaRow {
+    is_qa: bool,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct IdRow {
+    id: Uuid,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct CountRow {
+    count: i64,
+}
+
+/// The `question` and `solved` columns of the article `a` of a listing query.
+/// An answer deleted or hidden afterwards leaves the question unsolved.
+pub fn qa_columns() -> String {
+    format!("coalesce((SELECT sq.is_qa FROM section sq WHERE sq.id = a.section_id), false) AS question, \
+             EXISTS (SELECT 1 FROM section sq JOIN comment c ON c.id = a.accepted_comment_id \
+             WHERE sq.id = a.section_id AND sq.is_qa AND c.status = {}) AS solved", STATUS_NORMAL)
+}
+
+pub struct Qa;
+
+impl Qa {
+    /// Articles of a Q&A section are questions, its comments the answers.
+    pub fn is_qa_section(section_id: Uuid) -> bool {
+        let em = db::get_db();
+        let sql = "SELECT is_qa FROM section WHERE id = $1";
+
+        em.execute_sql_with_one_return::<QaRow>(sql, &[&section_id])
+            .map(|r| r.is_qa)
+            .unwrap_or(false)
+    }
+
+    pub fn set_qa_section(section_id: Uuid, is_qa: bool) -> Result<(), String> {
+        let em = db::get_db();
+        let sql = "UPDATE section SET is_qa = $2 WHERE id = $1 RETURNING is_qa";
+
+        em.execute_sql_with_one_return::<QaRow>(sql, &[&section_id, &is_qa])
+            .map(|_| ())
+            .map_err(|_| format!("no this section: {}", section_id))
+    }
+
+    pub fn accepted_answer_of(article_id: Uuid) -> Option<AcceptedAnswer> {
+        let em = db::get_db();
+        let sql = "SELECT c.id, c.content, c.author_id, u.nickname, c.created_time, c.score \
+                   FROM article a JOIN comment c ON c.id = a.accepted_comment_id JOIN ruser u ON u.id = c.author_id \
+                   WHERE a.id = $1 AND c.status = $2";
+
+        em.execute_sql_with_one_return::<AcceptedAnswer>(sql, &[&article_id, &STATUS_NORMAL]).ok()
+    }
+
+    /// Questions of the section without an accepted answer, newest first.
+    pub fn unanswered_in_section(section_id: Uuid, current_page: i64, napp: i64) -> Vec<Question> {
+        let em = db::get_db();
+        let offset = (current_page - 1).max(0) * napp;
+        let sql = format!("SELECT a.id, a.title, a.tags, a.author_id, u.nickname AS author_name, a.created_time, a.score, {} \
+                           FROM article a JOIN ruser u ON u.id = a.author_id \
+                           WHERE a.section_id = $1 AND a.status = $2 \
+                           AND NOT EXISTS (SELECT 1 FROM comment c WHERE c.id = a.accepted_comment_id AND c.status = $2) \
+                           ORDER BY a.created_time DESC LIMIT $3 OFFSET $4", qa_columns());
+
+        em.execute_sql_with_return::<Question>(&sql, &[&section_id, &STATUS_NORMAL, &napp, &offset])
+            .unwrap_or_default()
+    }
+
+    pub fn unanswered_in_section_count(section_id: Uuid) -> i64 {
+        let em = db::get_db();
+        let sql = "SELECT count(*) AS count FROM article a \
+                   WHERE a.section_id = $1 AND a.status = $2 \
+                   AND NOT EXISTS (SELECT 1 FROM comment c WHERE c.id = a.accepted_comment_id AND c.status = $2)";
+
+        em.execute_sql_with_one_return::<CountRow>(sql, &[&section_id, &STATUS_NORMAL])
+            .map(|r| r.count)
+            .unwrap_or(0)
+    }
+}
+
+pub struct AcceptAnswer {
+    pub article_id: Uuid,
+    // None takes the acceptance back
+    pub comment_id: Option<Uuid>,
+}
+
+impl AcceptAnswer {
+    // only a visible comment of the same article can be accepted
+    pub fn update(&self) -> Result<(), String> {
+        let em = db::get_db();
+        let sql = "UPDATE article SET accepted_comment_id = $2 WHERE id = $1 \
+                   AND ($2::uuid IS NULL OR EXISTS (SELECT 1 FROM comment WHERE id = $2 AND article_id = $1 AND status = $3)) \
+                   RETURNING id";
+
+        em.execute_sql_with_one_return::<IdRow>(sql, &[&self.article_id, &self.comment_id, &STATUS_NORMAL])
+            .map(|_| ())
+            .map_err(|_| "this comment can't be accepted as the answer.".to_string())
+    }
+}
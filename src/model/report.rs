@@ -0,0 +1,139 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames, ToTableName};
+
+use crate::db;
+
+pub const REPORT_OPEN: i16 = 0;
+pub const REPORT_DISMISSED: i16 = 1;
+pub const REPORT_RESOLVED: i16 = 2;
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames, ToTableName)]
+pub struct Report {
+    pub id: Uuid,
+    // "article" or "comment"
+    pub target_kind: String,
+    pub target_id: Uuid,
+    pub article_id: Uuid,
+    pub reporter_id: Uuid,
+    pub reason: String,
+    pub status: i16,
+    pub created_time: DateTime<Utc>,
+    pub resolved_by: Option<Uuid>,
+    pub resolved_time: Option<DateTime<Utc>>,
+}
+
+// one line of the moderation queue: all open reports on one target
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct ReportedTarget {
+    pub target_kind: String,
+    pub target_id: Uuid,
+    pub article_id: Uuid,
+    pub excerpt: String,
+    pub author_id: Uuid,
+    pub author_nickname: String,
+    pub target_status: i16,
+    pub report_count: i64,
+    // newline separated
+    pub reasons: String,
+    pub last_report_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct CountRow {
+    count: i64,
+}
+
+impl Report {
+    pub fn open_targets() -> Vec<ReportedTarget> {
+        let em = db::get_db();
+        let sql = "SELECT r.target_kind, r.target_id, r.article_id, \
+                   coalesce(a.title, left(c.raw_content, 80)) AS excerpt, \
+                   coalesce(a.author_id, c.author_id) AS author_id, u.nickname AS author_nickname, \
+                   coalesce(a.status, c.status) AS target_status, \
+                   count(*) AS report_count, string_agg(r.reason, E'\\n') AS reasons, max(r.created_time) AS last_report_time \
+                   FROM report r \
+                   LEFT JOIN article a ON r.target_kind = 'article' AND a.id = r.target_id \
+                   LEFT JOIN comment c ON r.target_kind = 'comment' AND c.id = r.target_id \
+                   JOIN ruser u ON u.id = coalesce(a.author_id, c.author_id) \
+                   WHERE r.status = $1 \
+                   GROUP BY r.target_kind, r.target_id, r.article_id, a.title, c.raw_content, a.author_id, c.author_id, u.nickname, a.status, c.status \
+                   ORDER BY last_report_time DESC";
+
+        em.execute_sql_with_return::<ReportedTarget>(sql, &[&REPORT_OPEN])
+            .unwrap_or_default()
+    }
+
+    pub fn open_count_of(target_id: Uuid) -> i64 {
+        let em = db::get_db();
+        let sql = "SELECT count(*) AS count FROM report WHERE target_id = $1 AND status = $2";
+
+        em.execute_sql_with_one_return::<CountRow>(sql, &[&target_id, &REPORT_OPEN])
+            .map(|r| r.count)
+            .unwrap_or(0)
+    }
+
+    // a moderator hid it, the threshold never resolves a report
+    pub fn was_resolved(target_id: Uuid) -> bool {
+        let em = db::get_db();
+        let sql = "SELECT count(*) AS count FROM report WHERE target_id = $1 AND status = $2";
+
+        em.execute_sql_with_one_return::<CountRow>(sql, &[&target_id, &REPORT_RESOLVED])
+            .map(|r| r.count > 0)
+            .unwrap_or(false)
+    }
+
+    /// Close all open reports of the target.
+    pub fn close_all_of(target_id: Uuid, status: i16, resolved_by: Uuid) -> Vec<Report> {
+        let em = db::get_db();
+        let sql = "UPDATE report SET status = $2, resolved_by = $3, resolved_time = now() \
+                   WHERE target_id = $1 AND status = $4 RETURNING *";
+
+        em.execute_sql_with_return::<Report>(sql, &[&target_id, &status, &resolved_by, &REPORT_OPEN])
+            .unwrap_or_default()
+    }
+}
+
+pub struct ReportCreate {
+    pub target_kind: String,
+    pub target_id: Uuid,
+    pub article_id: Uuid,
+    pub reporter_id: Uuid,
+    pub reason: String,
+}
+
+impl ReportCreate {
+    // one open report per reporter and target, so a single account can't force a hide
+    pub fn insert(&self) -> Result<Report, String> {
+        let em = db::get_db();
+        let sql = "INSERT INTO report (target_kind, target_id, article_id, reporter_id, reason) \
+                   SELECT $1, $2, $3, $4, $5 \
+                   WHERE NOT EXISTS (SELECT 1 FROM report WHERE target_id = $2 AND reporter_id = $4 AND status = $6) \
+                   RETURNING *";
+
+        em.execute_sql_with_one_return::<Report>(sql, &[&self.target_kind, &self.target_id, &self.article_id, &self.reporter_id, &self.reason, &REPORT_OPEN])
+            .map_err(|_| "you have already reported this.".to_string())
+    }
+}
+
+pub struct BanUser {
+    pub id: Uuid,
+}
+
+impl BanUser {
+    // status 0 is the only one allowed to log in; moderators and admins
+    // can't be banned from the report queue
+    pub fn update(&self) -> Result<(), String> {
+        let em = db::get_db();
+        let sql = "UPDATE ruser SET status = 1 WHERE id = $1 AND role < 5 RETURNING id";
+
+        em.execute_sql_with_one_return::<IdRow>(sql, &[&self.id])
+            .map(|_| ())
+            .map_err(|_| format!("ban user {} error", self.id))
+    }
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct IdRow {
+    id: Uuid,
+}
@@ -0,0 +1,58 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames};
+
+use crate::db;
+use crate::model::trash::STATUS_NORMAL;
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct SitemapArticle {
+    pub id: Uuid,
+    pub title: String,
+    pub lastmod: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct SitemapSection {
+    pub id: Uuid,
+    pub stype: i32,
+    pub suser: Option<Uuid>,
+}
+
+#[derive(Debug, FromDao, ToColumnNames)]
+struct CountRow {
+    count: i64,
+}
+
+pub struct Sitemap;
+
+impl Sitemap {
+    pub fn articles_count() -> i64 {
+        let em = db::get_db();
+        let sql = "SELECT count(*) AS count FROM article WHERE status = $1";
+
+        em.execute_sql_with_one_return::<CountRow>(sql, &[&STATUS_NORMAL])
+            .map(|row| row.count)
+            .unwrap_or(0)
+    }
+
+    // one chunk of the articles, oldest first so the chunks stay stable
+    pub fn articles_chunk(chunk: i64, chunk_size: i64) -> Vec<SitemapArticle> {
+        let em = db::get_db();
+        let offset = (chunk - 1) * chunk_size;
+        let sql = "SELECT id, title, greatest(created_time, updated_time) AS lastmod FROM article \
+                   WHERE status = $1 ORDER BY created_time, id LIMIT $2 OFFSET $3";
+
+        em.execute_sql_with_return::<SitemapArticle>(sql, &[&STATUS_NORMAL, &chunk_size, &offset])
+            .unwrap_or_default()
+    }
+
+    // forum sections and blogs
+    pub fn sections() -> Vec<SitemapSection> {
+        let em = db::get_db();
+        let sql = "SELECT id, stype, suser FROM section WHERE status = $1 ORDER BY created_time";
+
+        em.execute_sql_with_return::<SitemapSection>(sql, &[&STATUS_NORMAL])
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashSet;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames, ToTableName};
+
+use crate::db;
+use crate::model::trash::STATUS_NORMAL;
+use crate::model::qa::qa_columns;
+
+pub const MAX_TAGS_PER_ARTICLE: usize = 10;
+pub const MAX_TAG_LEN: usize = 32;
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames, ToTableName)]
+pub struct Tag {
+    pub id: Uuid,
+    pub name: String,
+    pub created_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct TagCount {
+    pub id: Uuid,
+    pub name: String,
+    pub article_count: i64,
+}
+
+// a line of the tag listing page
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct TagArticle {
+    pub id: Uuid,
+    pub title: String,
+    pub tags: String,
+    pub author_id: Uuid,
+    pub author_name: String,
+    pub created_time: DateTime<Utc>,
+    pub score: i32,
+    pub question: bool,
+    pub solved: bool,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct CountRow {
+    count: i64,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct IdRow {
+    id: Uuid,
+}
+
+/// Normalize one tag: lowercase, words joined by `-`, and only
+/// letters, digits and `-_.+` kept, so a tag is always safe in a path.
+pub fn normalize_tag(raw: &str) -> Option<String> {
+    let words: Vec<String> = raw.trim().trim_start_matches('#')
+        .split_whitespace()
+        .map(|w| w.chars()
+             .filter(|c| c.is_alphanumeric() || "-_.+".contains(*c))
+             .flat_map(|c| c.to_lowercase())
+             .collect::<String>())
+        .filter(|w| w != "")
+        .collect();
+    let name: String = words.join("-").chars().take(MAX_TAG_LEN).collect();
+
+    if name == "" {
+        None
+    }
+    else {
+        Some(name)
+    }
+}
+
+/// Parse the free form tags field, comma separated, into unique normalized tags.
+pub fn parse_tags(raw: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    raw.split(|c| c == ',' || c == '，' || c == ';')
+        .filter_map(normalize_tag)
+        .filter(|name| seen.insert(name.clone()))
+        .take(MAX_TAGS_PER_ARTICLE)
+        .collect()
+}
+
+impl Tag {
+    pub fn get_by_id(id: Uuid) -> Result<Tag, String> {
+        let em = db::get_db();
+        let sql = "SELECT * FROM tag WHERE id = $1";
+
+        em.execute_sql_with_one_return::<Tag>(sql, &[&id])
+            .map_err(|_| format!("no this tag: {}", id))
+    }
+
+    pub fn get_by_name(name: &str) -> Result<Tag, String> {
+        let em = db::get_db();
+        let sql = "SELECT * FROM tag WHERE name = $1";
+
+        em.execute_sql_with_one_return::<Tag>(sql, &[&name])
+            .map_err(|_| format!("no this tag: {}", name))
+    }
+
+    pub fn get_by_article(article_id: Uuid) -> Vec<Tag> {
+        let em = db::get_db();
+        let sql = "SELECT t.* FROM tag t JOIN article_tag at ON at.tag_id = t.id \
+                   WHERE at.article_id = $1 ORDER BY t.name";
+
+        em.execute_sql_with_return::<Tag>(sql, &[&article_id])
+            .unwrap_or_default()
+    }
+
+    /// Most used tags among the published articles, for the tag cloud.
+    pub fn popular(limit: i64) -> Vec<TagCount> {
+        let em = db::get_db();
+        let sql = "SELECT t.id, t.name, count(*) AS article_count \
+                   FROM tag t JOIN article_tag at ON at.tag_id = t.id JOIN article a ON a.id = at.article_id \
+                   WHERE a.status = $1 \
+                   GROUP BY t.id, t.name ORDER BY article_count DESC, t.name LIMIT $2";
+
+        em.execute_sql_with_return::<TagCount>(sql, &[&STATUS_NORMAL, &limit])
+            .unwrap_or_default()
+    }
+
+    /// All the tags, unused ones too, for the admin page.
+    pub fn all_with_counts() -> Vec<TagCount> {
+        let em = db::get_db();
+        let sql = "SELECT t.id, t.name, count(at.article_id) AS article_count \
+                   FROM tag t LEFT JOIN article_tag at ON at.tag_id = t.id \
+                   GROUP BY t.id, t.name ORDER BY t.name";
+
+        em.execute_sql_with_return::<TagCount>(sql, &[])
+            .unwrap_or_default()
+    }
+
+    /// Tags starting with `prefix`, most used first, for the editor autocomplete.
+    pub fn suggest(prefix: &str, limit: i64) -> Vec<TagCount> {
+        let em = db::get_db();
+        let pattern = format!("{}%", prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        let sql = "SELECT t.id, t.name, count(at.article_id) AS article_count \
+                   FROM tag t LEFT JOIN article_tag at ON at.tag_id = t.id \
+                   WHERE t.name LIKE $1 \
+                   GROUP BY t.id, t.name ORDER BY article_count DESC, t.name LIMIT $2";
+
+        em.execute_sql_with_return::<TagCount>(sql, &[&pattern, &limit])
+            .unwrap_or_default()
+    }
+
+    pub fn get_articles_count(tag_id: Uuid) -> i64 {
+        let em = db::get_db();
+        let sql = "SELECT count(*) AS count FROM article_tag at JOIN article a ON a.id = at.article_id \
+                   WHERE at.tag_id = $1 AND a.status = $2";
+
+        em.execute_sql_with_one_return::<CountRow>(sql, &[&tag_id, &STATUS_NORMAL])
+            .map(|r| r.count)
+            .unwrap_or(0)
+    }
+
+    pub fn get_articles_paging(tag_id: Uuid, current_page: i64, napp: i64) -> Vec<TagArticle> {
+        let em = db::get_db();
+        let offset = (current_page - 1).max(0) * napp;
+        let sql = format!("SELECT a.id, a.title, a.tags, a.author_id, u.nickname AS author_name, a.created_time, a.score, {} \
+                           FROM article_tag at JOIN article a ON a.id = at.article_id JOIN ruser u ON u.id = a.author_id \
+                           WHERE at.tag_id = $1 AND a.status = $2 \
+                           ORDER BY a.created_time DESC LIMIT $3 OFFSET $4", qa_columns());
+
+        em.execute_sql_with_return::<TagArticle>(&sql, &[&tag_id, &STATUS_NORMAL, &napp, &offset])
+            .unwrap_or_default()
+    }
+
+    /// Rename the tag; renaming onto an existing tag merges them.
+    pub fn rename(id: Uuid, new_name: &str) -> Result<Tag, String> {
+        let new_name = normalize_tag(new_name).ok_or("tag name is empty.".to_string())?;
+        if let Ok(existing) = Tag::get_by_name(&new_name) {
+            if existing.id != id {
+                return Tag::merge(id, existing.id);
+            }
+        }
+
+        let em = db::get_db();
+        let sql = "UPDATE tag SET name = $2 WHERE id = $1 RETURNING *";
+        let tag = em.execute_sql_with_one_return::<Tag>(sql, &[&id, &new_name])
+            .map_err(|_| format!("rename tag {} error", id))?;
+        Tag::refresh_article_tags(tag.id);
+
+        Ok(tag)
+    }
+
+    /// Move every article of `from` onto `into`, then drop `from`.
+    pub fn merge(from: Uuid, into: Uuid) -> Result<Tag, String> {
+        if from == into {
+            return Tag::get_by_id(into);
+        }
+        let into_tag = Tag::get_by_id(into)?;
+        let em = db::get_db();
+        let sql = "INSERT INTO article_tag (article_id, tag_id) \
+                   SELECT article_id, $2 FROM article_tag WHERE tag_id = $1 \
+                   ON CONFLICT DO NOTHING RETURNING article_id AS id";
+        em.execute_sql_with_return::<IdRow>(sql, &[&from, &into])
+            .map_err(|_| format!("merge tag {} error", from))?;
+
+        // links of `from` go away with it
+        let sql = "DELETE FROM tag WHERE id = $1 RETURNING id";
+        em.execute_sql_with_one_return::<IdRow>(sql, &[&from])
+            .map_err(|_| format!("merge tag {} error", from))?;
+        Tag::refresh_article_tags(into);
+
+        Ok(into_tag)
+    }
+
+    // keep the article.tags text in step with the links, it is what the pages show
+    fn refresh_article_tags(tag_id: Uuid) {
+        let em = db::get_db();
+        let sql = "UPDATE article a SET tags = coalesce((SELECT string_agg(t.name, ', ' ORDER BY t.name) \
+                   FROM article_tag at JOIN tag t ON t.id = at.tag_id WHERE at.article_id = a.id), '') \
+                   WHERE a.id IN (SELECT article_id FROM article_tag WHERE tag_id = $1) RETURNING a.id";
+
+        let _ = em.execute_sql_with_return::<IdRow>(sql, &[&tag_id]);
+    }
+}
+
+pub struct ArticleTags {
+    pub article_id: Uuid,
+    // the raw tags field, as typed in the editor
+    pub tags: String,
+}
+
+impl ArticleTags {
+    /// Replace the tags of the article, returns the normalized tags text.
+    pub fn update(&self) -> Result<String, String> {
+        let names = parse_tags(&self.tags);
+        let em = db::get_db();
+
+        let sql = "DELETE FROM article_tag WHERE article_id = $1 RETURNING tag_id AS id";
+        em.execute_sql_with_return::<IdRow>(sql, &[&self.article_id])
+            .map_err(|_| format!("update tags of article {} error", self.article_id))?;
+
+        for name in names.iter() {
+            let sql = "INSERT INTO tag (name) VALUES ($1) \
+                       ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name RETURNING id";
+            let tag_id = em.execute_sql_with_one_return::<IdRow>(sql, &[name])
+                .map_err(|_| format!("create tag {} error", name))?
+                .id;
+
+            let sql = "INSERT INTO article_tag (article_id, tag_id) VALUES ($1, $2) \
+                       ON CONFLICT DO NOTHING RETURNING tag_id AS id";
+            em.execute_sql_with_return::<IdRow>(sql, &[&self.article_id, &tag_id])
+                .map_err(|_| format!("tag article {} error", self.article_id))?;
+        }
+
+        let tags = names.join(", ");
+        let sql = "UPDATE article SET tags = $2 WHERE id = $1 RETURNING id";
+        em.execute_sql_with_one_return::<IdRow>(sql, &[&self.article_id, &tags])
+            .map_err(|_| format!("update tags of article {} error", self.article_id))?;
+
+        Ok(tags)
+    }
+}
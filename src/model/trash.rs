@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames};
+
+use crate::db;
+
+pub const STATUS_NORMAL: i16 = 0;
+pub const STATUS_DELETED: i16 = 1;
+// held back by moderation, until reviewed
+pub const STATUS_HIDDEN: i16 = 2;
+// articles only, not published yet
+pub const STATUS_DRAFT: i16 = 3;
+
+// what the pages need to know about a removed or restored article
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct ArticleRef {
+    pub id: Uuid,
+    pub section_id: Uuid,
+    pub tags: String,
+    pub author_id: Uuid,
+    pub status: i16,
+}
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct CommentRef {
+    pub id: Uuid,
+    pub article_id: Uuid,
+    // of its article
+    pub section_id: Uuid,
+    pub author_id: Uuid,
+    pub status: i16,
+}
+
+const ARTICLE_REF_COLUMNS: &str = "id, section_id, tags, author_id, status";
+const COMMENT_REF_COLUMNS: &str = "id, article_id, \
+                                   (SELECT a.section_id FROM article a WHERE a.id = comment.article_id) AS section_id, \
+                                   author_id, status";
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct TrashItem {
+    // "article" or "comment"
+    pub kind: String,
+    pub id: Uuid,
+    pub article_id: Uuid,
+    pub title: String,
+    pub author_nickname: String,
+    pub deleted_by_nickname: String,
+    pub deleted_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct IdStatusRow {
+    id: Uuid,
+    status: i16,
+}
+
+pub struct Trash;
+
+impl Trash {
+    pub fn list() -> Vec<TrashItem> {
+        let em = db::get_db();
+        let sql = "SELECT 'article' AS kind, a.id, a.id AS article_id, a.title, u.nickname AS author_nickname, \
+                   d.nickname AS deleted_by_nickname, a.deleted_time \
+                   FROM article a JOIN ruser u ON u.id = a.author_id JOIN ruser d ON d.id = a.deleted_by \
+                   WHERE a.status = $1 \
+                   UNION ALL \
+                   SELECT 'comment' AS kind, c.id, c.article_id, left(c.raw_content, 80) AS title, u.nickname AS author_nickname, \
+                   d.nickname AS deleted_by_nickname, c.deleted_time \
+                   FROM comment c JOIN ruser u ON u.id = c.author_id JOIN ruser d ON d.id = c.deleted_by \
+                   WHERE c.status = $1 \
+                   ORDER BY deleted_time DESC";
+
+        em.execute_sql_with_return::<TrashItem>(sql, &[&STATUS_DELETED])
+            .unwrap_or_default()
+    }
+
+    /// Status of the comments in the article which are not normal.
+    pub fn invisible_comments_in_article(article_id: Uuid) -> HashMap<Uuid, i16> {
+        let em = db::get_db();
+        let sql = "SELECT id, status FROM comment WHERE article_id = $1 AND status <> $2";
+
+        em.execute_sql_with_return::<IdStatusRow>(sql, &[&article_id, &STATUS_NORMAL])
+            .unwrap_or_default()
+            .into_iter()
+            .map(|r| (r.id, r.status))
+            .collect()
+    }
+
+    pub fn article_is_visible(id: Uuid) -> bool {
+        ArticleRef::get_by_id(id).map(|a| a.status == STATUS_NORMAL).unwrap_or(false)
+    }
+}
+
+impl ArticleRef {
+    pub fn get_by_id(id: Uuid) -> Result<ArticleRef, String> {
+        let em = db::get_db();
+        let sql = format!("SELECT {} FROM article WHERE id = $1", ARTICLE_REF_COLUMNS);
+
+        em.execute_sql_with_one_return::<ArticleRef>(&sql, &[&id])
+            .map_err(|_| format!("no this article: {}", id))
+    }
+
+    pub fn restore(id: Uuid) -> Result<ArticleRef, String> {
+        let em = db::get_db();
+        let sql = format!("UPDATE article SET status = $2, deleted_by = NULL, deleted_time = NULL WHERE id = $1 \
+                           RETURNING {}", ARTICLE_REF_COLUMNS);
+
+        em.execute_sql_with_one_return::<ArticleRef>(&sql, &[&id, &STATUS_NORMAL])
+            .map_err(|_| format!("restore article {} error", id))
+    }
+
+    // hide or show, deleted articles and drafts are left alone
+    pub fn set_visibility(id: Uuid, status: i16) -> Result<ArticleRef, String> {
+        let em = db::get_db();
+        let sql = format!("UPDATE article SET status = $2 WHERE id = $1 AND status IN ($3, $4) \
+                           RETURNING {}", ARTICLE_REF_COLUMNS);
+
+        em.execute_sql_with_one_return::<ArticleRef>(&sql, &[&id, &status, &STATUS_NORMAL, &STATUS_HIDDEN])
+            .map_err(|_| format!("update article {} status error", id))
+    }
+
+    /// Remove the article and everything hanging on it, for good, in one
+    /// statement so a failure leaves nothing half purged.
+    pub fn purge(id: Uuid) -> Result<ArticleRef, String> {
+        let em = db::get_db();
+        let sql = format!("WITH purged_comment AS (DELETE FROM comment WHERE article_id = $1 RETURNING id), \
+                           purged_vote AS (DELETE FROM vote WHERE (target_kind = 'article' AND target_id = $1) \
+                           OR (target_kind = 'comment' AND target_id IN (SELECT id FROM purged_comment)) RETURNING target_id), \
+                           purged_weight AS (DELETE FROM articleweight WHERE article_id = $1 RETURNING article_id) \
+                           DELETE FROM article WHERE id = $1 RETURNING {}", ARTICLE_REF_COLUMNS);
+
+        em.execute_sql_with_one_return::<ArticleRef>(&sql, &[&id])
+            .map_err(|_| format!("purge article {} error", id))
+    }
+}
+
+impl CommentRef {
+    pub fn get_by_id(id: Uuid) -> Result<CommentRef, String> {
+        let em = db::get_db();
+        let sql = format!("SELECT {} FROM comment WHERE id = $1", COMMENT_REF_COLUMNS);
+
+        em.execute_sql_with_one_return::<CommentRef>(&sql, &[&id])
+            .map_err(|_| format!("no this comment: {}", id))
+    }
+
+    pub fn restore(id: Uuid) -> Result<CommentRef, String> {
+        let em = db::get_db();
+        let sql = format!("UPDATE comment SET status = $2, deleted_by = NULL, deleted_time = NULL WHERE id = $1 \
+                           RETURNING {}", COMMENT_REF_COLUMNS);
+
+        em.execute_sql_with_one_return::<CommentRef>(&sql, &[&id, &STATUS_NORMAL])
+            .map_err(|_| format!("restore comment {} error", id))
+    }
+
+    pub fn set_visibility(id: Uuid, status: i16) -> Result<CommentRef, String> {
+        let em = db::get_db();
+        let sql = format!("UPDATE comment SET status = $2 WHERE id = $1 AND status <> $3 \
+                           RETURNING {}", COMMENT_REF_COLUMNS);
+
+        em.execute_sql_with_one_return::<CommentRef>(&sql, &[&id, &status, &STATUS_DELETED])
+            .map_err(|_| format!("update comment {} status error", id))
+    }
+
+    pub fn purge(id: Uuid) -> Result<CommentRef, String> {
+        let em = db::get_db();
+        let sql = format!("WITH purged_vote AS (DELETE FROM vote WHERE target_kind = 'comment' AND target_id = $1 RETURNING target_id) \
+                           DELETE FROM comment WHERE id = $1 RETURNING {}", COMMENT_REF_COLUMNS);
+
+        em.execute_sql_with_one_return::<CommentRef>(&sql, &[&id])
+            .map_err(|_| format!("purge comment {} error", id))
+    }
+}
+
+pub struct SoftDeleteArticle {
+    pub id: Uuid,
+    pub deleted_by: Uuid,
+}
+
+impl SoftDeleteArticle {
+    pub fn update(&self) -> Result<ArticleRef, String> {
+        let em = db::get_db();
+        let sql = format!("UPDATE article SET status = $3, deleted_by = $2, deleted_time = now() WHERE id = $1 \
+                           RETURNING {}", ARTICLE_REF_COLUMNS);
+
+        em.execute_sql_with_one_return::<ArticleRef>(&sql, &[&self.id, &self.deleted_by, &STATUS_DELETED])
+            .map_err(|_| format!("delete article {} error", self.id))
+    }
+}
+
+pub struct SoftDeleteComment {
+    pub id: Uuid,
+    pub deleted_by: Uuid,
+}
+
+impl SoftDeleteComment {
+    pub fn update(&self) -> Result<CommentRef, String> {
+        let em = db::get_db();
+        let sql = format!("UPDATE comment SET status = $3, deleted_by = $2, deleted_time = now() WHERE id = $1 \
+                           RETURNING {}", COMMENT_REF_COLUMNS);
+
+        em.execute_sql_with_one_return::<CommentRef>(&sql, &[&self.id, &self.deleted_by, &STATUS_DELETED])
+            .map_err(|_| format!("delete comment {} error", self.id))
+    }
+}
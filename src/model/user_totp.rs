@@ -0,0 +1,92 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames, ToTableName};
+
+use crate::db;
+use crate::util::make_pwd_encode;
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames, ToTableName)]
+pub struct UserTotp {
+    pub user_id: Uuid,
+    pub secret: String,
+    pub enabled: bool,
+    // hashed one time codes, separated by ','
+    pub recovery_codes: String,
+    pub created_time: DateTime<Utc>,
+}
+
+impl UserTotp {
+    pub fn get_by_user_id(user_id: Uuid) -> Result<UserTotp, String> {
+        let em = db::get_db();
+        let sql = "SELECT * FROM user_totp WHERE user_id = $1";
+
+        em.execute_sql_with_one_return::<UserTotp>(sql, &[&user_id])
+            .map_err(|_| format!("no totp of user: {}", user_id))
+    }
+
+    pub fn is_enabled_for(user_id: Uuid) -> bool {
+        match UserTotp::get_by_user_id(user_id) {
+            Ok(totp) => totp.enabled,
+            Err(_) => false
+        }
+    }
+
+    /// Use up one recovery code, return true when it matched.
+    ///
+    /// The check and the removal are one statement: of two logins racing
+    /// with the same code, the second finds it gone once the first commits.
+    pub fn consume_recovery_code(&self, code: &str, salt: &str) -> bool {
+        let hashed = make_pwd_encode(code.trim(), salt);
+        let em = db::get_db();
+        let sql = "UPDATE user_totp \
+                   SET recovery_codes = array_to_string(array_remove(string_to_array(recovery_codes, ','), $2), ',') \
+                   WHERE user_id = $1 AND $2 = ANY(string_to_array(recovery_codes, ',')) \
+                   RETURNING *";
+
+        // no row back, the code was not there (any more)
+        em.execute_sql_with_one_return::<UserTotp>(sql, &[&self.user_id, &hashed])
+            .is_ok()
+    }
+
+    pub fn delete_by_user_id(user_id: Uuid) -> Result<UserTotp, String> {
+        let em = db::get_db();
+        let sql = "DELETE FROM user_totp WHERE user_id = $1 RETURNING *";
+
+        em.execute_sql_with_one_return::<UserTotp>(sql, &[&user_id])
+            .map_err(|_| format!("delete totp of user {} error", user_id))
+    }
+}
+
+/// Start (or restart) an enrollment, the secret stays disabled until confirmed.
+pub struct UserTotpCreate {
+    pub user_id: Uuid,
+    pub secret: String,
+}
+
+impl UserTotpCreate {
+    pub fn insert(&self) -> Result<UserTotp, String> {
+        let em = db::get_db();
+        let sql = "INSERT INTO user_totp (user_id, secret, enabled, recovery_codes) VALUES ($1, $2, false, '') \
+                   ON CONFLICT (user_id) DO UPDATE SET secret = $2, enabled = false, recovery_codes = '', created_time = now() \
+                   RETURNING *";
+
+        em.execute_sql_with_one_return::<UserTotp>(sql, &[&self.user_id, &self.secret])
+            .map_err(|_| "user totp create error.".to_string())
+    }
+}
+
+pub struct UserTotpEnable {
+    pub user_id: Uuid,
+    // already hashed
+    pub recovery_codes: Vec<String>,
+}
+
+impl UserTotpEnable {
+    pub fn update(&self) -> Result<UserTotp, String> {
+        let em = db::get_db();
+        let sql = "UPDATE user_totp SET enabled = true, recovery_codes = $2 WHERE user_id = $1 RETURNING *";
+
+        em.execute_sql_with_one_return::<UserTotp>(sql, &[&self.user_id, &self.recovery_codes.join(",")])
+            .map_err(|_| "user totp enable error.".to_string())
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames};
+
+use crate::db;
+use crate::model::trash::STATUS_NORMAL;
+use crate::model::qa::qa_columns;
+
+pub const VOTE_UP: i16 = 1;
+pub const VOTE_DOWN: i16 = -1;
+// casting it takes the vote back
+pub const VOTE_NONE: i16 = 0;
+
+// the votes of one user, to highlight the arrows
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct MyVote {
+    pub target_id: Uuid,
+    pub value: i16,
+}
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct ScoreRow {
+    pub id: Uuid,
+    pub score: i32,
+}
+
+// one line of the "top" listing of a section
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames)]
+pub struct TopArticle {
+    pub id: Uuid,
+    pub title: String,
+    pub tags: String,
+    pub author_id: Uuid,
+    pub author_name: String,
+    pub created_time: DateTime<Utc>,
+    pub score: i32,
+    pub question: bool,
+    pub solved: bool,
+}
+
+#[derive(Debug, Clone, FromDao, ToColumnNames)]
+struct CountRow {
+    count: i64,
+}
+
+pub struct Vote;
+
+impl Vote {
+    pub fn my_votes_in_article(user_id: Uuid, article_id: Uuid) -> Vec<MyVote> {
+        let em = db::get_db();
+        let sql = "SELECT v.target_id, v.value FROM vote v \
+                   WHERE v.user_id = $1 AND (v.target_id = $2 \
+                   OR v.target_id IN (SELECT id FROM comment WHERE article_id = $2))";
+
+        em.execute_sql_with_return::<MyVote>(sql, &[&user_id, &article_id])
+            .unwrap_or_default()
+    }
+
+    pub fn article_score(article_id: Uuid) -> i32 {
+        let em = db::get_db();
+        let sql = "SELECT id, score FROM article WHERE id = $1";
+
+        em.execute_sql_with_one_return::<ScoreRow>(sql, &[&article_id])
+            .map(|r| r.score)
+            .unwrap_or(0)
+    }
+
+    pub fn comment_scores_in_article(article_id: Uuid) -> HashMap<Uuid, i32> {
+        let em = db::get_db();
+        let sql = "SELECT id, score FROM comment WHERE article_id = $1";
+
+        em.execute_sql_with_return::<ScoreRow>(sql, &[&article_id])
+            .unwrap_or_default()
+            .into_iter()
+            .map(|r| (r.id, r.score))
+            .collect()
+    }
+
+    /// Articles of the section by score, created after `since`, or of all time.
+    pub fn top_in_section(section_id: Uuid, since: Option<DateTime<Utc>>, current_page: i64, napp: i64) -> Vec<TopArticle> {
+        let em = db::get_db();
+        let offset = (current_page - 1).max(0) * napp;
+        let since = since.unwrap_or_else(|| DateTime::<Utc>::from_utc(chrono::NaiveDateTime::from_timestamp(0, 0), Utc));
+        let sql = format!("SELECT a.id, a.title, a.tags, a.author_id, u.nickname AS author_name, a.created_time, a.score, {} \
+                           FROM article a JOIN ruser u ON u.id = a.author_id \
+                           WHERE a.section_id = $1 AND a.status = $2 AND a.created_time >= $3 \
+                           ORDER BY a.score DESC, a.created_time DESC LIMIT $4 OFFSET $5", qa_columns());
+
+        em.execute_sql_with_return::<TopArticle>(&sql, &[&section_id, &STATUS_NORMAL, &since, &napp, &offset])
+            .unwrap_or_default()
+    }
+
+    pub fn top_in_section_count(section_id: Uuid, since: Option<DateTime<Utc>>) -> i64 {
+        let em = db::get_db();
+        let since = since.unwrap_or_else(|| DateTime::<Utc>::from_utc(chrono::NaiveDateTime::from_timestamp(0, 0), Utc));
+        let sql = "SELECT count(*) AS count FROM article WHERE section_id = $1 AND status = $2 AND created_time >= $3";
+
+        em.execute_sql_with_one_return::<CountRow>(sql, &[&section_id, &STATUS_NORMAL, &since])
+            .map(|r| r.count)
+            .unwrap_or(0)
+    }
+}
+
+pub struct VoteCast {
+    pub user_id: Uuid,
+    // "article" or "comment"
+    pub kind: String,
+    pub target_id: Uuid,
+    // VOTE_UP, VOTE_DOWN or VOTE_NONE
+    pub value: i16,
+}
+
+impl VoteCast {
+    /// Record the vote and move the score of the target by the difference,
+    /// in one statement so the two never disagree. Returns the new score.
+    pub fn cast(&self) -> Result<i32, String> {
+        let table = match self.kind.as_str() {
+            "article" => "article",
+            "comment" => "comment",
+            _ => return Err("unknown vote target.".to_string()),
+        };
+        if ![VOTE_UP, VOTE_DOWN, VOTE_NONE].contains(&self.value) {
+            return Err("a vote is 1, -1 or 0.".to_string());
+        }
+
+        // the delete waits for a concurrent vote of the same user to commit, and
+        // then the insert hits the primary key, so a double click can't count twice
+        let em = db::get_db();
+        let sql = format!("WITH target AS (SELECT id FROM {table} WHERE id = $2 AND status = $5), \
+                           removed AS ( \
+                           DELETE FROM vote WHERE user_id = $1 AND target_id IN (SELECT id FROM target) RETURNING value), \
+                           added AS ( \
+                           INSERT INTO vote (user_id, target_kind, target_id, value) \
+                           SELECT $1, $3, id, $4 FROM target WHERE $4 <> 0 RETURNING value) \
+                           UPDATE {table} SET score = score \
+                           - coalesce((SELECT sum(value) FROM removed), 0) \
+                           + coalesce((SELECT sum(value) FROM added), 0) \
+                           WHERE id IN (SELECT id FROM target) RETURNING id, score", table = table);
+
+        em.execute_sql_with_one_return::<ScoreRow>(&sql, &[&self.user_id, &self.target_id, &self.kind, &self.value, &STATUS_NORMAL])
+            .map(|r| r.score)
+            .map_err(|_| format!("vote on {} {} error, please try again.", self.kind, self.target_id))
+    }
+}
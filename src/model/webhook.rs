@@ -0,0 +1,192 @@
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rustorm::{FromDao, ToColumnNames, ToTableName};
+
+use crate::db;
+
+pub const EVENT_ARTICLE_CREATED: &str = "article.created";
+pub const EVENT_ARTICLE_EDITED: &str = "article.edited";
+pub const EVENT_ARTICLE_DELETED: &str = "article.deleted";
+pub const EVENT_COMMENT_CREATED: &str = "comment.created";
+// sent by hand from the admin page, to every hook
+pub const EVENT_PING: &str = "ping";
+pub const ALL_EVENTS: [&str; 4] = [EVENT_ARTICLE_CREATED, EVENT_ARTICLE_EDITED, EVENT_ARTICLE_DELETED, EVENT_COMMENT_CREATED];
+
+pub const DELIVERY_PENDING: i16 = 0;
+pub const DELIVERY_OK: i16 = 1;
+pub const DELIVERY_FAILED: i16 = 2;
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames, ToTableName)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub url: String,
+    // the key of the payload signature, shared with the receiver
+    pub secret: String,
+    // comma separated
+    pub events: String,
+    pub is_active: bool,
+    pub created_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromDao, ToColumnNames, ToTableName)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub event: String,
+    pub payload: String,
+    pub status: i16,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub response_body: String,
+    pub error: String,
+    pub next_attempt_time: DateTime<Utc>,
+    pub created_time: DateTime<Utc>,
+    pub delivered_time: Option<DateTime<Utc>>,
+}
+
+impl Webhook {
+    pub fn get_by_id(id: Uuid) -> Result<Webhook, String> {
+        let em = db::get_db();
+        let sql = "SELECT * FROM webhook WHERE id = $1";
+
+        em.execute_sql_with_one_return::<Webhook>(sql, &[&id])
+            .map_err(|_| format!("no this webhook: {}", id))
+    }
+
+    pub fn list_all() -> Vec<Webhook> {
+        let em = db::get_db();
+        let sql = "SELECT * FROM webhook ORDER BY created_time";
+
+        em.execute_sql_with_return::<Webhook>(sql, &[])
+            .unwrap_or_default()
+    }
+
+    /// The active hooks listening to this event.
+    pub fn subscribed_to(event: &str) -> Vec<Webhook> {
+        Webhook::list_all()
+            .into_iter()
+            .filter(|hook| hook.is_active && hook.wants(event))
+            .collect()
+    }
+
+    pub fn wants(&self, event: &str) -> bool {
+        event == EVENT_PING || self.events.split(',').any(|e| e == event)
+    }
+
+    pub fn set_active(id: Uuid, is_active: bool) -> Result<Webhook, String> {
+        let em = db::get_db();
+        let sql = "UPDATE webhook SET is_active = $2 WHERE id = $1 RETURNING *";
+
+        em.execute_sql_with_one_return::<Webhook>(sql, &[&id, &is_active])
+            .map_err(|_| format!("no this webhook: {}", id))
+    }
+
+    // its deliveries go with it
+    pub fn delete(id: Uuid) -> Result<Webhook, String> {
+        let em = db::get_db();
+        let sql = "DELETE FROM webhook WHERE id = $1 RETURNING *";
+
+        em.execute_sql_with_one_return::<Webhook>(sql, &[&id])
+            .map_err(|_| format!("no this webhook: {}", id))
+    }
+}
+
+pub struct WebhookCreate {
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>,
+}
+
+impl WebhookCreate {
+    pub fn insert(&self) -> Result<Webhook, String> {
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            return Err("the url must start with http:// or https://".to_string());
+        }
+        if self.secret.len() < 8 {
+            return Err("the secret needs at least 8 characters.".to_string());
+        }
+        let events: Vec<&str> = self.events.iter()
+            .map(|e| e.as_str())
+            .filter(|e| ALL_EVENTS.contains(e))
+            .collect();
+        if events.is_empty() {
+            return Err("need at least one event.".to_string());
+        }
+
+        let em = db::get_db();
+        let sql = "INSERT INTO webhook (url, secret, events) VALUES ($1, $2, $3) RETURNING *";
+
+        em.execute_sql_with_one_return::<Webhook>(sql, &[&self.url, &self.secret, &events.join(",")])
+            .map_err(|_| "webhook create error.".to_string())
+    }
+}
+
+impl WebhookDelivery {
+    pub fn get_by_id(id: Uuid) -> Result<WebhookDelivery, String> {
+        let em = db::get_db();
+        let sql = "SELECT * FROM webhook_delivery WHERE id = $1";
+
+        em.execute_sql_with_one_return::<WebhookDelivery>(sql, &[&id])
+            .map_err(|_| format!("no this delivery: {}", id))
+    }
+
+    pub fn get_latest_by_webhook(webhook_id: Uuid, limit: i64) -> Vec<WebhookDelivery> {
+        let em = db::get_db();
+        let sql = "SELECT * FROM webhook_delivery WHERE webhook_id = $1 ORDER BY created_time DESC LIMIT $2";
+
+        em.execute_sql_with_return::<WebhookDelivery>(sql, &[&webhook_id, &limit])
+            .unwrap_or_default()
+    }
+
+    /// Pending deliveries whose next attempt is due, oldest first.
+    /// Those of paused hooks wait until the hook is active again.
+    pub fn due(limit: i64) -> Vec<WebhookDelivery> {
+        let em = db::get_db();
+        let sql = "SELECT d.* FROM webhook_delivery d JOIN webhook w ON w.id = d.webhook_id \
+                   WHERE w.is_active AND d.status = $1 AND d.next_attempt_time <= now() \
+                   ORDER BY d.next_attempt_time LIMIT $2";
+
+        em.execute_sql_with_return::<WebhookDelivery>(sql, &[&DELIVERY_PENDING, &limit])
+            .unwrap_or_default()
+    }
+}
+
+pub struct WebhookDeliveryCreate {
+    pub webhook_id: Uuid,
+    pub event: String,
+    pub payload: String,
+}
+
+impl WebhookDeliveryCreate {
+    pub fn insert(&self) -> Result<WebhookDelivery, String> {
+        let em = db::get_db();
+        let sql = "INSERT INTO webhook_delivery (webhook_id, event, payload) VALUES ($1, $2, $3) RETURNING *";
+
+        em.execute_sql_with_one_return::<WebhookDelivery>(sql, &[&self.webhook_id, &self.event, &self.payload])
+            .map_err(|_| "webhook delivery create error.".to_string())
+    }
+}
+
+/// The outcome of one attempt to deliver.
+pub struct WebhookAttempt {
+    pub id: Uuid,
+    pub response_status: Option<i32>,
+    pub response_body: String,
+    pub error: String,
+    // DELIVERY_OK, DELIVERY_FAILED, or DELIVERY_PENDING to try again later
+    pub status: i16,
+    pub retry_in_secs: i64,
+}
+
+impl WebhookAttempt {
+    pub fn update(&self) -> Result<WebhookDelivery, String> {
+        let em = db::get_db();
+        let sql = "UPDATE webhook_delivery SET attempts = attempts + 1, status = $2, response_status = $3, \
+                   response_body = $4, error = $5, next_attempt_time = now() + $6 * interval '1 second', \
+                   delivered_time = CASE WHEN $2 = $7 THEN now() ELSE delivered_time END \
+                   WHERE id = $1 RETURNING *";
+
+        em.execute_sql_with_one_return::<WebhookDelivery>(sql, &[&self.id, &self.status, &self.response_status, &self.response_body, &self.error, &(self.retry_in_secs as f64), &DELIVERY_OK])
+            .map_err(|_| format!("update delivery {} error", self.id))
+    }
+}
@@ -0,0 +1,699 @@
+// the page cache, where every entry is tagged with what it shows
+//
+// A cached page carries tags like "article:<id>" or "section:<id>". A write
+// describes what it changed as a `Change`, and every entry carrying one of
+// the tags of that change is dropped. Which pages are cached, with which
+// tags, and which tags each change drops, are all declared in this file.
+// Entries are kept gzipped, a hit goes out as stored to clients taking gzip.
+use std::collections::HashMap;
+use uuid::Uuid;
+use log::warn;
+use sapper::{
+    Request,
+    Response,
+    Result as SapperResult,
+    Error as SapperError
+};
+use sapper_std::*;
+
+use crate::AppUser;
+use crate::RedisClient;
+use crate::compress;
+use crate::feed::FeedFormat;
+use crate::dataservice::section::Section;
+use crate::model::tag::normalize_tag;
+use crate::model::trash::Trash;
+use crate::middleware::{cache_on, cache_switch};
+use crate::page_forum::tag_page::percent_decode;
+use crate::page_forum::article_page::requested_article_id;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CacheTag {
+    // the front page and the site feeds
+    Index,
+    Section(Uuid),
+    Article(Uuid),
+    Tag(String),
+}
+
+impl CacheTag {
+    // the redis set of the entries carrying this tag
+    fn set_key(&self) -> String {
+        match self {
+            CacheTag::Index => "page_cache:tag:index".to_owned(),
+            CacheTag::Section(id) => format!("page_cache:tag:section:{}", id),
+            CacheTag::Article(id) => format!("page_cache:tag:article:{}", id),
+            CacheTag::Tag(name) => format!("page_cache:tag:tag:{}", name),
+        }
+    }
+}
+
+// what a write changed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    // created, edited, moved, deleted, restored or hidden; `tags` are the
+    // normalized names it carries
+    Article { article_id: Uuid, section_id: Uuid, tags: Vec<String> },
+    // created, edited, deleted, restored, hidden, or accepted as the answer
+    Comment { article_id: Uuid, section_id: Uuid },
+    ArticleScore { article_id: Uuid, section_id: Uuid },
+    CommentScore { article_id: Uuid },
+    Bookmarks { article_id: Uuid },
+    Followers { section_id: Uuid },
+    // its title, description or settings
+    Section { section_id: Uuid },
+    // created, or rearranged on the index
+    SectionList,
+    // an article got or lost it, or it was renamed or merged
+    Tag { name: String },
+}
+
+impl Change {
+    // the rules: the tags of the pages showing what changed
+    pub fn tags(&self) -> Vec<CacheTag> {
+        match self {
+            // the latest articles of the index, the section listing, the
+            // article page, and the feeds of its tags
+            Change::Article { article_id, section_id, tags } => {
+                let mut cache_tags = vec![
+                    CacheTag::Index,
+                    CacheTag::Section(*section_id),
+                    CacheTag::Article(*article_id),
+                ];
+                cache_tags.extend(tags.iter().map(|name| CacheTag::Tag(name.clone())));
+                cache_tags
+            },
+            // the reply orderings of the index and the section, and the
+            // article page with its comments feed
+            Change::Comment { article_id, section_id } => {
+                vec![
+                    CacheTag::Index,
+                    CacheTag::Article(*article_id),
+                    CacheTag::Section(*section_id),
+                ]
+            },
+            // scores are in the section listings and on the article page
+            Change::ArticleScore { article_id, section_id } => {
+                vec![CacheTag::Section(*section_id), CacheTag::Article(*article_id)]
+            },
+            Change::CommentScore { article_id } | Change::Bookmarks { article_id } => {
+                vec![CacheTag::Article(*article_id)]
+            },
+            Change::Followers { section_id } => {
+                vec![CacheTag::Section(*section_id)]
+            },
+            Change::Section { section_id } => {
+                vec![CacheTag::Index, CacheTag::Section(*section_id)]
+            },
+            Change::SectionList => {
+                vec![CacheTag::Index]
+            },
+            // the tag cloud of the index, and the tag feed
+            Change::Tag { name } => {
+                vec![CacheTag::Index, CacheTag::Tag(name.clone())]
+            },
+        }
+    }
+}
+
+// one cacheable page of a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedPage {
+    pub namespace: &'static str,
+    pub key: String,
+    pub tags: Vec<CacheTag>,
+    // feeds and pages are looked up apart, they go out with their own
+    // content types
+    pub feed: Option<FeedFormat>,
+    // drafts, hidden and deleted articles are only rendered for their
+    // author or a moderator, so are never stored
+    pub visible_article: Option<Uuid>,
+}
+
+impl CachedPage {
+    fn new(namespace: &'static str, key: String, tag: CacheTag) -> CachedPage {
+        CachedPage {
+            namespace,
+            key,
+            tags: vec![tag],
+            feed: None,
+            visible_article: None,
+        }
+    }
+
+    fn feed(namespace: &'static str, id: &str, format: FeedFormat, full: bool, tag: CacheTag) -> CachedPage {
+        let key = if full {
+            format!("{}:{}:full", id, format.name())
+        }
+        else {
+            format!("{}:{}", id, format.name())
+        };
+
+        CachedPage {
+            feed: Some(format),
+            ..CachedPage::new(namespace, key, tag)
+        }
+    }
+}
+
+// the rules: which requests are cached, under which key and tags
+pub fn page_of(req: &Request) -> SapperResult<Option<CachedPage>> {
+    let (path, _) = req.uri();
+    let params = get_query_params!(req);
+    let article_id = if path == "/article" || path.starts_with("/article/") {
+        requested_article_id(req).ok()
+    }
+    else {
+        None
+    };
+
+    page_of_uri(&path, &params, article_id.as_ref().map(|id| id.as_str()), &|author_id| {
+        Section::get_by_suser(author_id).ok().map(|section| section.id)
+    })
+}
+
+// `article_id` is the id of an article page, from its query or its path,
+// `blog_of` finds the section of a blog by its author
+fn page_of_uri(
+    path: &str,
+    params: &HashMap<String, Vec<String>>,
+    article_id: Option<&str>,
+    blog_of: &dyn Fn(Uuid) -> Option<Uuid>,
+) -> SapperResult<Option<CachedPage>> {
+    let current_page = t_param_parse_default!(params, "current_page", i64, 1);
+
+    // the feeds, "/rss" and "/section/:id/atom" alike
+    if let Some(format) = FeedFormat::from_path(path) {
+        let full = t_param_default!(params, "full", "") == "1";
+        let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        let page = match segments.as_slice() {
+            [_] => {
+                Some(CachedPage::feed("index", "site", format, full, CacheTag::Index))
+            },
+            ["section", id, _] => {
+                id.parse::<Uuid>().ok()
+                    .map(|id| CachedPage::feed("section", &id.to_string(), format, full, CacheTag::Section(id)))
+            },
+            ["blog", author_id, _] => {
+                author_id.parse::<Uuid>().ok()
+                    .and_then(blog_of)
+                    .map(|id| CachedPage::feed("section", &id.to_string(), format, full, CacheTag::Section(id)))
+            },
+            ["tag", name, _] => {
+                normalize_tag(&percent_decode(name))
+                    .map(|name| CachedPage::feed("tag", &name, format, full, CacheTag::Tag(name.clone())))
+            },
+            ["article", id, _] => {
+                id.parse::<Uuid>().ok()
+                    .map(|id| CachedPage::feed("article", &id.to_string(), format, full, CacheTag::Article(id)))
+            },
+            _ => None,
+        };
+
+        return Ok(page);
+    }
+
+    let page = if path == "/" {
+        Some(CachedPage::new("index", "index".to_owned(), CacheTag::Index))
+    }
+    else if path == "/section" || path == "/blog" || path == "/blog_with_author" {
+        // only the default listing is cached
+        if t_has_param!(params, "sort") {
+            None
+        }
+        else {
+            let section_id = if path == "/blog_with_author" {
+                t_param!(params, "author_id").parse::<Uuid>().ok()
+                    .and_then(blog_of)
+            }
+            else {
+                t_param!(params, "id").parse::<Uuid>().ok()
+            };
+
+            section_id.map(|id| CachedPage::new("section", format!("{}:{}", id, current_page), CacheTag::Section(id)))
+        }
+    }
+    else if path == "/article" || path.starts_with("/article/") {
+        article_id.and_then(|id| id.parse::<Uuid>().ok())
+            .map(|id| CachedPage {
+                visible_article: Some(id),
+                ..CachedPage::new("article", format!("{}:{}", id, current_page), CacheTag::Article(id))
+            })
+    }
+    else {
+        None
+    };
+
+    Ok(page)
+}
+
+// the server the page cache lives on, shared with the rate limits
+fn connection(req: &Request) -> redis::RedisResult<redis::Connection> {
+    get_ext!(req, RedisClient).unwrap().get_connection()
+}
+
+fn entry_member(namespace: &str, key: &str) -> String {
+    format!("{}|{}", namespace, key)
+}
+
+// the redis key of the gzipped body of an entry
+fn entry_key(member: &str) -> String {
+    format!("page_cache:entry:{}", member)
+}
+
+// where the entries are kept, redis but in the tests
+trait EntryStore {
+    fn get_entry(&mut self, member: &str) -> redis::RedisResult<Option<Vec<u8>>>;
+    fn put_entry(&mut self, member: &str, body: Vec<u8>, tags: &[CacheTag]) -> redis::RedisResult<()>;
+}
+
+impl EntryStore for redis::Connection {
+    fn get_entry(&mut self, member: &str) -> redis::RedisResult<Option<Vec<u8>>> {
+        redis::cmd("GET").arg(entry_key(member)).query(self)
+    }
+
+    // the body and its tags together, an untagged entry would never be dropped
+    fn put_entry(&mut self, member: &str, body: Vec<u8>, tags: &[CacheTag]) -> redis::RedisResult<()> {
+        let mut pipe = redis::pipe();
+        pipe.atomic().cmd("SET").arg(entry_key(member)).arg(body).ignore();
+        for tag in tags {
+            pipe.cmd("SADD").arg(tag.set_key()).arg(member).ignore();
+        }
+        pipe.query(self)
+    }
+}
+
+fn lookup_entry<S: EntryStore>(store: &mut S, page: &CachedPage) -> redis::RedisResult<Option<Vec<u8>>> {
+    store.get_entry(&entry_member(page.namespace, &page.key))
+}
+
+fn store_entry<S: EntryStore>(store: &mut S, page: &CachedPage, body: &[u8]) -> redis::RedisResult<()> {
+    match compress::gzip(body) {
+        Some(body) => store.put_entry(&entry_member(page.namespace, &page.key), body, &page.tags),
+        None => Ok(()),
+    }
+}
+
+fn lookup(req: &Request, feed: bool) -> SapperResult<Option<(CachedPage, Vec<u8>)>> {
+    lookup_for(
+        cache_on(),
+        get_ext!(req, AppUser).is_some(),
+        || Ok(page_of(req)?.filter(|page| page.feed.is_some() == feed)),
+        || connection(req),
+    )
+}
+
+// the page and the store are only reached for whom the cache is on
+fn lookup_for<P, C, S>(cache_on: bool, logged_in: bool, page_of: P, connect: C) -> SapperResult<Option<(CachedPage, Vec<u8>)>>
+where
+    P: FnOnce() -> SapperResult<Option<CachedPage>>,
+    C: FnOnce() -> redis::RedisResult<S>,
+    S: EntryStore,
+{
+    if !cache_switch(cache_on, logged_in) {
+        return Ok(None);
+    }
+    let page = match page_of()? {
+        Some(page) => page,
+        None => return Ok(None),
+    };
+
+    let body = connect().and_then(|mut con| lookup_entry(&mut con, &page));
+    match body {
+        Ok(body) => Ok(body.map(|body| (page, body))),
+        Err(e) => {
+            warn!("page cache lookup error: {}", e);
+            Ok(None)
+        }
+    }
+}
+
+// for the page handlers, which add the Last-Modified of what they show;
+// the body is gzipped
+pub fn lookup_page(req: &Request) -> SapperResult<Option<Vec<u8>>> {
+    let page = lookup(req, false)?;
+
+    Ok(page.map(|(_, body)| body))
+}
+
+// for the feed handlers, the body gzipped as well
+pub fn lookup_feed(req: &Request) -> SapperResult<Option<(FeedFormat, Vec<u8>)>> {
+    let page = lookup(req, true)?;
+
+    Ok(page.and_then(|(page, body)| page.feed.map(|format| (format, body))))
+}
+
+// for the after hooks, keeps the response if the request is a cached page
+pub fn store(req: &Request, res: &Response) -> SapperResult<()> {
+    // a hit is already encoded, and stored
+    if res.headers().get_raw("Content-Encoding").is_some() {
+        return Ok(());
+    }
+    let body = match res.body() {
+        Some(body) => body,
+        None => return Ok(()),
+    };
+
+    store_for(
+        cache_on(),
+        get_ext!(req, AppUser).is_some(),
+        body,
+        || {
+            let page = page_of(req)?;
+            Ok(page.filter(|page| page.visible_article.map_or(true, Trash::article_is_visible)))
+        },
+        || connection(req),
+    )
+}
+
+fn store_for<P, C, S>(cache_on: bool, logged_in: bool, body: &[u8], page_of: P, connect: C) -> SapperResult<()>
+where
+    P: FnOnce() -> SapperResult<Option<CachedPage>>,
+    C: FnOnce() -> redis::RedisResult<S>,
+    S: EntryStore,
+{
+    if !cache_switch(cache_on, logged_in) {
+        return Ok(());
+    }
+    let page = match page_of()? {
+        Some(page) => page,
+        None => return Ok(()),
+    };
+
+    let stored = connect().and_then(|mut con| store_entry(&mut con, &page, body));
+    if let Err(e) = stored {
+        warn!("page cache store error: {}", e);
+    }
+
+    Ok(())
+}
+
+// drop every entry carrying one of the tags
+pub fn invalidate_tags(req: &Request, tags: &[CacheTag]) {
+    let mut con = match connection(req) {
+        Ok(con) => con,
+        Err(e) => {
+            warn!("page cache invalidate error: {}", e);
+            return;
+        }
+    };
+
+    for tag in tags {
+        let set_key = tag.set_key();
+        let members = redis::pipe()
+            .atomic()
+            .cmd("SMEMBERS").arg(&set_key)
+            .cmd("DEL").arg(&set_key).ignore()
+            .query::<(Vec<String>,)>(&mut con);
+
+        match members {
+            Ok((members,)) if !members.is_empty() => {
+                let keys: Vec<String> = members.iter().map(|member| entry_key(member)).collect();
+                if let Err(e) = redis::cmd("DEL").arg(keys).query::<()>(&mut con) {
+                    warn!("page cache invalidate {} error: {}", set_key, e);
+                }
+            },
+            Ok(_) => {},
+            Err(e) => {
+                warn!("page cache invalidate {} error: {}", set_key, e);
+            }
+        }
+    }
+}
+
+pub fn invalidate(req: &Request, change: Change) {
+    invalidate_tags(req, &change.tags());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u8) -> Uuid {
+        Uuid::from_bytes(&[n; 16]).unwrap()
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, Vec<String>> {
+        pairs.iter().map(|(k, v)| (k.to_string(), vec![v.to_string()])).collect()
+    }
+
+    // author 7 owns the blog section 8, nobody else has one
+    fn blog_of(author_id: Uuid) -> Option<Uuid> {
+        if author_id == id(7) { Some(id(8)) } else { None }
+    }
+
+    fn page(path: &str, pairs: &[(&str, &str)]) -> Option<CachedPage> {
+        page_of_uri(path, &params(pairs), None, &blog_of).unwrap()
+    }
+
+    fn key_and_tags(page: Option<CachedPage>) -> Option<(&'static str, String, Vec<CacheTag>, Option<FeedFormat>)> {
+        page.map(|page| (page.namespace, page.key, page.tags, page.feed))
+    }
+
+    #[test]
+    fn article_change_drops_the_listings_and_its_tags() {
+        let change = Change::Article {
+            article_id: id(1),
+            section_id: id(2),
+            tags: vec!["rust".to_owned(), "web-dev".to_owned()],
+        };
+        assert_eq!(change.tags(), vec![
+            CacheTag::Index,
+            CacheTag::Section(id(2)),
+            CacheTag::Article(id(1)),
+            CacheTag::Tag("rust".to_owned()),
+            CacheTag::Tag("web-dev".to_owned()),
+        ]);
+
+        let untagged = Change::Article { article_id: id(1), section_id: id(2), tags: vec![] };
+        assert_eq!(untagged.tags(), vec![CacheTag::Index, CacheTag::Section(id(2)), CacheTag::Article(id(1))]);
+    }
+
+    #[test]
+    fn other_changes_drop_what_shows_them() {
+        assert_eq!(Change::Comment { article_id: id(1), section_id: id(2) }.tags(),
+                   vec![CacheTag::Index, CacheTag::Article(id(1)), CacheTag::Section(id(2))]);
+        assert_eq!(Change::ArticleScore { article_id: id(1), section_id: id(2) }.tags(),
+                   vec![CacheTag::Section(id(2)), CacheTag::Article(id(1))]);
+        assert_eq!(Change::CommentScore { article_id: id(1) }.tags(), vec![CacheTag::Article(id(1))]);
+        assert_eq!(Change::Bookmarks { article_id: id(1) }.tags(), vec![CacheTag::Article(id(1))]);
+        assert_eq!(Change::Followers { section_id: id(2) }.tags(), vec![CacheTag::Section(id(2))]);
+        assert_eq!(Change::Section { section_id: id(2) }.tags(), vec![CacheTag::Index, CacheTag::Section(id(2))]);
+        assert_eq!(Change::SectionList.tags(), vec![CacheTag::Index]);
+        assert_eq!(Change::Tag { name: "rust".to_owned() }.tags(),
+                   vec![CacheTag::Index, CacheTag::Tag("rust".to_owned())]);
+    }
+
+    #[test]
+    fn index_page() {
+        assert_eq!(key_and_tags(page("/", &[])), Some(("index", "index".to_owned(), vec![CacheTag::Index], None)));
+    }
+
+    #[test]
+    fn section_and_blog_listings() {
+        let section = id(2).to_string();
+        assert_eq!(key_and_tags(page("/section", &[("id", &section)])),
+                   Some(("section", format!("{}:1", section), vec![CacheTag::Section(id(2))], None)));
+        assert_eq!(key_and_tags(page("/section", &[("id", &section), ("current_page", "3")])),
+                   Some(("section", format!("{}:3", section), vec![CacheTag::Section(id(2))], None)));
+        assert_eq!(key_and_tags(page("/blog", &[("id", &section)])),
+                   Some(("section", format!("{}:1", section), vec![CacheTag::Section(id(2))], None)));
+        assert_eq!(page("/section", &[("id", "not-a-uuid")]), None);
+    }
+
+    #[test]
+    fn sorted_listings_are_not_cached() {
+        let section = id(2).to_string();
+        for sort in ["top", "unanswered", ""].iter() {
+            assert_eq!(page("/section", &[("id", &section), ("sort", *sort)]), None);
+            assert_eq!(page("/blog", &[("id", &section), ("sort", *sort)]), None);
+            assert_eq!(page("/blog_with_author", &[("author_id", &id(7).to_string()), ("sort", *sort)]), None);
+        }
+    }
+
+    #[test]
+    fn blog_with_author_is_keyed_by_the_blog_section() {
+        assert_eq!(key_and_tags(page("/blog_with_author", &[("author_id", &id(7).to_string())])),
+                   Some(("section", format!("{}:1", id(8)), vec![CacheTag::Section(id(8))], None)));
+        // the section listing of the same blog shares the entry
+        assert_eq!(page("/blog_with_author", &[("author_id", &id(7).to_string())]),
+                   page("/blog", &[("id", &id(8).to_string())]));
+        assert_eq!(page("/blog_with_author", &[("author_id", &id(9).to_string())]), None);
+    }
+
+    #[test]
+    fn article_pages() {
+        let article = id(1).to_string();
+        let expected = CachedPage {
+            visible_article: Some(id(1)),
+            ..CachedPage::new("article", format!("{}:1", article), CacheTag::Article(id(1)))
+        };
+        // "/article?id=" and "/article/:id/:slug" are the same entry
+        assert_eq!(page_of_uri("/article", &params(&[("id", &article)]), Some(&article), &blog_of).unwrap(),
+                   Some(expected.clone()));
+        assert_eq!(page_of_uri("/article/some-id/some-slug", &params(&[]), Some(&article), &blog_of).unwrap(),
+                   Some(expected));
+
+        let second = page_of_uri("/article", &params(&[("current_page", "2")]), Some(&article), &blog_of).unwrap().unwrap();
+        assert_eq!(second.key, format!("{}:2", article));
+        assert_eq!(page_of_uri("/article", &params(&[]), Some("nope"), &blog_of).unwrap(), None);
+        assert_eq!(page_of_uri("/article", &params(&[]), None, &blog_of).unwrap(), None);
+    }
+
+    #[test]
+    fn site_feeds() {
+        for format in [FeedFormat::Rss, FeedFormat::Atom, FeedFormat::Json].iter() {
+            let path = format!("/{}", format.name());
+            assert_eq!(key_and_tags(page(&path, &[])),
+                       Some(("index", format!("site:{}", format.name()), vec![CacheTag::Index], Some(*format))));
+            assert_eq!(page(&path, &[("full", "1")]).unwrap().key, format!("site:{}:full", format.name()));
+        }
+    }
+
+    #[test]
+    fn section_blog_tag_and_article_feeds() {
+        for format in [FeedFormat::Rss, FeedFormat::Atom, FeedFormat::Json].iter() {
+            let name = format.name();
+            assert_eq!(key_and_tags(page(&format!("/section/{}/{}", id(2), name), &[])),
+                       Some(("section", format!("{}:{}", id(2), name), vec![CacheTag::Section(id(2))], Some(*format))));
+            assert_eq!(key_and_tags(page(&format!("/blog/{}/{}", id(7), name), &[])),
+                       Some(("section", format!("{}:{}", id(8), name), vec![CacheTag::Section(id(8))], Some(*format))));
+            assert_eq!(page(&format!("/blog/{}/{}", id(9), name), &[]), None);
+            assert_eq!(key_and_tags(page(&format!("/tag/Web%20Dev/{}", name), &[])),
+                       Some(("tag", format!("web-dev:{}", name), vec![CacheTag::Tag("web-dev".to_owned())], Some(*format))));
+            assert_eq!(key_and_tags(page(&format!("/article/{}/{}", id(1), name), &[("full", "1")])),
+                       Some(("article", format!("{}:{}:full", id(1), name), vec![CacheTag::Article(id(1))], Some(*format))));
+            // feeds are never held back for visibility, they are built from visible content only
+            assert_eq!(page(&format!("/article/{}/{}", id(1), name), &[]).unwrap().visible_article, None);
+            assert_eq!(page(&format!("/section/not-a-uuid/{}", name), &[]), None);
+        }
+    }
+
+    #[test]
+    fn other_paths_are_not_cached() {
+        assert_eq!(page("/latest_articles_paging", &[]), None);
+        assert_eq!(page("/p/user/notifications", &[]), None);
+        assert_eq!(page("/s/article/create", &[]), None);
+    }
+
+    // an in-memory redis, counting what was written
+    #[derive(Default)]
+    struct MemoryStore {
+        entries: HashMap<String, Vec<u8>>,
+        tags: Vec<CacheTag>,
+        puts: usize,
+    }
+
+    impl EntryStore for MemoryStore {
+        fn get_entry(&mut self, member: &str) -> redis::RedisResult<Option<Vec<u8>>> {
+            Ok(self.entries.get(member).cloned())
+        }
+
+        fn put_entry(&mut self, member: &str, body: Vec<u8>, tags: &[CacheTag]) -> redis::RedisResult<()> {
+            self.entries.insert(member.to_owned(), body);
+            self.tags.extend(tags.iter().cloned());
+            self.puts += 1;
+            Ok(())
+        }
+    }
+
+    // what `connect` hands out, as a redis::Connection is handed out
+    impl<'a> EntryStore for &'a mut MemoryStore {
+        fn get_entry(&mut self, member: &str) -> redis::RedisResult<Option<Vec<u8>>> {
+            (**self).get_entry(member)
+        }
+
+        fn put_entry(&mut self, member: &str, body: Vec<u8>, tags: &[CacheTag]) -> redis::RedisResult<()> {
+            (**self).put_entry(member, body, tags)
+        }
+    }
+
+    const EDIT_CONTROLS: &str = "/p/article/edit";
+
+    // the article page as its template draws it: the viewer's nickname in
+    // the header, and the edit links for alice, its author
+    fn render_article(viewer: Option<&str>) -> String {
+        let header = match viewer {
+            Some(nickname) => format!("<span class=\"nickname\">{}</span>", nickname),
+            None => "<a href=\"/login\">login</a>".to_owned(),
+        };
+        let controls = if viewer == Some("alice") {
+            format!("<a href=\"{}?id={}\">edit</a>", EDIT_CONTROLS, id(1))
+        }
+        else {
+            String::new()
+        };
+
+        format!("<html>{}<h1>cached article</h1>{}</html>", header, controls)
+    }
+
+    // "/article?id=" through the handler, which looks the page up first,
+    // and its after hook, which stores what was rendered; true on a hit
+    fn serve_article(store: &mut MemoryStore, viewer: Option<&str>) -> (String, bool) {
+        let article = id(1).to_string();
+        let query = params(&[("id", &article)]);
+        let page_of = || page_of_uri("/article", &query, Some(&article), &blog_of);
+        let logged_in = viewer.is_some();
+
+        let con = &mut *store;
+        if let Some((_, body)) = lookup_for(true, logged_in, page_of, move || Ok(con)).unwrap() {
+            let body = compress::gunzip(&body).unwrap();
+            return (String::from_utf8(body).unwrap(), true);
+        }
+        let body = render_article(viewer);
+        let con = &mut *store;
+        store_for(true, logged_in, body.as_bytes(), page_of, move || Ok(con)).unwrap();
+
+        (body, false)
+    }
+
+    #[test]
+    fn cache_switch_is_for_anonymous_visitors_only() {
+        assert!(cache_switch(true, false));
+        assert!(!cache_switch(true, true));
+        assert!(!cache_switch(false, false));
+        assert!(!cache_switch(false, true));
+    }
+
+    #[test]
+    fn logged_in_pages_are_never_stored_nor_served() {
+        let mut store = MemoryStore::default();
+
+        let (alice, hit) = serve_article(&mut store, Some("alice"));
+        assert!(!hit);
+        assert!(alice.contains("alice") && alice.contains(EDIT_CONTROLS));
+        assert_eq!(store.puts, 0);
+
+        let (bob, hit) = serve_article(&mut store, Some("bob"));
+        assert!(!hit);
+        assert!(bob.contains("bob"));
+        assert!(!bob.contains("alice") && !bob.contains(EDIT_CONTROLS));
+        assert_eq!(store.puts, 0);
+
+        // the first anonymous visitor fills the cache, the next one reads it
+        let (anonymous, hit) = serve_article(&mut store, None);
+        assert!(!hit);
+        assert_eq!(store.puts, 1);
+        assert_eq!(store.tags, vec![CacheTag::Article(id(1))]);
+        let (again, hit) = serve_article(&mut store, None);
+        assert!(hit);
+        assert_eq!(again, anonymous);
+
+        // with an entry there, logged in users still get their own page
+        let (bob, hit) = serve_article(&mut store, Some("bob"));
+        assert!(!hit);
+        assert!(bob.contains("bob"));
+        assert!(!bob.contains("alice") && !bob.contains(EDIT_CONTROLS));
+        let (alice, hit) = serve_article(&mut store, Some("alice"));
+        assert!(!hit);
+        assert!(alice.contains(EDIT_CONTROLS));
+        assert_eq!(store.puts, 1);
+
+        // and what was stored is the anonymous page
+        for body in store.entries.values() {
+            let body = String::from_utf8(compress::gunzip(body).unwrap()).unwrap();
+            assert_eq!(body, anonymous);
+            assert!(!body.contains("alice") && !body.contains("bob") && !body.contains(EDIT_CONTROLS));
+        }
+    }
+}
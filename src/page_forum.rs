@@ -4,5 +4,15 @@ pub mod user_page;
 pub mod section_page;
 pub mod article_page;
 pub mod comment_page;
+pub mod api_page;
+pub mod admin_page;
+pub mod report_page;
+pub mod tag_page;
+pub mod notification_page;
+pub mod vote_page;
+pub mod bookmark_page;
+pub mod follow_page;
+pub mod feed_page;
+pub mod seo_page;
 
 
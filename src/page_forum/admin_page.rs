@@ -0,0 +1,497 @@
+use sapper::{
+    Request,
+    Response,
+    Result as SapperResult,
+    Error as SapperError,
+    Module as SapperModule,
+    Router as SapperRouter};
+use sapper_std::*;
+use uuid::Uuid;
+use serde_json::json;
+
+use crate::page_cache::{self, Change};
+// introduce macros
+use sapper_std::res_html;
+use crate::{
+    AppWebContext,
+    AppUser
+};
+
+use crate::dataservice::article::Article;
+use crate::dataservice::user::Ruser;
+use crate::tantivy_index::TanAction;
+use crate::model::trash::{
+    Trash,
+    ArticleRef,
+    CommentRef,
+    STATUS_NORMAL,
+    STATUS_HIDDEN
+};
+use crate::model::report::{
+    Report,
+    BanUser,
+    REPORT_DISMISSED,
+    REPORT_RESOLVED
+};
+use crate::model::tag::{
+    Tag,
+    parse_tags
+};
+use crate::model::webhook::{
+    Webhook,
+    WebhookCreate,
+    WebhookDelivery,
+    ALL_EVENTS,
+    EVENT_PING
+};
+use crate::webhook;
+use crate::model::notification::{
+    NotificationCreate,
+    NOTIFY_MODERATION
+};
+use crate::page_forum::article_page::{
+    index_article,
+    unindex_article,
+    sync_tags
+};
+use crate::middleware::{
+    permission_need_login,
+    permission_need_be_admin,
+    permission_need_be_moderator
+};
+
+/// Tell the author that a moderator acted on their article or comment.
+/// `detail` is what happened: "hidden", "deleted" or "restored".
+pub fn notify_moderation(actor_id: Uuid, kind: &str, id: Uuid, detail: &str) {
+    let (author_id, article_id, comment_id) = if kind == "article" {
+        match ArticleRef::get_by_id(id) {
+            Ok(article_ref) => (article_ref.author_id, article_ref.id, None),
+            Err(_) => return,
+        }
+    }
+    else {
+        match CommentRef::get_by_id(id) {
+            Ok(comment_ref) => (comment_ref.author_id, comment_ref.article_id, Some(comment_ref.id)),
+            Err(_) => return,
+        }
+    };
+
+    let notification_create = NotificationCreate {
+        user_id: author_id,
+        ntype: NOTIFY_MODERATION,
+        actor_id,
+        article_id,
+        comment_id,
+        detail: detail.to_string(),
+    };
+    let _ = notification_create.insert();
+}
+
+/// Hide or show an article or comment, keeping search and page caches in step.
+pub fn apply_visibility(req: &Request, kind: &str, id: Uuid, status: i16) -> Result<(), String> {
+    if kind == "article" {
+        let article_ref = ArticleRef::set_visibility(id, status)?;
+        if status == STATUS_NORMAL {
+            // replaces the doc if it was never taken out
+            if let Ok(article) = Article::get_by_id(article_ref.id) {
+                index_article(req, &article, TanAction::Update);
+            }
+        }
+        else {
+            unindex_article(req, article_ref.id);
+        }
+        page_cache::invalidate(req, Change::Article { article_id: article_ref.id, section_id: article_ref.section_id, tags: parse_tags(&article_ref.tags) });
+    }
+    else {
+        let comment_ref = CommentRef::set_visibility(id, status)?;
+        page_cache::invalidate(req, Change::Comment { article_id: comment_ref.article_id, section_id: comment_ref.section_id });
+    }
+
+    Ok(())
+}
+
+fn target_author(kind: &str, id: Uuid) -> Result<Uuid, String> {
+    if kind == "article" {
+        ArticleRef::get_by_id(id).map(|a| a.author_id)
+    }
+    else {
+        CommentRef::get_by_id(id).map(|c| c.author_id)
+    }
+}
+
+fn target_is_hidden(kind: &str, id: Uuid) -> bool {
+    if kind == "article" {
+        ArticleRef::get_by_id(id).map(|a| a.status == STATUS_HIDDEN).unwrap_or(false)
+    }
+    else {
+        CommentRef::get_by_id(id).map(|c| c.status == STATUS_HIDDEN).unwrap_or(false)
+    }
+}
+
+pub struct AdminPage;
+
+impl AdminPage {
+
+    pub fn trash_page(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_admin(req)?;
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+
+        let items = Trash::list();
+
+        web.insert("items", &items);
+
+        res_html!("forum/admin/trash.html", web)
+    }
+
+    pub fn trash_restore(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_admin(req)?;
+        let params = get_form_params!(req);
+        let kind = t_param!(params, "kind");
+        let id = t_param_parse!(params, "id", Uuid);
+        let admin_id = get_ext!(req, AppUser).unwrap().id;
+
+        if kind == "article" {
+            match ArticleRef::restore(id) {
+                Ok(article_ref) => {
+                    if let Ok(article) = Article::get_by_id(article_ref.id) {
+                        index_article(req, &article, TanAction::Add);
+                    }
+                    page_cache::invalidate(req, Change::Article { article_id: article_ref.id, section_id: article_ref.section_id, tags: parse_tags(&article_ref.tags) });
+                    notify_moderation(admin_id, "article", article_ref.id, "restored");
+                },
+                Err(info) => {
+                    return res_500!(info);
+                }
+            }
+        }
+        else {
+            match CommentRef::restore(id) {
+                Ok(comment_ref) => {
+                    page_cache::invalidate(req, Change::Comment { article_id: comment_ref.article_id, section_id: comment_ref.section_id });
+                    notify_moderation(admin_id, "comment", comment_ref.id, "restored");
+                },
+                Err(info) => {
+                    return res_500!(info);
+                }
+            }
+        }
+
+        res_redirect!("/p/admin/trash")
+    }
+
+    pub fn trash_purge(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_admin(req)?;
+        let params = get_form_params!(req);
+        let kind = t_param!(params, "kind");
+        let id = t_param_parse!(params, "id", Uuid);
+
+        if kind == "article" {
+            match ArticleRef::purge(id) {
+                Ok(article_ref) => {
+                    // it was unindexed at delete time, make sure anyway
+                    unindex_article(req, article_ref.id);
+                    page_cache::invalidate(req, Change::Article { article_id: article_ref.id, section_id: article_ref.section_id, tags: parse_tags(&article_ref.tags) });
+                },
+                Err(info) => {
+                    return res_500!(info);
+                }
+            }
+        }
+        else {
+            match CommentRef::purge(id) {
+                Ok(comment_ref) => {
+                    page_cache::invalidate(req, Change::Comment { article_id: comment_ref.article_id, section_id: comment_ref.section_id });
+                },
+                Err(info) => {
+                    return res_500!(info);
+                }
+            }
+        }
+
+        res_redirect!("/p/admin/trash")
+    }
+
+    pub fn reports_page(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_moderator(req)?;
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+
+        let targets = Report::open_targets();
+
+        web.insert("targets", &targets);
+
+        res_html!("forum/admin/reports.html", web)
+    }
+
+    pub fn report_resolve(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_moderator(req)?;
+        let params = get_form_params!(req);
+        let kind = t_param!(params, "kind").to_owned();
+        let target_id = t_param_parse!(params, "target_id", Uuid);
+        let action = t_param!(params, "action").to_owned();
+        let moderator_id = get_ext!(req, AppUser).unwrap().id;
+
+        match action.as_str() {
+            "dismiss" => {
+                // the content is fine, bring it back if the threshold had hidden
+                // it, but not if a moderator did
+                let auto_hidden = target_is_hidden(&kind, target_id) && !Report::was_resolved(target_id);
+                if auto_hidden {
+                    if let Err(info) = apply_visibility(req, &kind, target_id, STATUS_NORMAL) {
+                        return res_500!(info);
+                    }
+                    notify_moderation(moderator_id, &kind, target_id, "restored");
+                }
+                Report::close_all_of(target_id, REPORT_DISMISSED, moderator_id);
+            },
+            "hide" => {
+                if let Err(info) = apply_visibility(req, &kind, target_id, STATUS_HIDDEN) {
+                    return res_500!(info);
+                }
+                notify_moderation(moderator_id, &kind, target_id, "hidden");
+                Report::close_all_of(target_id, REPORT_RESOLVED, moderator_id);
+            },
+            "ban" => {
+                let author_id = match target_author(&kind, target_id) {
+                    Ok(author_id) => author_id,
+                    Err(info) => return res_400!(info),
+                };
+                match Ruser::get_user_by_id(author_id) {
+                    Ok(ref author) if author.role >= 5 => return res_400!("can not ban a moderator."),
+                    Ok(_) => {},
+                    Err(_) => return res_400!("no this user."),
+                }
+                if let Err(info) = (BanUser { id: author_id }).update() {
+                    return res_500!(info);
+                }
+                let _ = apply_visibility(req, &kind, target_id, STATUS_HIDDEN);
+                notify_moderation(moderator_id, &kind, target_id, "hidden");
+                Report::close_all_of(target_id, REPORT_RESOLVED, moderator_id);
+            },
+            _ => {
+                return res_400!("unknown action.");
+            }
+        }
+
+        res_redirect!("/p/admin/reports")
+    }
+
+    pub fn tags_page(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_admin(req)?;
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+
+        let tags = Tag::all_with_counts();
+
+        web.insert("tags", &tags);
+
+        res_html!("forum/admin/tags.html", web)
+    }
+
+    // renaming onto an existing name merges the two tags
+    pub fn tag_rename(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_admin(req)?;
+        let params = get_form_params!(req);
+        let id = t_param_parse!(params, "id", Uuid);
+        let name = t_param!(params, "name");
+
+        let old_name = Tag::get_by_id(id).map(|tag| tag.name).ok();
+        match Tag::rename(id, name) {
+            Ok(tag) => {
+                // the feed moves to the new name, or into the merged tag
+                if let Some(old_name) = old_name {
+                    page_cache::invalidate(req, Change::Tag { name: old_name });
+                }
+                page_cache::invalidate(req, Change::Tag { name: tag.name });
+            },
+            Err(info) => {
+                return res_400!(info);
+            }
+        }
+
+        res_redirect!("/p/admin/tags")
+    }
+
+    pub fn tag_merge(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_admin(req)?;
+        let params = get_form_params!(req);
+        let from = t_param_parse!(params, "from", Uuid);
+        let into = t_param_parse!(params, "into", Uuid);
+
+        let from_name = Tag::get_by_id(from).map(|tag| tag.name).ok();
+        match Tag::merge(from, into) {
+            Ok(tag) => {
+                if let Some(from_name) = from_name {
+                    page_cache::invalidate(req, Change::Tag { name: from_name });
+                }
+                page_cache::invalidate(req, Change::Tag { name: tag.name });
+            },
+            Err(info) => {
+                return res_400!(info);
+            }
+        }
+
+        res_redirect!("/p/admin/tags")
+    }
+
+    // link the articles written before tags were parsed
+    pub fn tags_rebuild(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_admin(req)?;
+
+        for mut article in Article::get_all_articles() {
+            sync_tags(req, &mut article);
+        }
+
+        res_redirect!("/p/admin/tags")
+    }
+
+    pub fn webhooks_page(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_admin(req)?;
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+
+        let webhooks = Webhook::list_all();
+
+        web.insert("webhooks", &webhooks);
+        web.insert("all_events", &ALL_EVENTS);
+
+        res_html!("forum/admin/webhooks.html", web)
+    }
+
+    pub fn webhook_create(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_admin(req)?;
+        let params = get_form_params!(req);
+        let url = t_param!(params, "url").trim().to_owned();
+        let secret = t_param!(params, "secret").trim().to_owned();
+        let events: Vec<String> = if t_has_param!(params, "events") {
+            t_arr_param!(params, "events").iter().map(|s| s.to_string()).collect()
+        }
+        else {
+            Vec::new()
+        };
+
+        let webhook_create = WebhookCreate {
+            url,
+            secret,
+            events,
+        };
+
+        match webhook_create.insert() {
+            Ok(_) => {
+                res_redirect!("/p/admin/webhooks")
+            },
+            Err(info) => {
+                res_400!(info)
+            }
+        }
+    }
+
+    // pause or resume, deliveries queued meanwhile wait
+    pub fn webhook_toggle(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_admin(req)?;
+        let params = get_form_params!(req);
+        let id = t_param_parse!(params, "id", Uuid);
+
+        let hook = match Webhook::get_by_id(id) {
+            Ok(hook) => hook,
+            Err(info) => return res_400!(info),
+        };
+        if let Err(info) = Webhook::set_active(id, !hook.is_active) {
+            return res_500!(info);
+        }
+
+        res_redirect!("/p/admin/webhooks")
+    }
+
+    pub fn webhook_delete(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_admin(req)?;
+        let params = get_form_params!(req);
+        let id = t_param_parse!(params, "id", Uuid);
+
+        if let Err(info) = Webhook::delete(id) {
+            return res_400!(info);
+        }
+
+        res_redirect!("/p/admin/webhooks")
+    }
+
+    // the delivery log of one hook
+    pub fn webhook_page(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_admin(req)?;
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let params = get_query_params!(req);
+        let id = t_param_parse!(params, "id", Uuid);
+
+        let hook = match Webhook::get_by_id(id) {
+            Ok(hook) => hook,
+            Err(info) => return res_400!(info),
+        };
+        let deliveries = WebhookDelivery::get_latest_by_webhook(id, 100);
+
+        web.insert("webhook", &hook);
+        web.insert("deliveries", &deliveries);
+
+        res_html!("forum/admin/webhook.html", web)
+    }
+
+    pub fn webhook_ping(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_admin(req)?;
+        let params = get_form_params!(req);
+        let id = t_param_parse!(params, "id", Uuid);
+
+        let hook = match Webhook::get_by_id(id) {
+            Ok(hook) => hook,
+            Err(info) => return res_400!(info),
+        };
+        webhook::dispatch_to(req, &hook, EVENT_PING, json!({ "webhook_id": hook.id }));
+
+        res_redirect!(format!("/p/admin/webhook?id={}", id))
+    }
+
+    pub fn webhook_redeliver(req: &mut Request) -> SapperResult<Response> {
+        permission_need_be_admin(req)?;
+        let params = get_form_params!(req);
+        let delivery_id = t_param_parse!(params, "delivery_id", Uuid);
+
+        let delivery = match WebhookDelivery::get_by_id(delivery_id) {
+            Ok(delivery) => delivery,
+            Err(info) => return res_400!(info),
+        };
+        if let Err(info) = webhook::redeliver(req, &delivery) {
+            return res_500!(info);
+        }
+
+        res_redirect!(format!("/p/admin/webhook?id={}", delivery.webhook_id))
+    }
+
+}
+
+
+impl SapperModule for AdminPage {
+    fn before(&self, req: &mut Request) -> SapperResult<()> {
+        permission_need_login(req)?;
+
+        Ok(())
+    }
+
+    fn router(&self, router: &mut SapperRouter) -> SapperResult<()> {
+        router.get("/p/admin/trash", Self::trash_page);
+        router.post("/s/admin/trash/restore", Self::trash_restore);
+        router.post("/s/admin/trash/purge", Self::trash_purge);
+
+        router.get("/p/admin/reports", Self::reports_page);
+        router.post("/s/admin/report/resolve", Self::report_resolve);
+
+        router.get("/p/admin/tags", Self::tags_page);
+        router.post("/s/admin/tag/rename", Self::tag_rename);
+        router.post("/s/admin/tag/merge", Self::tag_merge);
+        router.post("/s/admin/tags/rebuild", Self::tags_rebuild);
+
+        router.get("/p/admin/webhooks", Self::webhooks_page);
+        router.get("/p/admin/webhook", Self::webhook_page);
+        router.post("/s/admin/webhook/create", Self::webhook_create);
+        router.post("/s/admin/webhook/toggle", Self::webhook_toggle);
+        router.post("/s/admin/webhook/delete", Self::webhook_delete);
+        router.post("/s/admin/webhook/ping", Self::webhook_ping);
+        router.post("/s/admin/webhook/redeliver", Self::webhook_redeliver);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,699 @@
+use sapper::{
+    status,
+    header::ContentType,
+    Request,
+    Response,
+    Result as SapperResult,
+    Error as SapperError,
+    Module as SapperModule,
+    Router as SapperRouter};
+use sapper_std::*;
+use uuid::Uuid;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+
+use crate::page_cache::{self, Change};
+use crate::envconfig;
+use crate::conditional::header_value;
+use crate::mention::render_with_mentions;
+use crate::rate_limit::{self, RateAction};
+use crate::webhook::{self, article_data, comment_data};
+use crate::model::webhook::{
+    EVENT_ARTICLE_CREATED,
+    EVENT_ARTICLE_EDITED,
+    EVENT_ARTICLE_DELETED,
+    EVENT_COMMENT_CREATED
+};
+use crate::{TanIndexTx, TanQueryRx, AppUser, AppApiToken};
+use crate::tantivy_index::{DocFromIndexOuter, TanAction};
+
+use crate::dataservice::article::{
+    Article,
+    ArticleCreate,
+    ArticleEdit
+};
+use crate::dataservice::comment::{
+    Comment,
+    CommentCreate,
+    CommentEdit
+};
+use crate::dataservice::section::Section;
+use crate::dataservice::user::Ruser;
+use crate::model::api_token::{SCOPE_POST, SCOPE_MODERATE};
+use crate::model::comment_thread::CommentThread;
+use crate::model::listing::Listing;
+use crate::model::trash::{Trash, CommentRef, SoftDeleteArticle, SoftDeleteComment};
+use crate::model::article_revision::ArticleRevision;
+use crate::model::tag::{Tag, normalize_tag, parse_tags};
+use crate::model::mention::MentionedUser;
+use crate::page_forum::article_page::{
+    index_article,
+    unindex_article,
+    record_revision,
+    sync_tags
+};
+use crate::page_forum::comment_page::{
+    touch_article_updated_time,
+    prepare_comments,
+    link_reply,
+    notify_comment,
+    notify_mentions
+};
+use crate::page_forum::admin_page::notify_moderation;
+
+#[derive(Debug, Serialize)]
+pub struct Pagination {
+    pub total_item: i64,
+    pub total_page: i64,
+    pub current_page: i64,
+}
+
+impl Pagination {
+    pub fn new(total_item: i64, per_page: i64, current_page: i64) -> Pagination {
+        Pagination {
+            total_item,
+            total_page: (total_item - 1) / per_page + 1,
+            current_page,
+        }
+    }
+}
+
+pub fn res_json_status<T: Serialize>(status: status::StatusCode, body: &T) -> SapperResult<Response> {
+    let mut response = Response::new();
+    response.set_status(status);
+    response.headers_mut().set(ContentType::json());
+    response.write_body(serde_json::to_string(body).unwrap());
+
+    Ok(response)
+}
+
+pub fn res_json_404(info: &str) -> SapperResult<Response> {
+    res_json_status(status::NotFound, &json!({ "error": info }))
+}
+
+pub fn res_json_400(info: &str) -> SapperResult<Response> {
+    res_json_status(status::BadRequest, &json!({ "error": info }))
+}
+
+fn json_body<T: DeserializeOwned>(req: &Request) -> Result<T, String> {
+    match req.body() {
+        Some(body) => serde_json::from_slice(body).map_err(|e| format!("invalid json body: {}", e)),
+        None => Err("need json body".to_string())
+    }
+}
+
+fn path_uuid(req: &Request) -> Option<Uuid> {
+    let path_params = get_path_params!(req);
+    t_param!(path_params, "id").parse::<Uuid>().ok()
+}
+
+// a session cookie carries every scope, a token only the ones it was created with;
+// the browser sends the cookie along with a cross-site form too, but such a
+// form can't set a json content type, so cookie writes must come with one
+fn check_scope(req: &Request, scope: &str) -> Result<(), SapperResult<Response>> {
+    if get_ext!(req, AppUser).is_none() {
+        return Err(res_json_status(status::Unauthorized, &json!({ "error": "need login or api token" })));
+    }
+    match get_ext!(req, AppApiToken) {
+        Some(token) if !token.has_scope(scope) => {
+            Err(res_json_status(status::Forbidden, &json!({ "error": format!("api token lacks scope: {}", scope) })))
+        },
+        Some(_) => Ok(()),
+        None if !is_json_content_type(header_value(req, "Content-Type").as_ref().map(|v| v.as_str())) => {
+            Err(res_json_status(status::UnsupportedMediaType, &json!({ "error": "need Content-Type: application/json" })))
+        },
+        None => Ok(())
+    }
+}
+
+fn is_json_content_type(content_type: Option<&str>) -> bool {
+    match content_type.and_then(|v| v.split(';').next()) {
+        Some(mime) => mime.trim().eq_ignore_ascii_case("application/json"),
+        None => false
+    }
+}
+
+fn check_rate(req: &Request, action: RateAction) -> Result<(), SapperResult<Response>> {
+    match rate_limit::check(req, action) {
+        Some(wait) => {
+            Err(res_json_status(status::TooManyRequests, &json!({
+                "error": format!("slow down, try again in {} seconds", wait),
+                "retry_after": wait,
+            })))
+        },
+        None => Ok(())
+    }
+}
+
+// authors manage their own content, moderators (with moderate scope) everybody's
+fn can_manage(req: &Request, author_id: Uuid) -> bool {
+    match get_ext!(req, AppUser) {
+        Some(user) => {
+            if user.id == author_id {
+                return true;
+            }
+            user.role >= 5 && check_scope(req, SCOPE_MODERATE).is_ok()
+        },
+        None => false
+    }
+}
+
+// anyone may post into a forum section, a blog takes only its owner's
+// articles, as the html handlers only ever post into the user's own blog
+fn section_to_post_into(req: &Request, section_id: Uuid) -> Result<Section, SapperResult<Response>> {
+    let section = match Section::get_by_id(section_id) {
+        Ok(section) => section,
+        Err(_) => return Err(res_json_404("no this section")),
+    };
+    let user = get_ext!(req, AppUser).unwrap();
+    if section.stype != 0 && section.suser != Some(user.id) {
+        return Err(res_json_status(status::Forbidden, &json!({ "error": "not your blog." })));
+    }
+
+    Ok(section)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArticleInput {
+    pub section_id: Option<Uuid>,
+    pub title: Option<String>,
+    pub tags: Option<String>,
+    pub extlink: Option<String>,
+    pub raw_content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommentInput {
+    pub article_id: Option<Uuid>,
+    pub parent_id: Option<Uuid>,
+    pub raw_content: String,
+}
+
+pub struct ApiPage;
+
+impl ApiPage {
+
+    pub fn sections(req: &mut Request) -> SapperResult<Response> {
+        let sections = Section::forum_sections();
+
+        res_json!(json!({ "sections": sections }))
+    }
+
+    pub fn section_articles(req: &mut Request) -> SapperResult<Response> {
+        let path_params = get_path_params!(req);
+        let section_id = match t_param!(path_params, "id").parse::<Uuid>() {
+            Ok(id) => id,
+            Err(_) => return res_json_404("no this section"),
+        };
+        let params = get_query_params!(req);
+        let current_page = t_param_parse_default!(params, "current_page", i64, 1);
+
+        let section = match Section::get_by_id(section_id) {
+            Ok(section) => section,
+            Err(_) => return res_json_404("no this section"),
+        };
+
+        let napp = envconfig::get_int_item("NUMBER_ARTICLE_PER_PAGE");
+        let total_item = Listing::section_count(section.id);
+        let articles = Listing::in_section(section.id, current_page, napp);
+
+        res_json!(json!({
+            "section": section,
+            "articles": articles,
+            "pagination": Pagination::new(total_item as i64, napp, current_page),
+        }))
+    }
+
+    pub fn article_detail(req: &mut Request) -> SapperResult<Response> {
+        let path_params = get_path_params!(req);
+        let id = match t_param!(path_params, "id").parse::<Uuid>() {
+            Ok(id) => id,
+            Err(_) => return res_json_404("no this article"),
+        };
+        let params = get_query_params!(req);
+        let current_page = t_param_parse_default!(params, "current_page", i64, 1);
+
+        let article = match Article::get_by_id(id) {
+            Ok(article) => article,
+            Err(_) => return res_json_404("no this article"),
+        };
+        if !Trash::article_is_visible(id) {
+            return res_json_404("no this article");
+        }
+        let author = match Ruser::get_user_by_id(article.author_id) {
+            Ok(author) => author,
+            Err(_) => return res_json_404("no this author"),
+        };
+
+        let ncpp = envconfig::get_int_item("NUMBER_COMMENT_PER_PAGE");
+        let total_item = CommentThread::count_in_article(id);
+        let comments = CommentThread::page_in_article(id, current_page, ncpp);
+        let comments = prepare_comments(id, &comments);
+        let viewtimes = Article::get_viewtimes(article.id);
+
+        // only public fields of the author
+        res_json!(json!({
+            "article": article,
+            "author": {
+                "id": author.id,
+                "nickname": author.nickname,
+                "avatar": author.avatar,
+            },
+            "viewtimes": viewtimes,
+            "comments": comments,
+            "pagination": Pagination::new(total_item as i64, ncpp, current_page),
+        }))
+    }
+
+    pub fn latest_articles(req: &mut Request) -> SapperResult<Response> {
+        let params = get_query_params!(req);
+        let current_page = t_param_parse_default!(params, "current_page", i64, 1);
+
+        let napp = envconfig::get_int_item("BIG_NUMBER_ARTICLE_PER_PAGE");
+        let total_item = Listing::forum_count();
+        let articles = Listing::latest_forum(current_page, napp);
+
+        res_json!(json!({
+            "articles": articles,
+            "pagination": Pagination::new(total_item as i64, napp, current_page),
+        }))
+    }
+
+    pub fn latest_reply_articles(req: &mut Request) -> SapperResult<Response> {
+        let params = get_query_params!(req);
+        let current_page = t_param_parse_default!(params, "current_page", i64, 1);
+
+        let napp = envconfig::get_int_item("BIG_NUMBER_ARTICLE_PER_PAGE");
+        let total_item = Listing::forum_count();
+        let articles = Listing::latest_reply(current_page, napp);
+
+        res_json!(json!({
+            "articles": articles,
+            "pagination": Pagination::new(total_item as i64, napp, current_page),
+        }))
+    }
+
+    pub fn latest_blog_articles(req: &mut Request) -> SapperResult<Response> {
+        let params = get_query_params!(req);
+        let current_page = t_param_parse_default!(params, "current_page", i64, 1);
+
+        let napp = envconfig::get_int_item("BIG_NUMBER_ARTICLE_PER_PAGE");
+        let total_item = Listing::blog_count();
+        let articles = Listing::latest_blog(current_page, napp);
+
+        res_json!(json!({
+            "articles": articles,
+            "pagination": Pagination::new(total_item as i64, napp, current_page),
+        }))
+    }
+
+    pub fn search(req: &mut Request) -> SapperResult<Response> {
+        let params = get_query_params!(req);
+        let q = t_param_default!(params, "q", "");
+        if q == "" {
+            return res_json_400("need q parameter");
+        }
+
+        let tan_index = get_ext!(req, TanIndexTx).unwrap();
+        tan_index.send((TanAction::Query, q.to_string(), None)).unwrap();
+
+        let tan_query = get_ext!(req, TanQueryRx).unwrap();
+        let mut docs: Vec<DocFromIndexOuter> = tan_query.recv().unwrap();
+        docs.retain(|doc| Trash::article_is_visible(doc.article_id));
+
+        res_json!(json!({
+            "q": q,
+            "docs": docs,
+        }))
+    }
+
+    // tag autocomplete of the editor
+    pub fn tags(req: &mut Request) -> SapperResult<Response> {
+        let params = get_query_params!(req);
+        let q = t_param_default!(params, "q", "");
+        let limit = t_param_parse_default!(params, "limit", i64, 10).min(50).max(1);
+
+        let tags = match normalize_tag(q) {
+            Some(prefix) => Tag::suggest(&prefix, limit),
+            None => Tag::popular(limit),
+        };
+
+        res_json!(json!({ "tags": tags }))
+    }
+
+    // @mention autocomplete of the editor
+    pub fn users(req: &mut Request) -> SapperResult<Response> {
+        let params = get_query_params!(req);
+        let q = t_param_default!(params, "q", "").trim().to_owned();
+        let limit = t_param_parse_default!(params, "limit", i64, 10).min(50).max(1);
+
+        let users = if q == "" {
+            Vec::new()
+        }
+        else {
+            MentionedUser::search_by_prefix(&q, limit)
+        };
+
+        res_json!(json!({ "users": users }))
+    }
+
+    pub fn article_create(req: &mut Request) -> SapperResult<Response> {
+        if let Err(res) = check_scope(req, SCOPE_POST) {
+            return res;
+        }
+        if let Err(res) = check_rate(req, RateAction::Article) {
+            return res;
+        }
+        let input: ArticleInput = match json_body(req) {
+            Ok(input) => input,
+            Err(info) => return res_json_400(&info),
+        };
+        let (section_id, title, raw_content) = match (input.section_id, input.title, input.raw_content) {
+            (Some(section_id), Some(title), Some(raw_content)) => (section_id, title, raw_content),
+            _ => return res_json_400("need section_id, title and raw_content"),
+        };
+        let section = match section_to_post_into(req, section_id) {
+            Ok(section) => section,
+            Err(res) => return res,
+        };
+
+        let user = get_ext!(req, AppUser).unwrap();
+        let (content, mentioned) = render_with_mentions(&raw_content);
+        let article_create = ArticleCreate {
+            title,
+            tags: input.tags.unwrap_or_default(),
+            extlink: input.extlink.unwrap_or_default(),
+            section_id,
+            author_id: user.id,
+            content,
+            raw_content,
+            // a blog article, in the owner's blog
+            stype: if section.stype == 0 { 0 } else { 1 },
+            status: 0,
+        };
+
+        match article_create.insert() {
+            Ok(mut article) => {
+                sync_tags(req, &mut article);
+                notify_mentions(req, user.id, article.id, None, &mentioned);
+                index_article(req, &article, TanAction::Add);
+                webhook::dispatch(req, EVENT_ARTICLE_CREATED, article_data(&article));
+                page_cache::invalidate(req, Change::Article { article_id: article.id, section_id: article.section_id, tags: parse_tags(&article.tags) });
+
+                res_json_status(status::Created, &article)
+            },
+            Err(_) => {
+                res_json_status(status::InternalServerError, &json!({ "error": "article create error." }))
+            }
+        }
+    }
+
+    pub fn article_edit(req: &mut Request) -> SapperResult<Response> {
+        if let Err(res) = check_scope(req, SCOPE_POST) {
+            return res;
+        }
+        if let Err(res) = check_rate(req, RateAction::Edit) {
+            return res;
+        }
+        let id = match path_uuid(req) {
+            Some(id) => id,
+            None => return res_json_404("no this article"),
+        };
+        let article = match Article::get_by_id(id) {
+            Ok(article) => article,
+            Err(_) => return res_json_404("no this article"),
+        };
+        if !Trash::article_is_visible(id) {
+            return res_json_404("no this article");
+        }
+        if !can_manage(req, article.author_id) {
+            return res_json_status(status::Forbidden, &json!({ "error": "no permission." }));
+        }
+        let input: ArticleInput = match json_body(req) {
+            Ok(input) => input,
+            Err(info) => return res_json_400(&info),
+        };
+        // only forum articles move, and only between forum sections
+        if let Some(section_id) = input.section_id.filter(|section_id| *section_id != article.section_id) {
+            if article.stype != 0 {
+                return res_json_400("a blog article stays in its blog");
+            }
+            match Section::get_by_id(section_id) {
+                Ok(ref section) if section.stype == 0 => {},
+                Ok(_) => return res_json_400("can not move an article into a blog"),
+                Err(_) => return res_json_404("no this section"),
+            }
+        }
+
+        // fields not given keep their current value
+        let raw_content = input.raw_content.unwrap_or(article.raw_content);
+        let (content, mentioned) = render_with_mentions(&raw_content);
+        let article_edit = ArticleEdit {
+            id,
+            section_id: input.section_id.unwrap_or(article.section_id),
+            title: input.title.unwrap_or(article.title),
+            tags: input.tags.unwrap_or(article.tags),
+            extlink: input.extlink.unwrap_or(article.extlink),
+            content,
+            raw_content,
+        };
+        let user = get_ext!(req, AppUser).unwrap();
+
+        ArticleRevision::ensure_baseline(id);
+        match article_edit.update() {
+            Ok(mut edited) => {
+                sync_tags(req, &mut edited);
+                notify_mentions(req, user.id, edited.id, None, &mentioned);
+                record_revision(&edited, user.id);
+                index_article(req, &edited, TanAction::Update);
+                webhook::dispatch(req, EVENT_ARTICLE_EDITED, article_data(&edited));
+                page_cache::invalidate(req, Change::Article { article_id: edited.id, section_id: edited.section_id, tags: parse_tags(&edited.tags) });
+                if edited.section_id != article.section_id {
+                    page_cache::invalidate(req, Change::Article { article_id: edited.id, section_id: article.section_id, tags: parse_tags(&edited.tags) });
+                }
+
+                res_json!(edited)
+            },
+            Err(_) => {
+                res_json_status(status::InternalServerError, &json!({ "error": "article edit error." }))
+            }
+        }
+    }
+
+    pub fn article_delete(req: &mut Request) -> SapperResult<Response> {
+        if let Err(res) = check_scope(req, SCOPE_POST) {
+            return res;
+        }
+        let id = match path_uuid(req) {
+            Some(id) => id,
+            None => return res_json_404("no this article"),
+        };
+        let article = match Article::get_by_id(id) {
+            Ok(article) => article,
+            Err(_) => return res_json_404("no this article"),
+        };
+        if !Trash::article_is_visible(id) {
+            return res_json_404("no this article");
+        }
+        if !can_manage(req, article.author_id) {
+            return res_json_status(status::Forbidden, &json!({ "error": "no permission." }));
+        }
+
+        let user = get_ext!(req, AppUser).unwrap();
+        let soft_delete = SoftDeleteArticle {
+            id,
+            deleted_by: user.id,
+        };
+
+        match soft_delete.update() {
+            Ok(article) => {
+                unindex_article(req, article.id);
+                page_cache::invalidate(req, Change::Article { article_id: article.id, section_id: article.section_id, tags: parse_tags(&article.tags) });
+                notify_moderation(user.id, "article", article.id, "deleted");
+                webhook::dispatch(req, EVENT_ARTICLE_DELETED, json!({ "id": article.id, "section_id": article.section_id }));
+
+                res_json!(json!({ "deleted": article.id }))
+            },
+            Err(_) => {
+                res_json_status(status::InternalServerError, &json!({ "error": "article delete error." }))
+            }
+        }
+    }
+
+    pub fn comment_create(req: &mut Request) -> SapperResult<Response> {
+        if let Err(res) = check_scope(req, SCOPE_POST) {
+            return res;
+        }
+        if let Err(res) = check_rate(req, RateAction::Comment) {
+            return res;
+        }
+        let input: CommentInput = match json_body(req) {
+            Ok(input) => input,
+            Err(info) => return res_json_400(&info),
+        };
+        let article_id = match input.article_id {
+            Some(article_id) => article_id,
+            None => return res_json_400("need article_id"),
+        };
+        let section_id = match Article::get_by_id(article_id) {
+            Ok(article) if Trash::article_is_visible(article_id) => article.section_id,
+            _ => return res_json_404("no this article"),
+        };
+
+        let user = get_ext!(req, AppUser).unwrap();
+        let (content, mentioned) = render_with_mentions(&input.raw_content);
+        let comment_create = CommentCreate {
+            article_id,
+            author_id: user.id,
+            content,
+            raw_content: input.raw_content,
+            status: 0
+        };
+
+        match comment_create.insert() {
+            Ok(comment) => {
+                touch_article_updated_time(comment.article_id);
+                let replied_to = input.parent_id.and_then(|parent_id| link_reply(req, &comment, parent_id));
+                notify_comment(req, &comment, replied_to);
+                notify_mentions(req, user.id, comment.article_id, Some(comment.id), &mentioned);
+                webhook::dispatch(req, EVENT_COMMENT_CREATED, comment_data(&comment));
+                page_cache::invalidate(req, Change::Comment { article_id: comment.article_id, section_id });
+
+                res_json_status(status::Created, &comment)
+            },
+            Err(_) => {
+                res_json_status(status::InternalServerError, &json!({ "error": "comment create error." }))
+            }
+        }
+    }
+
+    pub fn comment_edit(req: &mut Request) -> SapperResult<Response> {
+        if let Err(res) = check_scope(req, SCOPE_POST) {
+            return res;
+        }
+        if let Err(res) = check_rate(req, RateAction::Edit) {
+            return res;
+        }
+        let id = match path_uuid(req) {
+            Some(id) => id,
+            None => return res_json_404("no this comment"),
+        };
+        let comment = match Comment::get_by_id(id) {
+            Ok(comment) => comment,
+            Err(_) => return res_json_404("no this comment"),
+        };
+        if !can_manage(req, comment.author_id) {
+            return res_json_status(status::Forbidden, &json!({ "error": "no permission." }));
+        }
+        let input: CommentInput = match json_body(req) {
+            Ok(input) => input,
+            Err(info) => return res_json_400(&info),
+        };
+
+        let (content, mentioned) = render_with_mentions(&input.raw_content);
+        let comment_edit = CommentEdit {
+            id,
+            content,
+            raw_content: input.raw_content,
+        };
+
+        match comment_edit.update() {
+            Ok(edited) => {
+                if let Ok(comment_ref) = CommentRef::get_by_id(id) {
+                    page_cache::invalidate(req, Change::Comment { article_id: comment_ref.article_id, section_id: comment_ref.section_id });
+                }
+                let user = get_ext!(req, AppUser).unwrap();
+                notify_mentions(req, user.id, comment.article_id, Some(comment.id), &mentioned);
+
+                res_json!(edited)
+            },
+            Err(_) => {
+                res_json_status(status::InternalServerError, &json!({ "error": "comment edit error." }))
+            }
+        }
+    }
+
+    pub fn comment_delete(req: &mut Request) -> SapperResult<Response> {
+        if let Err(res) = check_scope(req, SCOPE_POST) {
+            return res;
+        }
+        let id = match path_uuid(req) {
+            Some(id) => id,
+            None => return res_json_404("no this comment"),
+        };
+        let comment = match Comment::get_by_id(id) {
+            Ok(comment) => comment,
+            Err(_) => return res_json_404("no this comment"),
+        };
+        if !can_manage(req, comment.author_id) {
+            return res_json_status(status::Forbidden, &json!({ "error": "no permission." }));
+        }
+
+        let user = get_ext!(req, AppUser).unwrap();
+        let soft_delete = SoftDeleteComment {
+            id,
+            deleted_by: user.id,
+        };
+
+        match soft_delete.update() {
+            Ok(comment_ref) => {
+                page_cache::invalidate(req, Change::Comment { article_id: comment_ref.article_id, section_id: comment_ref.section_id });
+                notify_moderation(user.id, "comment", id, "deleted");
+
+                res_json!(json!({ "deleted": id }))
+            },
+            Err(_) => {
+                res_json_status(status::InternalServerError, &json!({ "error": "comment delete error." }))
+            }
+        }
+    }
+
+}
+
+
+impl SapperModule for ApiPage {
+    fn before(&self, req: &mut Request) -> SapperResult<()> {
+        Ok(())
+    }
+
+    fn router(&self, router: &mut SapperRouter) -> SapperResult<()> {
+        router.get("/api/v1/sections", Self::sections);
+        router.get("/api/v1/section/:id/articles", Self::section_articles);
+        router.get("/api/v1/article/:id", Self::article_detail);
+        router.get("/api/v1/latest_articles", Self::latest_articles);
+        router.get("/api/v1/latest_reply_articles", Self::latest_reply_articles);
+        router.get("/api/v1/latest_blog_articles", Self::latest_blog_articles);
+        router.get("/api/v1/search", Self::search);
+        router.get("/api/v1/tags", Self::tags);
+        router.get("/api/v1/users", Self::users);
+
+        // write api, needs a session or a token with the post scope
+        router.post("/api/v1/article", Self::article_create);
+        router.post("/api/v1/article/:id/edit", Self::article_edit);
+        router.post("/api/v1/article/:id/delete", Self::article_delete);
+        router.post("/api/v1/comment", Self::comment_create);
+        router.post("/api/v1/comment/:id/edit", Self::comment_edit);
+        router.post("/api/v1/comment/:id/delete", Self::comment_delete);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_json_content_types_pass() {
+        assert!(is_json_content_type(Some("application/json")));
+        assert!(is_json_content_type(Some("application/json; charset=utf-8")));
+        assert!(is_json_content_type(Some("Application/JSON")));
+
+        // what a cross-site form can send
+        assert!(!is_json_content_type(Some("text/plain")));
+        assert!(!is_json_content_type(Some("application/x-www-form-urlencoded")));
+        assert!(!is_json_content_type(Some("multipart/form-data; boundary=x")));
+        assert!(!is_json_content_type(None));
+    }
+}
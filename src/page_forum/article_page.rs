@@ -8,9 +8,9 @@ use sapper::{
     Router as SapperRouter};
 use sapper_std::*;
 use uuid::Uuid;
+use serde_json::json;
 
 use crate::db;
-use crate::cache;
 use crate::envconfig;
 // introduce macros
 use sapper_std::res_html;
@@ -29,11 +29,60 @@ use crate::dataservice::article::{
 use crate::dataservice::section::Section;
 use crate::dataservice::user::Ruser;
 
-use crate::util::markdown_render;
+use crate::mention::render_with_mentions;
+use crate::rate_limit::{self, RateAction};
+use crate::webhook::{self, article_data};
+use crate::model::webhook::{
+    EVENT_ARTICLE_CREATED,
+    EVENT_ARTICLE_EDITED,
+    EVENT_ARTICLE_DELETED
+};
+use crate::text_diff::line_diff;
+use crate::seo::{
+    article_path,
+    absolute_url,
+    description_of
+};
+use crate::page_forum::comment_page::{
+    prepare_comments,
+    notify_mentions
+};
+use crate::page_forum::admin_page::notify_moderation;
+use crate::page_cache::{self, Change};
+use crate::conditional::{
+    res_cached_html,
+    set_last_modified
+};
+use crate::model::comment_thread::CommentThread;
+use crate::model::trash::{
+    Trash,
+    SoftDeleteArticle
+};
+use crate::model::draft::{
+    Draft,
+    DraftSave,
+    PublishDraft
+};
+use crate::model::article_revision::{
+    ArticleRevision,
+    ArticleRevisionCreate
+};
+use crate::model::tag::{
+    Tag,
+    ArticleTags,
+    parse_tags
+};
+use crate::model::vote::Vote;
+use crate::model::bookmark::Bookmark;
+use crate::model::activity::Activity;
+use crate::model::qa::{
+    Qa,
+    AcceptAnswer
+};
 use crate::middleware::{
     permission_need_login,
     permission_need_be_admin,
-    check_cache_switch
+    is_moderator
 };
 
 struct CommentPaginator {
@@ -48,6 +97,91 @@ use crate::tantivy_index::{
 
 };
 
+// send the article to the tantivy worker
+pub fn index_article(req: &Request, article: &Article, action: TanAction) {
+    let ttv_index = get_ext!(req, TanIndexTx).unwrap();
+    let doc2index = Doc2Index {
+        article_id: article.id.to_string(),
+        created_time: article.created_time.timestamp().to_string(),
+        title: article.title.clone(),
+        content: article.raw_content.clone()
+    };
+    ttv_index.send((action, "".to_string(), Some(doc2index))).unwrap();
+}
+
+pub fn unindex_article(req: &Request, article_id: Uuid) {
+    let ttv_index = get_ext!(req, TanIndexTx).unwrap();
+    ttv_index.send((TanAction::Delete, article_id.to_string(), None)).unwrap();
+}
+
+// the id from the query of "/article?id=", or from the path of "/article/:id/:slug"
+pub fn requested_article_id(req: &Request) -> SapperResult<String> {
+    let (path, _) = req.uri();
+    if path == "/article" {
+        let params = get_query_params!(req);
+        Ok(t_param!(params, "id").to_owned())
+    }
+    else {
+        let params = get_path_params!(req);
+        Ok(t_param!(params, "id").to_owned())
+    }
+}
+
+// keep a copy of what an edit wrote, for the history page
+pub fn record_revision(article: &Article, editor_id: Uuid) {
+    let revision_create = ArticleRevisionCreate {
+        article_id: article.id,
+        editor_id,
+        title: article.title.clone(),
+        tags: article.tags.clone(),
+        raw_content: article.raw_content.clone(),
+    };
+    let _ = revision_create.insert();
+}
+
+// link the article to its parsed tags, and keep the normalized text
+pub fn sync_tags(req: &Request, article: &mut Article) {
+    let mut changed: Vec<String> = Tag::get_by_article(article.id).into_iter().map(|tag| tag.name).collect();
+    let article_tags = ArticleTags {
+        article_id: article.id,
+        tags: article.tags.clone(),
+    };
+    if let Ok(tags) = article_tags.update() {
+        article.tags = tags;
+    }
+
+    // the tags it left and the tags it got
+    changed.extend(Tag::get_by_article(article.id).into_iter().map(|tag| tag.name));
+    changed.sort();
+    changed.dedup();
+    for name in changed {
+        page_cache::invalidate(req, Change::Tag { name });
+    }
+}
+
+// save the final text into the draft and make it a normal article
+fn publish_draft(req: &Request, draft_save: DraftSave) -> Result<Article, String> {
+    let draft = draft_save.save()?;
+    let draft = (PublishDraft { id: draft.id, author_id: draft.author_id }).update()?;
+
+    let mut article = Article::get_by_id(draft.id)?;
+    sync_tags(req, &mut article);
+
+    Ok(article)
+}
+
+// ?draft_id= on the create pages picks up where the editor left off
+fn insert_draft(req: &Request, web: &mut WebContext, draft_id: Uuid) -> Result<(), String> {
+    if draft_id == Uuid::default() {
+        return Ok(());
+    }
+    let user = get_ext!(req, AppUser).unwrap();
+    let draft = Draft::get_by_id(draft_id, user.id)?;
+    web.insert("draft", &draft);
+
+    Ok(())
+}
+
 pub struct ArticlePage;
 
 impl ArticlePage {
@@ -57,12 +191,15 @@ impl ArticlePage {
         let params = get_query_params!(req);
 
         let section_id = t_param_parse_default!(params, "section_id", Uuid, Uuid::default());
+        let draft_id = t_param_parse_default!(params, "draft_id", Uuid, Uuid::default());
         let sections = Section::forum_sections();
 
+        if let Err(info) = insert_draft(req, &mut web, draft_id) {
+            return res_400!(info);
+        }
         web.insert("section_id", &section_id);
         web.insert("sections", &sections);
 
-
         res_html!("forum/new_article.html", web)
     }
 
@@ -103,18 +240,18 @@ impl ArticlePage {
         res_html!("forum/delete_article.html", web)
     }
 
-    pub fn article_path_page(req: &mut Request) -> SapperResult<Response> {
-        let params = get_path_params!(req);
-        let id = t_param_parse!(params, "id", Uuid);
-
-        res_redirect!(format!("/article?id={}", id))
-    }
-
+    // served at "/article?id=", "/article/:id" and the canonical "/article/:id/:slug"
     pub fn article_detail_page(req: &mut Request) -> SapperResult<Response> {
+        let id = match requested_article_id(req)?.parse::<Uuid>() {
+            Ok(id) => id,
+            Err(_) => return res_400!("no this artile"),
+        };
+        let last_modified = Activity::of_article(id);
+        if let Some(cache_content) = page_cache::lookup_page(req)? {
+            return res_cached_html(cache_content, last_modified);
+        }
         let mut web = get_ext_owned!(req, AppWebContext).unwrap();
-
         let params = get_query_params!(req);
-        let id = t_param_parse!(params, "id", Uuid);
         let current_page = t_param_parse_default!(params, "current_page", i64, 1);
         let wx = t_param_parse_default!(params, "wx", i64, 0);
 
@@ -124,6 +261,12 @@ impl ArticlePage {
         }
         let article = article_r.unwrap();
 
+        // deleted and hidden articles stay visible to moderators only, until reviewed
+        let can_moderate = is_moderator(req);
+        if !Trash::article_is_visible(article.id) && !can_moderate {
+            return res_400!(format!("no this artile: {}", id));
+        }
+
         let author_r = Ruser::get_user_by_id(article.author_id);
         if author_r.is_err() {
             return res_400!(format!("no this author: {}", article.author_id));
@@ -151,12 +294,23 @@ impl ArticlePage {
 
         let ncpp = envconfig::get_int_item("NUMBER_COMMENT_PER_PAGE");
         // retrieve comments belongs to this article, and calculate its paginator
-        let total_item = Article::get_comments_count_belong_to_this(id);
+        let total_item = CommentThread::count_in_article(id);
         let total_page = ((total_item -1) / ncpp) as i64 + 1;
-        let comments = Article::get_comments_paging_belong_to_this(id, current_page);
+        let comments = CommentThread::page_in_article(id, current_page, ncpp);
+        let comments = prepare_comments(id, &comments);
 
         let viewtimes = Article::get_viewtimes(article.id);
         Article::increase_viewtimes(article.id);
+        let last_edit = ArticleRevision::last_edit_of(article.id);
+        let tags = Tag::get_by_article(article.id);
+        let score = Vote::article_score(article.id);
+        let bookmark_count = Bookmark::count_of(article.id);
+        // in a Q&A section, the author or a moderator picks the answer
+        let is_question = article.stype == 0 && Qa::is_qa_section(article.section_id);
+        if is_question {
+            web.insert("accepted_answer", &Qa::accepted_answer_of(article.id));
+            web.insert("can_accept", &(is_author || can_moderate));
+        }
 
         web.insert("article", &article);
         web.insert("author", &author);
@@ -167,12 +321,31 @@ impl ArticlePage {
         web.insert("is_author", &is_author);
         web.insert("is_admin", &is_admin);
         web.insert("viewtimes", &viewtimes);
+        web.insert("last_edit", &last_edit);
+        web.insert("tags", &tags);
+        web.insert("score", &score);
+        web.insert("bookmark_count", &bookmark_count);
+        web.insert("is_question", &is_question);
         web.insert("wx", &wx);
 
-        res_html!("forum/article.html", web)
+        // every way to this page points crawlers at one url
+        let mut canonical_path = article_path(article.id, &article.title);
+        if current_page > 1 {
+            canonical_path = format!("{}?current_page={}", canonical_path, current_page);
+        }
+        web.insert("canonical_url", &absolute_url(&canonical_path));
+        web.insert("meta_description", &description_of(&article.raw_content));
+
+        res_html!("forum/article.html", web).map(|mut response| {
+            set_last_modified(&mut response, last_modified);
+            response
+        })
     }
 
     pub fn article_create(req: &mut Request) -> SapperResult<Response> {
+        if let Some(wait) = rate_limit::check(req, RateAction::Article) {
+            return rate_limit::slow_down_page(req, wait);
+        }
         let params = get_form_params!(req);
         let section_id = t_param_parse_default!(params, "section_id", Uuid, Uuid::default());
         let title = t_param!(params, "title").to_owned();
@@ -180,11 +353,40 @@ impl ArticlePage {
         let extlink = t_param!(params, "extlink").to_owned();
         let raw_content = t_param!(params, "raw_content");
         let stype = t_param_parse_default!(params, "stype", i32, 0);
+        let draft_id = t_param_parse_default!(params, "draft_id", Uuid, Uuid::default());
 
-        let content = markdown_render(raw_content);
+        let (content, mentioned) = render_with_mentions(raw_content);
         let user = get_ext!(req, AppUser).unwrap();
 
         let raw_content = raw_content.to_owned();
+        if draft_id != Uuid::default() {
+            let draft_save = DraftSave {
+                id: Some(draft_id),
+                title,
+                tags,
+                extlink,
+                section_id,
+                author_id: user.id,
+                raw_content,
+                content,
+                stype,
+            };
+
+            return match publish_draft(req, draft_save) {
+                Ok(article) => {
+                    page_cache::invalidate(req, Change::Article { article_id: article.id, section_id: article.section_id, tags: parse_tags(&article.tags) });
+                    index_article(req, &article, TanAction::Add);
+                    notify_mentions(req, user.id, article.id, None, &mentioned);
+                    webhook::dispatch(req, EVENT_ARTICLE_CREATED, article_data(&article));
+
+                    res_redirect!(format!("/article?id={}", article.id))
+                },
+                Err(info) => {
+                    res_500!(info)
+                }
+            };
+        }
+
         let article_create = ArticleCreate {
             title,
             tags,
@@ -198,16 +400,13 @@ impl ArticlePage {
         };
 
         match article_create.insert() {
-            Ok(article) => {
+            Ok(mut article) => {
+                sync_tags(req, &mut article);
+                page_cache::invalidate(req, Change::Article { article_id: article.id, section_id: article.section_id, tags: parse_tags(&article.tags) });
+                notify_mentions(req, user.id, article.id, None, &mentioned);
                 // add to tantivy index
-                let ttv_index = get_ext!(req, TanIndexTx).unwrap();
-                let doc2index = Doc2Index {
-                   article_id: article.id.to_string(),
-                   created_time: article.created_time.timestamp().to_string(),
-                   title: article.title,
-                   content: article.raw_content
-                };
-                ttv_index.send((TanAction::Add, "".to_string(), Some(doc2index))).unwrap();
+                index_article(req, &article, TanAction::Add);
+                webhook::dispatch(req, EVENT_ARTICLE_CREATED, article_data(&article));
 
                 res_redirect!(format!("/article?id={}", article.id))
             },
@@ -218,6 +417,9 @@ impl ArticlePage {
      }
 
     pub fn article_edit(req: &mut Request) -> SapperResult<Response> {
+        if let Some(wait) = rate_limit::check(req, RateAction::Edit) {
+            return rate_limit::slow_down_page(req, wait);
+        }
         let params = get_form_params!(req);
         let id = t_param_parse!(params, "id", Uuid);
         let section_id = t_param_parse!(params, "section_id", Uuid);
@@ -225,8 +427,9 @@ impl ArticlePage {
         let tags = t_param!(params, "tags").to_owned();
         let extlink = t_param!(params, "extlink").to_owned();
         let raw_content = t_param!(params, "raw_content");
+        let user = get_ext!(req, AppUser).unwrap();
 
-        let content = markdown_render(raw_content);
+        let (content, mentioned) = render_with_mentions(raw_content);
         let raw_content = raw_content.to_owned();
 
         let article_edit = ArticleEdit {
@@ -239,16 +442,22 @@ impl ArticlePage {
             content,
         };
 
+        // an edit can move it to another section
+        let old_section_id = Article::get_by_id(id).map(|article| article.section_id).ok();
+        ArticleRevision::ensure_baseline(id);
         match article_edit.update() {
-            Ok(article) => {
-                let ttv_index = get_ext!(req, TanIndexTx).unwrap();
-                let doc2index = Doc2Index {
-                   article_id: article.id.to_string(),
-                   created_time: article.created_time.timestamp().to_string(),
-                   title: article.title,
-                   content: article.raw_content
-                };
-                ttv_index.send((TanAction::Update, "".to_string(), Some(doc2index))).unwrap();
+            Ok(mut article) => {
+                sync_tags(req, &mut article);
+                page_cache::invalidate(req, Change::Article { article_id: article.id, section_id: article.section_id, tags: parse_tags(&article.tags) });
+                if let Some(old_section_id) = old_section_id.filter(|old| *old != article.section_id) {
+                    page_cache::invalidate(req, Change::Article { article_id: article.id, section_id: old_section_id, tags: parse_tags(&article.tags) });
+                }
+                notify_mentions(req, user.id, article.id, None, &mentioned);
+                record_revision(&article, user.id);
+                if Trash::article_is_visible(article.id) {
+                    index_article(req, &article, TanAction::Update);
+                    webhook::dispatch(req, EVENT_ARTICLE_EDITED, article_data(&article));
+                }
 
                 res_redirect!(format!("/article?id={}", article.id))
             },
@@ -262,11 +471,19 @@ impl ArticlePage {
         let params = get_form_params!(req);
         let article_id = t_param_parse!(params, "article_id", Uuid);
         let section_id = t_param_parse!(params, "section_id", Uuid);
+        let user = get_ext!(req, AppUser).unwrap();
+
+        let soft_delete = SoftDeleteArticle {
+            id: article_id,
+            deleted_by: user.id,
+        };
 
-        match Article::delete_by_id(article_id) {
+        match soft_delete.update() {
             Ok(article) => {
-                let ttv_index = get_ext!(req, TanIndexTx).unwrap();
-                ttv_index.send((TanAction::Delete, article.id.to_string(), None)).unwrap();
+                page_cache::invalidate(req, Change::Article { article_id: article.id, section_id: article.section_id, tags: parse_tags(&article.tags) });
+                unindex_article(req, article.id);
+                notify_moderation(user.id, "article", article.id, "deleted");
+                webhook::dispatch(req, EVENT_ARTICLE_DELETED, json!({ "id": article.id, "section_id": article.section_id }));
                 res_redirect!(format!("/section?id={}", section_id))
             },
             Err(_) => {
@@ -289,10 +506,15 @@ impl ArticlePage {
     pub fn blog_article_create_page(req: &mut Request) -> SapperResult<Response> {
         let mut web = get_ext_owned!(req, AppWebContext).unwrap();
         let params = get_query_params!(req);
+        let draft_id = t_param_parse_default!(params, "draft_id", Uuid, Uuid::default());
 
         let is_in_blog = true;
         web.insert("is_in_blog", &is_in_blog);
 
+        if let Err(info) = insert_draft(req, &mut web, draft_id) {
+            return res_400!(info);
+        }
+
         res_html!("forum/new_article.html", web)
     }
 
@@ -317,17 +539,47 @@ impl ArticlePage {
     }
 
     pub fn blog_article_create(req: &mut Request) -> SapperResult<Response> {
+        if let Some(wait) = rate_limit::check(req, RateAction::Article) {
+            return rate_limit::slow_down_page(req, wait);
+        }
         let params = get_form_params!(req);
 
         let title = t_param!(params, "title").to_owned();
         let tags = t_param!(params, "tags").to_owned();
         let raw_content = t_param!(params, "raw_content");
         let stype = t_param_parse_default!(params, "stype", i32, 1);
+        let draft_id = t_param_parse_default!(params, "draft_id", Uuid, Uuid::default());
         let user = get_ext!(req, AppUser).unwrap();
         let section_id = Section::get_by_suser(user.id).unwrap().id;
 
-        let content = markdown_render(raw_content);
+        let (content, mentioned) = render_with_mentions(raw_content);
         let raw_content = raw_content.to_owned();
+        if draft_id != Uuid::default() {
+            let draft_save = DraftSave {
+                id: Some(draft_id),
+                title,
+                tags,
+                extlink: "".to_string(),
+                section_id,
+                author_id: user.id,
+                raw_content,
+                content,
+                stype,
+            };
+
+            return match publish_draft(req, draft_save) {
+                Ok(article) => {
+                    page_cache::invalidate(req, Change::Article { article_id: article.id, section_id: article.section_id, tags: parse_tags(&article.tags) });
+                    notify_mentions(req, user.id, article.id, None, &mentioned);
+                    webhook::dispatch(req, EVENT_ARTICLE_CREATED, article_data(&article));
+                    res_redirect!(format!("/article?id={}", article.id))
+                },
+                Err(info) => {
+                    res_500!(info)
+                }
+            };
+        }
+
         let article_create = ArticleCreate {
             title,
             tags,
@@ -341,7 +593,11 @@ impl ArticlePage {
         };
 
         match article_create.insert() {
-            Ok(article) => {
+            Ok(mut article) => {
+                sync_tags(req, &mut article);
+                page_cache::invalidate(req, Change::Article { article_id: article.id, section_id: article.section_id, tags: parse_tags(&article.tags) });
+                notify_mentions(req, user.id, article.id, None, &mentioned);
+                webhook::dispatch(req, EVENT_ARTICLE_CREATED, article_data(&article));
                 res_redirect!(format!("/article?id={}", article.id))
             },
             Err(_) => {
@@ -351,7 +607,9 @@ impl ArticlePage {
      }
 
      pub fn blog_article_edit(req: &mut Request) -> SapperResult<Response> {
-
+        if let Some(wait) = rate_limit::check(req, RateAction::Edit) {
+            return rate_limit::slow_down_page(req, wait);
+        }
         let params = get_form_params!(req);
         let id = t_param_parse!(params, "id", Uuid);
         let title = t_param!(params, "title").to_owned();
@@ -360,7 +618,7 @@ impl ArticlePage {
         let user = get_ext!(req, AppUser).unwrap();
         let section_id = Section::get_by_suser(user.id).unwrap().id;
 
-        let content = markdown_render(raw_content);
+        let (content, mentioned) = render_with_mentions(raw_content);
         let raw_content = raw_content.to_owned();
 
         let article_edit = ArticleEdit {
@@ -373,8 +631,16 @@ impl ArticlePage {
             content,
         };
 
+        ArticleRevision::ensure_baseline(id);
         match article_edit.update() {
-            Ok(article) => {
+            Ok(mut article) => {
+                sync_tags(req, &mut article);
+                page_cache::invalidate(req, Change::Article { article_id: article.id, section_id: article.section_id, tags: parse_tags(&article.tags) });
+                notify_mentions(req, user.id, article.id, None, &mentioned);
+                record_revision(&article, user.id);
+                if Trash::article_is_visible(article.id) {
+                    webhook::dispatch(req, EVENT_ARTICLE_EDITED, article_data(&article));
+                }
                 res_redirect!(format!("/article?id={}", article.id))
             },
             Err(_) => {
@@ -383,99 +649,233 @@ impl ArticlePage {
         }
     }
 
-}
-
+    pub fn article_history_page(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let params = get_query_params!(req);
+        let id = t_param_parse!(params, "id", Uuid);
+        let from = t_param_parse_default!(params, "from", Uuid, Uuid::default());
+        let to = t_param_parse_default!(params, "to", Uuid, Uuid::default());
 
-impl SapperModule for ArticlePage {
-    fn before(&self, req: &mut Request) -> SapperResult<()> {
-        if check_cache_switch(req) {
-            let (path, _) = req.uri();
-            if &path == "/article" {
-                let params = get_query_params!(req);
-                let article_id = t_param!(params, "id");
-                let current_page = t_param_parse_default!(params, "current_page", i64, 1);
-                let part_key = article_id.to_string() + ":" + &current_page.to_string();
-                if cache::cache_is_valid("article", &part_key) {
-                    let cache_content = cache::cache_get("article", &part_key);
-                    return res_html_before!(cache_content);
+        let article = match Article::get_by_id(id) {
+            Ok(article) => article,
+            Err(_) => return res_400!(format!("no this artile: {}", id)),
+        };
+        if !Trash::article_is_visible(article.id) && !is_moderator(req) {
+            return res_400!(format!("no this artile: {}", id));
+        }
+        let user = get_ext!(req, AppUser).unwrap();
+        let can_rollback = user.id == article.author_id || user.role >= 9;
+
+        let revisions = ArticleRevision::get_revisions_of(id);
+
+        // by default, the latest edit against the revision before it
+        let to = if to == Uuid::default() { revisions.get(0).map(|r| r.id) } else { Some(to) };
+        let from = if from == Uuid::default() { revisions.get(1).map(|r| r.id) } else { Some(from) };
+        if let (Some(from), Some(to)) = (from, to) {
+            let from_revision = ArticleRevision::get_by_id(from);
+            let to_revision = ArticleRevision::get_by_id(to);
+            match (from_revision, to_revision) {
+                (Ok(from_revision), Ok(to_revision)) => {
+                    if from_revision.article_id != id || to_revision.article_id != id {
+                        return res_400!("revisions are not of this article.");
+                    }
+                    let diff = line_diff(&from_revision.raw_content, &to_revision.raw_content);
+
+                    web.insert("from_revision", &from_revision);
+                    web.insert("to_revision", &to_revision);
+                    web.insert("diff", &diff);
+                },
+                _ => {
+                    return res_400!("no this revision.");
                 }
             }
         }
 
-        permission_need_login(req)?;
+        web.insert("article", &article);
+        web.insert("revisions", &revisions);
+        web.insert("can_rollback", &can_rollback);
 
-        Ok(())
+        res_html!("forum/article_history.html", web)
     }
 
-    fn after(&self, req: &Request, res: &mut Response) -> SapperResult<()> {
-        let (path, _) = req.uri();
-        if &path == "/s/article/create"
-            || &path == "/s/article/edit"
-            || &path == "/s/article/delete"
-            || &path == "/s/blogarticle/create"
-            || &path == "/s/blogarticle/edit" {
+    // rolling back is one more edit, so it can be undone the same way
+    pub fn article_rollback(req: &mut Request) -> SapperResult<Response> {
+        let params = get_form_params!(req);
+        let id = t_param_parse!(params, "id", Uuid);
+        let revision_id = t_param_parse!(params, "revision_id", Uuid);
+        let user = get_ext!(req, AppUser).unwrap();
 
-            cache::cache_set_invalid("index", "index");
+        let article = match Article::get_by_id(id) {
+            Ok(article) => article,
+            Err(_) => return res_400!(format!("no this artile: {}", id)),
+        };
+        if user.id != article.author_id && user.role < 9 {
+            return res_400!("No permissions: need be author or admin.");
         }
+        let revision = match ArticleRevision::get_by_id(revision_id) {
+            Ok(revision) if revision.article_id == id => revision,
+            _ => return res_400!("no this revision."),
+        };
 
-        // check other urls
-        if &path == "/s/article/create"
-            || &path == "/s/article/edit"
-            || &path == "/s/article/delete" {
-
-            let params = get_form_params!(req);
-            let section_id = t_param_parse!(params, "section_id", Uuid);
+        let (content, mentioned) = render_with_mentions(&revision.raw_content);
+        let article_edit = ArticleEdit {
+            id,
+            section_id: article.section_id,
+            title: revision.title,
+            tags: revision.tags,
+            extlink: article.extlink,
+            content,
+            raw_content: revision.raw_content,
+        };
 
-            let napp = envconfig::get_int_item("NUMBER_ARTICLE_PER_PAGE");
-            let n = Section::get_articles_count_belong_to_this(section_id);
-            let total_page = ((n -1) / napp) as i64 + 1;
+        ArticleRevision::ensure_baseline(id);
+        match article_edit.update() {
+            Ok(mut edited) => {
+                sync_tags(req, &mut edited);
+                notify_mentions(req, user.id, edited.id, None, &mentioned);
+                record_revision(&edited, user.id);
+                if Trash::article_is_visible(edited.id) {
+                    index_article(req, &edited, TanAction::Update);
+                    webhook::dispatch(req, EVENT_ARTICLE_EDITED, article_data(&edited));
+                }
+                page_cache::invalidate(req, Change::Article { article_id: edited.id, section_id: edited.section_id, tags: parse_tags(&edited.tags) });
 
-            for i in 1..=total_page {
-                let part_key = section_id.to_string() + ":" + &i.to_string();
-                cache::cache_set_invalid("section", &part_key);
+                res_redirect!(format!("/p/article/history?id={}", edited.id))
+            },
+            Err(_) => {
+                res_500!("article rollback error.")
             }
         }
+    }
 
-        if &path == "/s/blogarticle/create"
-            || &path == "/s/blogarticle/edit" {
-            let user = get_ext!(req, AppUser).unwrap();
-            let section_id = Section::get_by_suser(user.id).unwrap().id;
+    // called by the editor every little while, the first call creates the draft
+    pub fn article_autosave(req: &mut Request) -> SapperResult<Response> {
+        let params = get_form_params!(req);
+        let draft_id = t_param_parse_default!(params, "draft_id", Uuid, Uuid::default());
+        let title = t_param_default!(params, "title", "").to_owned();
+        let tags = t_param_default!(params, "tags", "").to_owned();
+        let extlink = t_param_default!(params, "extlink", "").to_owned();
+        let raw_content = t_param_default!(params, "raw_content", "");
+        let stype = t_param_parse_default!(params, "stype", i32, 0);
+        let user = get_ext!(req, AppUser).unwrap();
 
-            let napp = envconfig::get_int_item("NUMBER_ARTICLE_PER_PAGE");
-            let n = Section::get_articles_count_belong_to_this(section_id);
-            let total_page = ((n -1) / napp) as i64 + 1;
+        let section_id = if stype == 1 {
+            match Section::get_by_suser(user.id) {
+                Ok(section) => section.id,
+                Err(info) => return res_400!(info),
+            }
+        }
+        else {
+            // a draft row needs a real section, nothing is saved until one is picked
+            let section_id = t_param_parse_default!(params, "section_id", Uuid, Uuid::default());
+            match Section::get_by_id(section_id) {
+                Ok(section) => section.id,
+                Err(_) => return res_400!("no section picked for the draft."),
+            }
+        };
+
+        // mentioned users are told when the draft is published
+        let (content, _) = render_with_mentions(raw_content);
+        let raw_content = raw_content.to_owned();
+        let draft_save = DraftSave {
+            id: if draft_id == Uuid::default() { None } else { Some(draft_id) },
+            title,
+            tags,
+            extlink,
+            section_id,
+            author_id: user.id,
+            raw_content,
+            content,
+            stype,
+        };
 
-            for i in 1..=total_page {
-                let part_key = section_id.to_string() + ":" + &i.to_string();
-                cache::cache_set_invalid("section", &part_key);
+        match draft_save.save() {
+            Ok(draft) => {
+                res_json!(json!({
+                    "draft_id": draft.id,
+                    "updated_time": draft.updated_time,
+                }))
+            },
+            Err(info) => {
+                res_500!(info)
             }
         }
+    }
+
+    // an empty comment_id takes the acceptance back
+    pub fn article_accept_answer(req: &mut Request) -> SapperResult<Response> {
+        let can_moderate = is_moderator(req);
+        let params = get_form_params!(req);
+        let article_id = t_param_parse!(params, "article_id", Uuid);
+        let comment_id = t_param_default!(params, "comment_id", "").parse::<Uuid>().ok();
+        let user = get_ext!(req, AppUser).unwrap();
+
+        let article = match Article::get_by_id(article_id) {
+            Ok(article) => article,
+            Err(_) => return res_400!(format!("no this artile: {}", article_id)),
+        };
+        if article.stype != 0 || !Qa::is_qa_section(article.section_id) {
+            return res_400!("this article is not a question.");
+        }
+        if article.author_id != user.id && !can_moderate {
+            return res_400!("no permission.");
+        }
+
+        let accept_answer = AcceptAnswer {
+            article_id,
+            comment_id,
+        };
 
-        if &path == "/article" {
-            let params = get_query_params!(req);
-            let article_id = t_param!(params, "id");
-            let current_page = t_param_parse_default!(params, "current_page", i64, 1);
-            let part_key = article_id.to_string() + ":" + &current_page.to_string();
-            if !cache::cache_is_valid("article", &part_key) {
-                cache::cache_set("article", &part_key, res.body());
+        match accept_answer.update() {
+            Ok(_) => {
+                page_cache::invalidate(req, Change::Comment { article_id, section_id: article.section_id });
+
+                match comment_id {
+                    Some(comment_id) => res_redirect!(format!("/article?id={}#comment-{}", article_id, comment_id)),
+                    None => res_redirect!(format!("/article?id={}", article_id)),
+                }
+            },
+            Err(info) => {
+                res_400!(info)
             }
         }
+    }
 
-        if &path == "/s/article/edit"
-            || &path == "/s/blogarticle/edit"  {
-            let params = get_form_params!(req);
-            let article_id = t_param!(params, "id");
+    pub fn article_draft_delete(req: &mut Request) -> SapperResult<Response> {
+        let params = get_form_params!(req);
+        let id = t_param_parse!(params, "id", Uuid);
+        let user = get_ext!(req, AppUser).unwrap();
 
-            cache::cache_set_invalid("article", article_id);
+        match Draft::delete(id, user.id) {
+            Ok(_) => {
+                res_redirect!("/p/user/drafts")
+            },
+            Err(info) => {
+                res_500!(info)
+            }
         }
+    }
+
+}
 
 
+impl SapperModule for ArticlePage {
+    fn before(&self, req: &mut Request) -> SapperResult<()> {
+        permission_need_login(req)?;
+
+        Ok(())
+    }
+
+    fn after(&self, req: &Request, res: &mut Response) -> SapperResult<()> {
+        page_cache::store(req, res)?;
+
         Ok(())
     }
 
     fn router(&self, router: &mut SapperRouter) -> SapperResult<()> {
         router.get("/article", Self::article_detail_page);
-        router.get("/article/:id", Self::article_path_page);
+        router.get("/article/:id", Self::article_detail_page);
+        router.get("/article/:id/:slug", Self::article_detail_page);
 
         router.get("/p/article/create", Self::article_create_page);
         router.get("/p/article/edit", Self::article_edit_page);
@@ -483,6 +883,11 @@ impl SapperModule for ArticlePage {
         router.post("/s/article/create", Self::article_create);
         router.post("/s/article/edit", Self::article_edit);
         router.post("/s/article/delete", Self::article_delete);
+        router.post("/s/article/autosave", Self::article_autosave);
+        router.get("/p/article/history", Self::article_history_page);
+        router.post("/s/article/rollback", Self::article_rollback);
+        router.post("/s/article/draft/delete", Self::article_draft_delete);
+        router.post("/s/article/accept_answer", Self::article_accept_answer);
         router.get("/s/article/delete_index", Self::article_delete_index);
 
         router.get("/p/blogarticle/create", Self::blog_article_create_page);
@@ -0,0 +1,110 @@
+use sapper::{
+    Request,
+    Response,
+    Result as SapperResult,
+    Error as SapperError,
+    Module as SapperModule,
+    Router as SapperRouter};
+use sapper_std::*;
+use uuid::Uuid;
+use serde_json::json;
+
+use crate::AppUser;
+use crate::model::bookmark::{
+    Bookmark,
+    BookmarkToggle,
+    BookmarkNotify
+};
+use crate::page_cache::{self, Change};
+use crate::middleware::permission_need_login;
+
+pub struct BookmarkPage;
+
+impl BookmarkPage {
+
+    // called by bookmark.js, answers the new state and count
+    pub fn bookmark_toggle(req: &mut Request) -> SapperResult<Response> {
+        let params = get_form_params!(req);
+        let article_id = t_param_parse!(params, "article_id", Uuid);
+        let user = get_ext!(req, AppUser).unwrap();
+
+        let bookmark_toggle = BookmarkToggle {
+            user_id: user.id,
+            article_id,
+        };
+
+        match bookmark_toggle.toggle() {
+            Ok(bookmarked) => {
+                // the count is on the article page
+                page_cache::invalidate(req, Change::Bookmarks { article_id });
+
+                res_json!(json!({
+                    "bookmarked": bookmarked,
+                    "notify": false,
+                    "count": Bookmark::count_of(article_id),
+                }))
+            },
+            Err(info) => {
+                res_400!(info)
+            }
+        }
+    }
+
+    pub fn bookmark_notify(req: &mut Request) -> SapperResult<Response> {
+        let params = get_form_params!(req);
+        let article_id = t_param_parse!(params, "article_id", Uuid);
+        let notify = t_param_parse_default!(params, "notify", i32, 0) == 1;
+        let user = get_ext!(req, AppUser).unwrap();
+
+        let bookmark_notify = BookmarkNotify {
+            user_id: user.id,
+            article_id,
+            notify,
+        };
+
+        match bookmark_notify.update() {
+            Ok(bookmark) => {
+                res_json!(json!({
+                    "bookmarked": true,
+                    "notify": bookmark.notify,
+                }))
+            },
+            Err(info) => {
+                res_400!(info)
+            }
+        }
+    }
+
+    // the cached article page is the same for everybody, the state of
+    // the current user's bookmark is filled in afterwards from this
+    pub fn my_bookmark(req: &mut Request) -> SapperResult<Response> {
+        let params = get_query_params!(req);
+        let article_id = t_param_parse!(params, "article_id", Uuid);
+        let user = get_ext!(req, AppUser).unwrap();
+
+        let bookmark = Bookmark::get(user.id, article_id);
+
+        res_json!(json!({
+            "bookmarked": bookmark.is_some(),
+            "notify": bookmark.map(|b| b.notify).unwrap_or(false),
+        }))
+    }
+
+}
+
+
+impl SapperModule for BookmarkPage {
+    fn before(&self, req: &mut Request) -> SapperResult<()> {
+        permission_need_login(req)?;
+
+        Ok(())
+    }
+
+    fn router(&self, router: &mut SapperRouter) -> SapperResult<()> {
+        router.post("/s/bookmark/toggle", Self::bookmark_toggle);
+        router.post("/s/bookmark/notify", Self::bookmark_notify);
+        router.get("/p/bookmark/mine", Self::my_bookmark);
+
+        Ok(())
+    }
+}
@@ -11,7 +11,6 @@ use uuid::Uuid;
 use chrono::*;
 
 use crate::db;
-use crate::cache;
 // introduce macros
 use sapper_std::res_html;
 use crate::{
@@ -29,10 +28,165 @@ use crate::dataservice::comment::{
     CommentEdit
 };
 
-use crate::util::markdown_render;
+use crate::mention::render_with_mentions;
+use crate::model::mention::MentionedUser;
+use crate::rate_limit::{self, RateAction};
+use crate::mailer::queue_notification;
+use crate::webhook::{self, comment_data};
+use crate::model::webhook::EVENT_COMMENT_CREATED;
 use crate::middleware::permission_need_login;
 use crate::envconfig;
+use crate::model::comment_thread::{
+    CommentParent,
+    UpdateCommentParent
+};
+use crate::model::trash::{
+    Trash,
+    ArticleRef,
+    CommentRef,
+    SoftDeleteComment,
+    STATUS_DELETED
+};
+use crate::model::notification::{
+    NotificationCreate,
+    NOTIFY_REPLY,
+    NOTIFY_COMMENT,
+    NOTIFY_MENTION,
+    NOTIFY_BOOKMARK
+};
+use crate::model::bookmark::Bookmark;
+use crate::model::vote::Vote;
+use crate::page_forum::admin_page::notify_moderation;
+use crate::page_cache::{self, Change};
+
+
+// a new comment lifts the article in the reply ordering
+pub fn touch_article_updated_time(article_id: Uuid) {
+    let do_update = UpdateArticleUpdatedTime {
+        id: article_id,
+        updated_time: Utc::now()
+    };
+
+    let _ = do_update.update();
+}
+
+// remember which comment this one replies to, and tell its author.
+// Returns who was told.
+pub fn link_reply(req: &Request, comment: &Comment, parent_id: Uuid) -> Option<Uuid> {
+    let update_parent = UpdateCommentParent {
+        id: comment.id,
+        parent_id,
+    };
+    if update_parent.update().is_err() {
+        return None;
+    }
+
+    let parent = Comment::get_by_id(parent_id).ok()?;
+    let notification_create = NotificationCreate {
+        user_id: parent.author_id,
+        ntype: NOTIFY_REPLY,
+        actor_id: comment.author_id,
+        article_id: comment.article_id,
+        comment_id: Some(comment.id),
+        detail: "".to_string(),
+    };
+    if let Ok(notification) = notification_create.insert() {
+        queue_notification(req, &notification);
+    }
+
+    Some(parent.author_id)
+}
+
+// tell the article author and the users watching their bookmark about a
+// new comment, once: not again when the comment is a reply to them
+pub fn notify_comment(req: &Request, comment: &Comment, replied_to: Option<Uuid>) {
+    let article = match Article::get_by_id(comment.article_id) {
+        Ok(article) => article,
+        Err(_) => return,
+    };
+
+    if replied_to != Some(article.author_id) {
+        let notification_create = NotificationCreate {
+            user_id: article.author_id,
+            ntype: NOTIFY_COMMENT,
+            actor_id: comment.author_id,
+            article_id: comment.article_id,
+            comment_id: Some(comment.id),
+            detail: "".to_string(),
+        };
+        if let Ok(notification) = notification_create.insert() {
+            queue_notification(req, &notification);
+        }
+    }
+
+    for user_id in Bookmark::notified_users_of(article.id) {
+        if user_id == article.author_id || replied_to == Some(user_id) {
+            continue;
+        }
+        let notification_create = NotificationCreate {
+            user_id,
+            ntype: NOTIFY_BOOKMARK,
+            actor_id: comment.author_id,
+            article_id: comment.article_id,
+            comment_id: Some(comment.id),
+            detail: "".to_string(),
+        };
+        if let Ok(notification) = notification_create.insert() {
+            queue_notification(req, &notification);
+        }
+    }
+}
+
+// tell the mentioned users, only once for an article or comment however often it is edited
+pub fn notify_mentions(req: &Request, actor_id: Uuid, article_id: Uuid, comment_id: Option<Uuid>, mentioned: &[MentionedUser]) {
+    for user in mentioned {
+        let notification_create = NotificationCreate {
+            user_id: user.id,
+            ntype: NOTIFY_MENTION,
+            actor_id,
+            article_id,
+            comment_id,
+            detail: "".to_string(),
+        };
+        if let Ok(notification) = notification_create.insert_once() {
+            queue_notification(req, &notification);
+        }
+    }
+}
 
+// add the "in reply to" fields to the comments which have a parent,
+// and blank the deleted ones so the thread still reads right
+pub fn prepare_comments<T: serde::Serialize>(article_id: Uuid, comments: &[T]) -> Vec<serde_json::Value> {
+    let ncpp = envconfig::get_int_item("NUMBER_COMMENT_PER_PAGE");
+    let parents = CommentParent::get_parents_in_article(article_id);
+    let invisible = Trash::invisible_comments_in_article(article_id);
+    let scores = Vote::comment_scores_in_article(article_id);
+
+    comments.iter().map(|comment| {
+        let mut value = serde_json::to_value(comment).unwrap();
+        let id = value.get("id")
+            .and_then(|id| id.as_str())
+            .and_then(|id| id.parse::<Uuid>().ok());
+        if let Some(status) = id.and_then(|id| invisible.get(&id)) {
+            if *status == STATUS_DELETED {
+                value["deleted"] = serde_json::json!(true);
+            }
+            else {
+                value["hidden"] = serde_json::json!(true);
+            }
+            value["content"] = serde_json::json!("");
+            value["raw_content"] = serde_json::json!("");
+        }
+        if let Some(parent) = id.and_then(|id| parents.iter().find(|p| p.id == id)) {
+            value["parent_id"] = serde_json::json!(parent.parent_id);
+            value["parent_author_id"] = serde_json::json!(parent.parent_author_id);
+            value["parent_nickname"] = serde_json::json!(parent.parent_nickname);
+            value["parent_page"] = serde_json::json!(parent.parent_page(ncpp));
+        }
+        value["score"] = serde_json::json!(id.and_then(|id| scores.get(&id).cloned()).unwrap_or(0));
+        value
+    }).collect()
+}
 
 pub struct CommentPage;
 
@@ -118,11 +272,24 @@ impl CommentPage {
     }
 
     pub fn comment_new(req: &mut Request) -> SapperResult<Response> {
+        if let Some(wait) = rate_limit::check(req, RateAction::Comment) {
+            return rate_limit::slow_down_page(req, wait);
+        }
         let params = get_form_params!(req);
         let article_id = t_param_parse!(params, "article_id", Uuid);
         let raw_content = t_param!(params, "raw_content");
+        let reply_comment_id = if t_has_param!(params, "reply_comment_id") {
+            t_param!(params, "reply_comment_id").parse::<Uuid>().ok()
+        }
+        else {
+            None
+        };
+        // deleted, hidden and draft articles take no comments
+        if !Trash::article_is_visible(article_id) {
+            return res_400!("no this article.");
+        }
 
-        let content = markdown_render(raw_content);
+        let (content, mentioned) = render_with_mentions(raw_content);
         let raw_content = raw_content.to_owned();
 
         let user = get_ext!(req, AppUser).unwrap();
@@ -138,13 +305,11 @@ impl CommentPage {
 
         match comment_create.insert() {
             Ok(comment) => {
-                                // update article updated_time
-                                let do_update = UpdateArticleUpdatedTime {
-                                        id: comment.article_id,
-                                        updated_time: Utc::now()
-                                };
-
-                                let _ = do_update.update();
+                touch_article_updated_time(comment.article_id);
+                let replied_to = reply_comment_id.and_then(|parent_id| link_reply(req, &comment, parent_id));
+                notify_comment(req, &comment, replied_to);
+                notify_mentions(req, author_id, article_id, Some(comment.id), &mentioned);
+                webhook::dispatch(req, EVENT_COMMENT_CREATED, comment_data(&comment));
 
                 res_redirect!(format!("/article?id={}", article_id))
             },
@@ -155,12 +320,15 @@ impl CommentPage {
     }
 
     pub fn comment_edit(req: &mut Request) -> SapperResult<Response> {
+        if let Some(wait) = rate_limit::check(req, RateAction::Edit) {
+            return rate_limit::slow_down_page(req, wait);
+        }
         let params = get_form_params!(req);
         let id = t_param_parse!(params, "id", Uuid);
         let article_id = t_param_parse!(params, "article_id", Uuid);
         let raw_content = t_param!(params, "raw_content");
 
-        let content = markdown_render(raw_content);
+        let (content, mentioned) = render_with_mentions(raw_content);
         let raw_content = raw_content.to_owned();
 
         let comment_edit = CommentEdit {
@@ -171,6 +339,8 @@ impl CommentPage {
 
         match comment_edit.update() {
             Ok(comment) => {
+                let user = get_ext!(req, AppUser).unwrap();
+                notify_mentions(req, user.id, comment.article_id, Some(comment.id), &mentioned);
                 res_redirect!(format!("/article?id={}", article_id))
             },
             Err(_) => {
@@ -183,9 +353,16 @@ impl CommentPage {
         let params = get_form_params!(req);
         let article_id = t_param_parse!(params, "article_id", Uuid);
         let comment_id = t_param_parse!(params, "comment_id", Uuid);
+        let user = get_ext!(req, AppUser).unwrap();
 
-        match Comment::delete_by_id(comment_id) {
+        let soft_delete = SoftDeleteComment {
+            id: comment_id,
+            deleted_by: user.id,
+        };
+
+        match soft_delete.update() {
             Ok(comment) => {
+                notify_moderation(user.id, "comment", comment.id, "deleted");
                 res_redirect!(format!("/article?id={}", article_id))
             },
             Err(_) => {
@@ -212,17 +389,23 @@ impl SapperModule for CommentPage {
             || &path == "/s/comment/delete" {
 
             let params = get_form_params!(req);
-            let article_id = t_param_parse!(params, "article_id", Uuid);
-
-            let ncpp = envconfig::get_int_item("NUMBER_COMMENT_PER_PAGE");
-            let n = Article::get_comments_count_belong_to_this(article_id);
-            let total_page = ((n -1) / ncpp) as i64 + 1;
-
-            for i in 1..=total_page {
-                let part_key = article_id.to_string() + ":" + &i.to_string();
-                cache::cache_set_invalid("article", &part_key);
+            // an edited or deleted comment tells its own article, the form
+            // field may be missing or wrong
+            let target = if &path == "/s/comment/new" {
+                let article_id = t_param_parse_default!(params, "article_id", Uuid, Uuid::default());
+                ArticleRef::get_by_id(article_id).ok()
+                    .map(|article| (article.id, article.section_id))
+            }
+            else {
+                let field = if &path == "/s/comment/edit" { "id" } else { "comment_id" };
+                let comment_id = t_param_parse_default!(params, field, Uuid, Uuid::default());
+                CommentRef::get_by_id(comment_id).ok()
+                    .map(|comment| (comment.article_id, comment.section_id))
+            };
+
+            if let Some((article_id, section_id)) = target {
+                page_cache::invalidate(req, Change::Comment { article_id, section_id });
             }
-
         }
 
         Ok(())
@@ -0,0 +1,215 @@
+use sapper::{
+    header::ContentType,
+    Request,
+    Response,
+    Result as SapperResult,
+    Error as SapperError,
+    Module as SapperModule,
+    Router as SapperRouter};
+use sapper_std::*;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+use crate::envconfig;
+use crate::page_cache;
+use crate::feed::{
+    self,
+    FeedItem,
+    FeedFormat
+};
+use crate::dataservice::article::Article;
+use crate::dataservice::section::Section;
+use crate::model::feed_article::{
+    FeedArticle,
+    FeedComment
+};
+use crate::model::tag::{
+    Tag,
+    normalize_tag
+};
+use crate::model::trash::Trash;
+use crate::model::activity::Activity;
+use crate::conditional::set_last_modified;
+use crate::compress;
+use crate::page_forum::tag_page::percent_decode;
+
+// the format and `full` flag asked for
+fn feed_request(req: &Request) -> SapperResult<(FeedFormat, bool)> {
+    let (path, _) = req.uri();
+    let format = FeedFormat::from_path(&path).unwrap_or(FeedFormat::Rss);
+    let params = get_query_params!(req);
+    let full = t_param_default!(params, "full", "") == "1";
+
+    Ok((format, full))
+}
+
+fn feed_response(format: FeedFormat, last_modified: Option<DateTime<Utc>>) -> Response {
+    let mut response = Response::new();
+    response.headers_mut().set(ContentType(format.content_type().parse().unwrap()));
+    set_last_modified(&mut response, last_modified);
+
+    response
+}
+
+pub fn res_feed(format: FeedFormat, body: String, last_modified: Option<DateTime<Utc>>) -> SapperResult<Response> {
+    let mut response = feed_response(format, last_modified);
+    response.write_body(body);
+
+    Ok(response)
+}
+
+// a feed from the page cache, gzipped
+pub fn res_cached_feed(format: FeedFormat, body: Vec<u8>, last_modified: Option<DateTime<Utc>>) -> SapperResult<Response> {
+    let mut response = feed_response(format, last_modified);
+    compress::write_gzipped(&mut response, body);
+
+    Ok(response)
+}
+
+fn article_items(articles: &[FeedArticle], full: bool) -> Vec<FeedItem> {
+    articles.iter().map(|article| FeedItem::from_article(article, full)).collect()
+}
+
+pub struct FeedPage;
+
+impl FeedPage {
+
+    pub fn section_feed(req: &mut Request) -> SapperResult<Response> {
+        let section_id = {
+            let params = get_path_params!(req);
+            t_param_parse!(params, "id", Uuid)
+        };
+        let last_modified = Activity::of_section(section_id);
+        // the hooks can only answer html
+        if let Some((format, cached)) = page_cache::lookup_feed(req)? {
+            return res_cached_feed(format, cached, last_modified);
+        }
+        let (format, full) = feed_request(req)?;
+
+        let section = match Section::get_by_id(section_id) {
+            Ok(section) => section,
+            Err(_) => return res_400!("no this section"),
+        };
+
+        let napp = envconfig::get_int_item("BIG_NUMBER_ARTICLE_PER_PAGE");
+        let articles = FeedArticle::get_latest_in_section(section.id, napp);
+        let title = format!("{} - {}", section.title, envconfig::get_str_item("SITE_TITLE"));
+        let feed_link = format!("/section/{}/{}", section.id, format.name());
+        let feed_string = feed::make_feed(format, &title, &format!("/section?id={}", section.id), &feed_link, &article_items(&articles, full));
+
+        res_feed(format, feed_string, last_modified)
+    }
+
+    pub fn blog_feed(req: &mut Request) -> SapperResult<Response> {
+        let author_id = {
+            let params = get_path_params!(req);
+            t_param_parse!(params, "author_id", Uuid)
+        };
+        let section = match Section::get_by_suser(author_id) {
+            Ok(section) => section,
+            Err(_) => return res_400!("no this blog"),
+        };
+        let last_modified = Activity::of_section(section.id);
+        // the hooks can only answer html
+        if let Some((format, cached)) = page_cache::lookup_feed(req)? {
+            return res_cached_feed(format, cached, last_modified);
+        }
+        let (format, full) = feed_request(req)?;
+
+        let napp = envconfig::get_int_item("BIG_NUMBER_ARTICLE_PER_PAGE");
+        let articles = FeedArticle::get_latest_in_section(section.id, napp);
+        let title = format!("{} - {}", section.title, envconfig::get_str_item("SITE_TITLE"));
+        let feed_link = format!("/blog/{}/{}", author_id, format.name());
+        let feed_string = feed::make_feed(format, &title, &format!("/blog_with_author?author_id={}", author_id), &feed_link, &article_items(&articles, full));
+
+        res_feed(format, feed_string, last_modified)
+    }
+
+    pub fn tag_feed(req: &mut Request) -> SapperResult<Response> {
+        // the hooks can only answer html, a tag feed has only its ETag
+        if let Some((format, cached)) = page_cache::lookup_feed(req)? {
+            return res_cached_feed(format, cached, None);
+        }
+        let name = {
+            let params = get_path_params!(req);
+            percent_decode(t_param!(params, "name"))
+        };
+        let (format, full) = feed_request(req)?;
+
+        let tag = match normalize_tag(&name).ok_or(()).and_then(|name| Tag::get_by_name(&name).map_err(|_| ())) {
+            Ok(tag) => tag,
+            Err(_) => return res_400!("no this tag"),
+        };
+
+        let napp = envconfig::get_int_item("BIG_NUMBER_ARTICLE_PER_PAGE");
+        let articles = FeedArticle::get_latest_by_tag(tag.id, napp);
+        let title = format!("#{} - {}", tag.name, envconfig::get_str_item("SITE_TITLE"));
+        let feed_link = format!("/tag/{}/{}", tag.name, format.name());
+        let feed_string = feed::make_feed(format, &title, &format!("/tag/{}", tag.name), &feed_link, &article_items(&articles, full));
+
+        res_feed(format, feed_string, None)
+    }
+
+    // the latest comments of one article
+    pub fn article_comments_feed(req: &mut Request) -> SapperResult<Response> {
+        let article_id = {
+            let params = get_path_params!(req);
+            t_param_parse!(params, "id", Uuid)
+        };
+        let last_modified = Activity::of_article(article_id);
+        // the hooks can only answer html
+        if let Some((format, cached)) = page_cache::lookup_feed(req)? {
+            return res_cached_feed(format, cached, last_modified);
+        }
+        let (format, full) = feed_request(req)?;
+
+        if !Trash::article_is_visible(article_id) {
+            return res_400!(format!("no this artile: {}", article_id));
+        }
+        let article = match Article::get_by_id(article_id) {
+            Ok(article) => article,
+            Err(_) => return res_400!(format!("no this artile: {}", article_id)),
+        };
+
+        let ncpp = envconfig::get_int_item("NUMBER_COMMENT_PER_PAGE");
+        let items: Vec<FeedItem> = FeedComment::get_latest_in_article(article.id, ncpp).iter()
+            .map(|comment| FeedItem::from_comment(comment, full))
+            .collect();
+        let title = format!("Comments on {} - {}", article.title, envconfig::get_str_item("SITE_TITLE"));
+        let feed_link = format!("/article/{}/{}", article.id, format.name());
+        let feed_string = feed::make_feed(format, &title, &format!("/article?id={}", article.id), &feed_link, &items);
+
+        res_feed(format, feed_string, last_modified)
+    }
+
+}
+
+
+impl SapperModule for FeedPage {
+    fn before(&self, _req: &mut Request) -> SapperResult<()> {
+        Ok(())
+    }
+
+    fn after(&self, req: &Request, res: &mut Response) -> SapperResult<()> {
+        page_cache::store(req, res)?;
+
+        Ok(())
+    }
+
+    fn router(&self, router: &mut SapperRouter) -> SapperResult<()> {
+        router.get("/section/:id/rss", Self::section_feed);
+        router.get("/section/:id/atom", Self::section_feed);
+        router.get("/section/:id/feed.json", Self::section_feed);
+        router.get("/blog/:author_id/rss", Self::blog_feed);
+        router.get("/blog/:author_id/atom", Self::blog_feed);
+        router.get("/blog/:author_id/feed.json", Self::blog_feed);
+        router.get("/tag/:name/rss", Self::tag_feed);
+        router.get("/tag/:name/atom", Self::tag_feed);
+        router.get("/tag/:name/feed.json", Self::tag_feed);
+        router.get("/article/:id/rss", Self::article_comments_feed);
+        router.get("/article/:id/atom", Self::article_comments_feed);
+        router.get("/article/:id/feed.json", Self::article_comments_feed);
+
+        Ok(())
+    }
+}
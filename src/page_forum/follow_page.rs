@@ -0,0 +1,103 @@
+use sapper::{
+    Request,
+    Response,
+    Result as SapperResult,
+    Error as SapperError,
+    Module as SapperModule,
+    Router as SapperRouter};
+use sapper_std::*;
+use uuid::Uuid;
+use serde_json::json;
+
+use crate::AppUser;
+use crate::dataservice::section::Section;
+use crate::model::follow::{
+    SectionFollow,
+    UserFollow
+};
+use crate::page_cache::{self, Change};
+use crate::middleware::permission_need_login;
+
+pub struct FollowPage;
+
+impl FollowPage {
+
+    // called by follow.js, answers the new state and follower count
+    pub fn follow_toggle(req: &mut Request) -> SapperResult<Response> {
+        let params = get_form_params!(req);
+        let kind = t_param!(params, "kind").to_owned();
+        let id = t_param_parse!(params, "id", Uuid);
+        let user = get_ext!(req, AppUser).unwrap();
+
+        let result = match kind.as_str() {
+            "section" => {
+                SectionFollow::toggle(user.id, id)
+                    .map(|following| (following, SectionFollow::followers_count(id)))
+            },
+            "user" => {
+                UserFollow::toggle(user.id, id)
+                    .map(|following| (following, UserFollow::followers_count(id)))
+            },
+            _ => {
+                return res_400!("unknown follow target.");
+            }
+        };
+
+        match result {
+            Ok((following, followers)) => {
+                // the count is on the section page, or on the blog of the user
+                let section_id = if kind == "section" {
+                    Some(id)
+                }
+                else {
+                    Section::get_by_suser(id).ok().map(|section| section.id)
+                };
+                if let Some(section_id) = section_id {
+                    page_cache::invalidate(req, Change::Followers { section_id });
+                }
+
+                res_json!(json!({
+                    "following": following,
+                    "followers": followers,
+                }))
+            },
+            Err(info) => {
+                res_400!(info)
+            }
+        }
+    }
+
+    // the cached section page is the same for everybody, the button
+    // of the current user is set afterwards from this
+    pub fn follow_state(req: &mut Request) -> SapperResult<Response> {
+        let params = get_query_params!(req);
+        let kind = t_param!(params, "kind").to_owned();
+        let id = t_param_parse!(params, "id", Uuid);
+        let user = get_ext!(req, AppUser).unwrap();
+
+        let following = match kind.as_str() {
+            "section" => SectionFollow::is_following(user.id, id),
+            "user" => UserFollow::is_following(user.id, id),
+            _ => return res_400!("unknown follow target."),
+        };
+
+        res_json!(json!({ "following": following }))
+    }
+
+}
+
+
+impl SapperModule for FollowPage {
+    fn before(&self, req: &mut Request) -> SapperResult<()> {
+        permission_need_login(req)?;
+
+        Ok(())
+    }
+
+    fn router(&self, router: &mut SapperRouter) -> SapperResult<()> {
+        router.post("/s/follow", Self::follow_toggle);
+        router.get("/p/follow/state", Self::follow_state);
+
+        Ok(())
+    }
+}
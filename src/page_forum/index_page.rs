@@ -1,5 +1,4 @@
 use sapper::{
-    status,
     Request,
     Response,
     Result as SapperResult,
@@ -13,50 +12,81 @@ use crate::db;
 // introduce macros
 use sapper_std::res_html;
 use crate::{AppWebContext, AppUser};
-use crate::cache;
-use crate::rss;
+use crate::page_cache;
+use crate::feed::{self, FeedItem, FeedFormat};
 
 use crate::envconfig;
-use crate::dataservice::article::Article;
 use crate::dataservice::section::Section;
+use crate::model::trash::Trash;
+use crate::model::listing::Listing;
+use crate::model::feed_article::FeedArticle;
+use crate::model::tag::Tag;
+use crate::model::follow::FollowingFeed;
+use crate::model::activity::Activity;
+use crate::page_forum::feed_page::{
+    res_feed,
+    res_cached_feed
+};
+use crate::conditional::{
+    res_cached_html,
+    set_last_modified
+};
 
 use crate::{TanIndexTx, TanQueryRx};
 use crate::tantivy_index::{DocFromIndexOuter, Doc2Index, TanAction};
-use crate::middleware::{
-    permission_need_be_admin,
-    check_cache_switch
-};
+use crate::middleware::permission_need_be_admin;
 
 pub struct IndexPage;
 
 impl IndexPage {
 
     pub fn index(req: &mut Request) -> SapperResult<Response> {
+        let last_modified = Activity::of_site();
+        if let Some(cache_content) = page_cache::lookup_page(req)? {
+            return res_cached_html(cache_content, last_modified);
+        }
         let mut web = get_ext_owned!(req, AppWebContext).unwrap();
 
         let napp = envconfig::get_int_item("NUMBER_ARTICLE_PER_PAGE");
-        let articles = Article::get_latest_articles(napp);
+        let articles = Listing::latest_forum(1, napp);
 
-        let reply_articles = Article::get_latest_reply_articles(napp);
+        let reply_articles = Listing::latest_reply(1, napp);
 
-        let blog_articles = Article::get_latest_blog_articles(napp);
+        let blog_articles = Listing::latest_blog(1, napp);
 
         // get all configured index displaying sections
         // and latest commented three articles
         let sections = Section::forum_sections();
 
+        let popular_tags = Tag::popular(envconfig::get_int_item("NUMBER_POPULAR_TAGS"));
+
         web.insert("articles", &articles);
         web.insert("reply_articles", &reply_articles);
         web.insert("blog_articles", &blog_articles);
         web.insert("sections", &sections);
+        web.insert("popular_tags", &popular_tags);
 
-        res_html!("forum/index.html", web)
+        res_html!("forum/index.html", web).map(|mut response| {
+            set_last_modified(&mut response, last_modified);
+            response
+        })
     }
 
-    pub fn rss_xml(req: &mut Request) -> SapperResult<Response> {
-        let rss_string = rss::make_rss_feed();
+    // the whole site, at /rss, /atom and /feed.json
+    pub fn site_feed(req: &mut Request) -> SapperResult<Response> {
+        let last_modified = Activity::of_site();
+        if let Some((format, cached)) = page_cache::lookup_feed(req)? {
+            return res_cached_feed(format, cached, last_modified);
+        }
+        let (path, _) = req.uri();
+        let format = FeedFormat::from_path(&path).unwrap_or(FeedFormat::Rss);
+        let napp = envconfig::get_int_item("BIG_NUMBER_ARTICLE_PER_PAGE");
+        let articles = FeedArticle::get_latest(napp);
+        let title = envconfig::get_str_item("SITE_TITLE");
+        let items: Vec<FeedItem> = articles.iter().map(|article| FeedItem::from_article(article, true)).collect();
+        let feed_string = feed::make_feed(format, &title, "/", &path, &items);
 
-        res_xml_string!(rss_string)
+        res_feed(format, feed_string, last_modified)
     }
 
     pub fn search_query_page(req: &mut Request) -> SapperResult<Response> {
@@ -74,6 +104,7 @@ impl IndexPage {
             let tan_query = get_ext!(req, TanQueryRx).unwrap();
             // block receiving
             docs = tan_query.recv().unwrap();
+            docs.retain(|doc| Trash::article_is_visible(doc.article_id));
         }
 
         web.insert("docs", &docs);
@@ -94,7 +125,8 @@ impl IndexPage {
 
         let tan_index = get_ext!(req, TanIndexTx).unwrap();
 
-        let articles = Article::get_all_articles();
+        // deleted, hidden and draft articles stay out of search
+        let articles = Listing::searchable();
 
         for article in articles {
             let doc2index = Doc2Index {
@@ -103,8 +135,8 @@ impl IndexPage {
                 title: article.title,
                 content: article.raw_content
             };
-            // send query directive
-            tan_index.send((TanAction::Add, "".to_string(), Some(doc2index))).unwrap();
+            // replaces the doc already indexed, if any
+            tan_index.send((TanAction::Update, "".to_string(), Some(doc2index))).unwrap();
         }
 
         info!("Make index test finished.");
@@ -140,10 +172,10 @@ impl IndexPage {
         }
 
         let napp = envconfig::get_int_item("BIG_NUMBER_ARTICLE_PER_PAGE");
-        let total_item = Article::get_all_section_articles_count();
+        let total_item = Listing::forum_count();
         let total_page = ((total_item - 1) / napp) as i64 + 1;
 
-        let articles = Article::get_latest_articles_paging(current_page-1, napp);
+        let articles = Listing::latest_forum(current_page, napp);
 
         web.insert("is_admin", &is_admin);
         web.insert("total_item", &total_item);
@@ -178,10 +210,10 @@ impl IndexPage {
         }
 
         let napp = envconfig::get_int_item("BIG_NUMBER_ARTICLE_PER_PAGE");
-        let total_item = Article::get_all_section_articles_count();
+        let total_item = Listing::forum_count();
         let total_page = ((total_item - 1) / napp) as i64 + 1;
 
-        let articles = Article::get_latest_reply_articles_paging(current_page-1, napp);
+        let articles = Listing::latest_reply(current_page, napp);
 
         web.insert("is_admin", &is_admin);
         web.insert("total_item", &total_item);
@@ -216,10 +248,10 @@ impl IndexPage {
         }
 
         let napp = envconfig::get_int_item("BIG_NUMBER_ARTICLE_PER_PAGE");
-        let total_item = Article::get_all_blog_articles_count();
+        let total_item = Listing::blog_count();
         let total_page = ((total_item - 1) / napp) as i64 + 1;
 
-        let articles = Article::get_latest_blog_articles_paging(current_page-1, napp);
+        let articles = Listing::latest_blog(current_page, napp);
 
         web.insert("is_admin", &is_admin);
         web.insert("total_item", &total_item);
@@ -232,33 +264,46 @@ impl IndexPage {
         res_html!("forum/article_list_paging.html", web)
     }
 
-}
+    // different for everybody, so never cached like the index
+    pub fn following_articles_paging(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let params = get_query_params!(req);
 
+        let current_page = t_param_parse_default!(params, "current_page", i64, 1);
 
-impl SapperModule for IndexPage {
-    fn before(&self, req: &mut Request) -> SapperResult<()> {
-        let (path, _) = req.uri();
-        if check_cache_switch(req) {
-            if &path == "/" {
-                if cache::cache_is_valid("index", "index") {
-                    let cache_content = cache::cache_get("index", "index");
+        let user = match get_ext!(req, AppUser) {
+            Some(user) => user,
+            None => return res_redirect!("/account"),
+        };
 
-                    splog(req, status::Ok).unwrap();
-                    return res_html_before!(cache_content);
-                }
-            }
-        }
+        let napp = envconfig::get_int_item("BIG_NUMBER_ARTICLE_PER_PAGE");
+        let total_item = FollowingFeed::count(user.id);
+        let total_page = ((total_item - 1) / napp) as i64 + 1;
+
+        let articles = FollowingFeed::get_paging(user.id, current_page, napp);
 
+        web.insert("is_login", &true);
+        web.insert("is_admin", &(user.role >= 9));
+        web.insert("total_item", &total_item);
+        web.insert("total_page", &total_page);
+        web.insert("current_page", &current_page);
+        web.insert("articles", &articles);
+        web.insert("this_page_url", "following");
+        web.insert("s_title", "Following");
+
+        res_html!("forum/article_list_paging.html", web)
+    }
+
+}
+
+
+impl SapperModule for IndexPage {
+    fn before(&self, _req: &mut Request) -> SapperResult<()> {
         Ok(())
     }
 
     fn after(&self, req: &Request, res: &mut Response) -> SapperResult<()> {
-        let (path, _) = req.uri();
-        if &path == "/" {
-            if !cache::cache_is_valid("index", "index") {
-                cache::cache_set("index", "index", res.body());
-            }
-        }
+        page_cache::store(req, res)?;
 
         Ok(())
     }
@@ -269,8 +314,11 @@ impl SapperModule for IndexPage {
         router.get("/latest_articles_paging", Self::latest_articles_paging);
         router.get("/latest_reply_articles_paging", Self::latest_reply_articles_paging);
         router.get("/latest_blog_articles_paging", Self::latest_blog_articles_paging);
+        router.get("/following", Self::following_articles_paging);
 
-        router.get("/rss", Self::rss_xml);
+        router.get("/rss", Self::site_feed);
+        router.get("/atom", Self::site_feed);
+        router.get("/feed.json", Self::site_feed);
         router.get("/search", Self::search_query_page);
         router.post("/search", Self::search_query);
         router.get("/acknowledgement", Self::acknowledgement);
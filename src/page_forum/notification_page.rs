@@ -0,0 +1,93 @@
+use sapper::{
+    Request,
+    Response,
+    Result as SapperResult,
+    Error as SapperError,
+    Module as SapperModule,
+    Router as SapperRouter};
+use sapper_std::*;
+use uuid::Uuid;
+
+// introduce macros
+use sapper_std::res_html;
+use crate::{
+    AppWebContext,
+    AppUser
+};
+use crate::envconfig;
+use crate::model::notification::Notification;
+use crate::middleware::permission_need_login;
+
+pub struct NotificationPage;
+
+impl NotificationPage {
+
+    pub fn notifications_page(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let params = get_query_params!(req);
+        let current_page = t_param_parse_default!(params, "current_page", i64, 1);
+
+        let user = match get_ext!(req, AppUser) {
+            Some(user) => user,
+            None => return res_redirect!("/account"),
+        };
+
+        let napp = envconfig::get_int_item("BIG_NUMBER_ARTICLE_PER_PAGE");
+        let total_item = Notification::count_by_user(user.id);
+        let total_page = ((total_item - 1) / napp) as i64 + 1;
+
+        let notifications = Notification::get_paging_by_user(user.id, current_page, napp);
+
+        web.insert("notifications", &notifications);
+        web.insert("total_item", &total_item);
+        web.insert("total_page", &total_page);
+        web.insert("current_page", &current_page);
+
+        res_html!("forum/notifications.html", web)
+    }
+
+    // mark it read on the way to what it is about
+    pub fn notification_open(req: &mut Request) -> SapperResult<Response> {
+        let params = get_query_params!(req);
+        let id = t_param_parse!(params, "id", Uuid);
+        let user = get_ext!(req, AppUser).unwrap();
+
+        match Notification::mark_read(id, user.id) {
+            Ok(notification) => {
+                match notification.comment_id {
+                    Some(comment_id) => res_redirect!(format!("/article?id={}#comment-{}", notification.article_id, comment_id)),
+                    None => res_redirect!(format!("/article?id={}", notification.article_id)),
+                }
+            },
+            Err(info) => {
+                res_400!(info)
+            }
+        }
+    }
+
+    pub fn notification_read_all(req: &mut Request) -> SapperResult<Response> {
+        let user = get_ext!(req, AppUser).unwrap();
+
+        Notification::mark_all_read(user.id);
+
+        res_redirect!("/notifications")
+    }
+
+}
+
+
+impl SapperModule for NotificationPage {
+    fn before(&self, req: &mut Request) -> SapperResult<()> {
+        permission_need_login(req)?;
+
+        Ok(())
+    }
+
+    fn router(&self, router: &mut SapperRouter) -> SapperResult<()> {
+        router.get("/notifications", Self::notifications_page);
+        router.get("/p/notification/open", Self::notification_open);
+        router.post("/s/notification/read_all", Self::notification_read_all);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use sapper::{
+    Request,
+    Response,
+    Result as SapperResult,
+    Error as SapperError,
+    Module as SapperModule,
+    Router as SapperRouter};
+use sapper_std::*;
+use uuid::Uuid;
+
+// introduce macros
+use sapper_std::res_html;
+use crate::{
+    AppWebContext,
+    AppUser
+};
+use crate::envconfig;
+
+use crate::model::trash::{
+    ArticleRef,
+    CommentRef,
+    STATUS_HIDDEN
+};
+use crate::model::report::{
+    Report,
+    ReportCreate
+};
+use crate::page_forum::admin_page::apply_visibility;
+use crate::middleware::permission_need_login;
+
+pub struct ReportPage;
+
+impl ReportPage {
+
+    pub fn report_new_page(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let params = get_query_params!(req);
+        let kind = t_param!(params, "kind").to_owned();
+        let id = t_param_parse!(params, "id", Uuid);
+
+        web.insert("kind", &kind);
+        web.insert("id", &id);
+
+        res_html!("forum/new_report.html", web)
+    }
+
+    pub fn report_new(req: &mut Request) -> SapperResult<Response> {
+        let params = get_form_params!(req);
+        let kind = t_param!(params, "kind").to_owned();
+        let id = t_param_parse!(params, "id", Uuid);
+        let reason = t_param!(params, "reason").trim().to_owned();
+        if reason == "" {
+            return res_400!("please tell us the reason.");
+        }
+        let user = get_ext!(req, AppUser).unwrap();
+
+        let article_id = match kind.as_str() {
+            "article" => {
+                match ArticleRef::get_by_id(id) {
+                    Ok(article_ref) => article_ref.id,
+                    Err(info) => return res_400!(info),
+                }
+            },
+            "comment" => {
+                match CommentRef::get_by_id(id) {
+                    Ok(comment_ref) => comment_ref.article_id,
+                    Err(info) => return res_400!(info),
+                }
+            },
+            _ => {
+                return res_400!("unknown report target.");
+            }
+        };
+
+        let report_create = ReportCreate {
+            target_kind: kind.clone(),
+            target_id: id,
+            article_id,
+            reporter_id: user.id,
+            reason,
+        };
+
+        match report_create.insert() {
+            Ok(_) => {
+                // enough people complained, hide it until a moderator looks
+                let threshold = envconfig::get_int_item("REPORT_HIDE_THRESHOLD");
+                if threshold > 0 && Report::open_count_of(id) >= threshold {
+                    let _ = apply_visibility(req, &kind, id, STATUS_HIDDEN);
+                }
+
+                res_redirect!(format!("/article?id={}", article_id))
+            },
+            Err(info) => {
+                res_400!(info)
+            }
+        }
+    }
+
+}
+
+
+impl SapperModule for ReportPage {
+    fn before(&self, req: &mut Request) -> SapperResult<()> {
+        permission_need_login(req)?;
+
+        Ok(())
+    }
+
+    fn router(&self, router: &mut SapperRouter) -> SapperResult<()> {
+        router.get("/p/report/new", Self::report_new_page);
+        router.post("/s/report/new", Self::report_new);
+
+        Ok(())
+    }
+}
@@ -8,9 +8,10 @@ use sapper::{
     Router as SapperRouter};
 use sapper_std::*;
 use uuid::Uuid;
+use chrono::{Utc, Duration};
 
 use crate::db;
-use crate::cache;
+use crate::page_cache::{self, Change};
 // introduce macros
 use sapper_std::res_html;
 use crate::{
@@ -34,9 +35,21 @@ use crate::middleware::{
     permission_need_be_admin,
     permission_need_login,
     is_admin,
-    check_cache_switch,
 };
 use crate::envconfig;
+use crate::seo::absolute_url;
+use crate::model::activity::Activity;
+use crate::conditional::{
+    res_cached_html,
+    set_last_modified
+};
+use crate::model::listing::Listing;
+use crate::model::vote::Vote;
+use crate::model::qa::Qa;
+use crate::model::follow::{
+    SectionFollow,
+    UserFollow
+};
 
 
 pub struct SectionPage;
@@ -72,6 +85,9 @@ impl SectionPage {
 
 	    if is_admin(req) {
 		let section = Section::get_by_id(section_id).unwrap();
+		// blogs are no Q&A
+		web.insert("can_set_qa", &(section.stype == 0));
+		web.insert("is_qa", &Qa::is_qa_section(section.id));
 		web.insert("section", &section);
 		res_html!("forum/edit_section.html", web)
 	    }
@@ -112,8 +128,16 @@ impl SectionPage {
 	else {
 	    t_param_parse!(params, "id", Uuid)
 	};
+	let last_modified = Activity::of_section(section_id);
+	if let Some(cache_content) = page_cache::lookup_page(req)? {
+	    return res_cached_html(cache_content, last_modified);
+	}
 
 	let current_page = t_param_parse_default!(params, "current_page", i64, 1);
+	// "top" orders by score, over the last day, week, or all time,
+	// "unanswered" keeps the questions without an accepted answer
+	let sort = t_param_default!(params, "sort", "latest").to_owned();
+	let window = t_param_default!(params, "window", "week").to_owned();
 
 	let section_result = Section::get_by_id(section_id);
 	if section_result.is_err() {
@@ -125,6 +149,12 @@ impl SectionPage {
 	if section.stype == 1 {
 	    is_a_blog = true;
 	}
+	let is_qa = Qa::is_qa_section(section.id);
+	let sort = match sort.as_str() {
+	    "top" => sort,
+	    "unanswered" if is_qa => sort,
+	    _ => "latest".to_string(),
+	};
 	let mut is_myown_blog = false;
 	let mut is_admin = false;
 	let mut is_login = false;
@@ -145,21 +175,73 @@ impl SectionPage {
 	}
 
 	let napp = envconfig::get_int_item("NUMBER_ARTICLE_PER_PAGE");
-	let total_item = Section::get_articles_count_belong_to_this(section.id);
-	let total_page = ((total_item - 1) / napp) as i64 + 1;
+	// the sorted listings come as plain article rows, the default one with stats
+	if sort == "top" {
+	    let since = match window.as_str() {
+		"day" => Some(Utc::now() - Duration::days(1)),
+		"all" => None,
+		_ => Some(Utc::now() - Duration::weeks(1)),
+	    };
+	    let total_item = Vote::top_in_section_count(section.id, since);
+	    let total_page = ((total_item - 1) / napp) as i64 + 1;
+	    let plain_articles = Vote::top_in_section(section.id, since, current_page, napp);
+
+	    web.insert("plain_articles", &plain_articles);
+	    web.insert("total_item", &total_item);
+	    web.insert("total_page", &total_page);
+	}
+	else if sort == "unanswered" {
+	    let total_item = Qa::unanswered_in_section_count(section.id);
+	    let total_page = ((total_item - 1) / napp) as i64 + 1;
+	    let plain_articles = Qa::unanswered_in_section(section.id, current_page, napp);
+
+	    web.insert("plain_articles", &plain_articles);
+	    web.insert("total_item", &total_item);
+	    web.insert("total_page", &total_page);
+	}
+	else {
+	    let total_item = Listing::section_count(section.id);
+	    let total_page = ((total_item - 1) / napp) as i64 + 1;
+	    let articles = Listing::in_section(section.id, current_page, napp);
 
-	let articles = Section::get_articles_paging_belong_to_this(section.id, current_page);
+	    web.insert("articles", &articles);
+	    web.insert("total_item", &total_item);
+	    web.insert("total_page", &total_page);
+	}
+
+	// following a blog is following its author
+	let (follow_kind, follow_id, followers) = match section.suser {
+	    Some(suser) if is_a_blog => ("user", suser, UserFollow::followers_count(suser)),
+	    _ => ("section", section.id, SectionFollow::followers_count(section.id)),
+	};
 
 	web.insert("section", &section);
+	web.insert("follow_kind", follow_kind);
+	web.insert("follow_id", &follow_id);
+	web.insert("followers", &followers);
 	web.insert("is_a_blog", &is_a_blog);
 	web.insert("is_myown_blog", &is_myown_blog);
 	web.insert("is_admin", &is_admin);
-	web.insert("total_item", &total_item);
-	web.insert("total_page", &total_page);
+	web.insert("is_qa", &is_qa);
 	web.insert("current_page", &current_page);
-	web.insert("articles", &articles);
+	web.insert("sort", &sort);
+	web.insert("window", &window);
 
-	res_html!("forum/section.html", web)
+	// a blog is canonical at its author, the sorted listings at the plain one
+	let mut canonical_path = match section.suser {
+	    Some(suser) if is_a_blog => format!("/blog_with_author?author_id={}", suser),
+	    _ => format!("/section?id={}", section.id),
+	};
+	if current_page > 1 {
+	    canonical_path = format!("{}&current_page={}", canonical_path, current_page);
+	}
+	web.insert("canonical_url", &absolute_url(&canonical_path));
+	web.insert("meta_description", &section.description);
+
+	res_html!("forum/section.html", web).map(|mut response| {
+	    set_last_modified(&mut response, last_modified);
+	    response
+	})
     }
 
 
@@ -177,6 +259,9 @@ impl SectionPage {
 
 	match section_new.create() {
 	    Ok(section) => {
+		if t_has_param!(params, "is_qa") {
+		    let _ = Qa::set_qa_section(section.id, true);
+		}
 		res_redirect!(format!("/section?id={}", section.id))
 	    },
 	    Err(_) => {
@@ -186,6 +271,7 @@ impl SectionPage {
     }
 
     pub fn section_edit(req: &mut Request) -> SapperResult<Response> {
+	let is_admin = is_admin(req);
 	let params = get_form_params!(req);
 	let id = t_param_parse!(params, "id", Uuid);
 	let title = t_param!(params, "title").to_owned();
@@ -199,6 +285,10 @@ impl SectionPage {
 
 	match section_edit.update() {
 	    Ok(section) => {
+		// only admins see the Q&A box
+		if is_admin && section.stype == 0 {
+		    let _ = Qa::set_qa_section(section.id, t_has_param!(params, "is_qa"));
+		}
 		res_redirect!(format!("/section?id={}", section.id))
 	    },
 	    Err(_) => {
@@ -299,21 +389,6 @@ impl SectionPage {
 
 impl SapperModule for SectionPage {
     fn before(&self, req: &mut Request) -> SapperResult<()> {
-	// check cache
-	if check_cache_switch(req) {
-	    let (path, _) = req.uri();
-	    if &path == "/section" || &path == "/blog" {
-		let params = get_query_params!(req);
-		let section_id = t_param!(params, "id");
-		let current_page = t_param_parse_default!(params, "current_page", i64, 1);
-		let part_key = section_id.to_string() + ":" + &current_page.to_string();
-		if cache::cache_is_valid("section", &part_key) {
-		    let cache_content = cache::cache_get("section", &part_key);
-		    return res_html_before!(cache_content);
-		}
-	    }
-	}
-
 	// permission
 	permission_need_login(req)?;
 
@@ -323,29 +398,21 @@ impl SapperModule for SectionPage {
     fn after(&self, req: &Request, res: &mut Response) -> SapperResult<()> {
 	let (path, _) = req.uri();
 	if &path == "/s/section/create"
-	    || &path == "/s/section/edit"
-	    || &path == "/s/section/rearrange" {
+	    || &path == "/s/section/rearrange"
+	    || &path == "/s/section/manage_article_view/add"
+	    || &path == "/s/section/manage_article_view/delete" {
 
-	    cache::cache_set_invalid("index", "index");
-	}
-
-	// check other url
-	if &path == "/section" || &path == "/blog" {
-	    let params = get_query_params!(req);
-	    let section_id = t_param!(params, "id");
-	    let current_page = t_param_parse_default!(params, "current_page", i64, 1);
-	    let part_key = section_id.to_string() + ":" + &current_page.to_string();
-	    if !cache::cache_is_valid("section", &part_key) {
-		cache::cache_set("section", &part_key, res.body());
-	    }
+	    page_cache::invalidate(req, Change::SectionList);
 	}
 
 	if &path == "/s/section/edit" {
 	    let params = get_form_params!(req);
-	    let section_id = t_param!(params, "id");
-	    cache::cache_set_invalid("section", section_id);
+	    let section_id = t_param_parse!(params, "id", Uuid);
+	    page_cache::invalidate(req, Change::Section { section_id });
 	}
 
+	page_cache::store(req, res)?;
+
 	Ok(())
     }
 
@@ -0,0 +1,124 @@
+use sapper::{
+    header::ContentType,
+    Request,
+    Response,
+    Result as SapperResult,
+    Error as SapperError,
+    Module as SapperModule,
+    Router as SapperRouter};
+use sapper_std::*;
+
+use crate::envconfig;
+use crate::feed::xml_escape;
+use crate::seo::{
+    article_path,
+    absolute_url
+};
+use crate::model::sitemap::Sitemap;
+
+// the protocol allows 50000 urls a file, smaller ones are cheaper to build
+const SITEMAP_CHUNK_SIZE: i64 = 10000;
+
+const SITEMAP_NS: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+
+fn url_entry(path: &str, lastmod: Option<String>) -> String {
+    let lastmod = lastmod.map(|time| format!("<lastmod>{}</lastmod>", time)).unwrap_or_default();
+    format!("<url><loc>{}</loc>{}</url>", xml_escape(&absolute_url(path)), lastmod)
+}
+
+pub struct SeoPage;
+
+impl SeoPage {
+
+    // the index of the sitemap chunks, the one to submit to crawlers
+    pub fn sitemap_index(_req: &mut Request) -> SapperResult<Response> {
+        let total_item = Sitemap::articles_count();
+        let total_chunk = (total_item - 1) / SITEMAP_CHUNK_SIZE + 1;
+
+        let mut sitemaps = format!("<sitemap><loc>{}</loc></sitemap>", xml_escape(&absolute_url("/sitemap/sections.xml")));
+        for chunk in 1..=total_chunk {
+            let path = format!("/sitemap/articles/{}.xml", chunk);
+            sitemaps.push_str(&format!("<sitemap><loc>{}</loc></sitemap>", xml_escape(&absolute_url(&path))));
+        }
+
+        res_xml_string!(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><sitemapindex xmlns=\"{}\">{}</sitemapindex>",
+            SITEMAP_NS,
+            sitemaps
+        ))
+    }
+
+    pub fn sitemap_sections(_req: &mut Request) -> SapperResult<Response> {
+        let mut urls = url_entry("/", None);
+        for section in Sitemap::sections() {
+            // a blog is found by its author
+            let path = match (section.stype, section.suser) {
+                (1, Some(author_id)) => format!("/blog_with_author?author_id={}", author_id),
+                _ => format!("/section?id={}", section.id),
+            };
+            urls.push_str(&url_entry(&path, None));
+        }
+
+        res_xml_string!(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><urlset xmlns=\"{}\">{}</urlset>",
+            SITEMAP_NS,
+            urls
+        ))
+    }
+
+    // `/sitemap/articles/:chunk`, with the chunk as "3.xml"
+    pub fn sitemap_articles(req: &mut Request) -> SapperResult<Response> {
+        let params = get_path_params!(req);
+        let chunk = match t_param!(params, "chunk").trim_end_matches(".xml").parse::<i64>() {
+            Ok(chunk) if chunk >= 1 => chunk,
+            _ => return res_400!("no this sitemap"),
+        };
+
+        let mut urls = String::new();
+        for article in Sitemap::articles_chunk(chunk, SITEMAP_CHUNK_SIZE) {
+            let lastmod = article.lastmod.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+            urls.push_str(&url_entry(&article_path(article.id, &article.title), Some(lastmod)));
+        }
+
+        res_xml_string!(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><urlset xmlns=\"{}\">{}</urlset>",
+            SITEMAP_NS,
+            urls
+        ))
+    }
+
+    // lets browsers add the forum search to their search bar
+    pub fn opensearch(_req: &mut Request) -> SapperResult<Response> {
+        let title = envconfig::get_str_item("SITE_TITLE");
+        let body = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><OpenSearchDescription xmlns=\"http://a9.com/-/spec/opensearch/1.1/\"><ShortName>{}</ShortName><Description>Search {}</Description><InputEncoding>UTF-8</InputEncoding><Image width=\"16\" height=\"16\" type=\"image/x-icon\">{}</Image><Url type=\"text/html\" method=\"get\" template=\"{}\"/></OpenSearchDescription>",
+            xml_escape(&title.chars().take(16).collect::<String>()),
+            xml_escape(&title),
+            xml_escape(&absolute_url("/favicon.ico")),
+            xml_escape(&absolute_url("/search?q={searchTerms}")),
+        );
+
+        let mut response = Response::new();
+        response.headers_mut().set(ContentType("application/opensearchdescription+xml; charset=utf-8".parse().unwrap()));
+        response.write_body(body);
+
+        Ok(response)
+    }
+
+}
+
+
+impl SapperModule for SeoPage {
+    fn before(&self, _req: &mut Request) -> SapperResult<()> {
+        Ok(())
+    }
+
+    fn router(&self, router: &mut SapperRouter) -> SapperResult<()> {
+        router.get("/sitemap.xml", Self::sitemap_index);
+        router.get("/sitemap/sections.xml", Self::sitemap_sections);
+        router.get("/sitemap/articles/:chunk", Self::sitemap_articles);
+        router.get("/opensearch.xml", Self::opensearch);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use sapper::{
+    Request,
+    Response,
+    Result as SapperResult,
+    Error as SapperError,
+    Module as SapperModule,
+    Router as SapperRouter};
+use sapper_std::*;
+
+// introduce macros
+use sapper_std::res_html;
+use crate::{
+    AppWebContext,
+    AppUser
+};
+use crate::envconfig;
+use crate::model::tag::{
+    Tag,
+    normalize_tag
+};
+
+// path params come percent encoded for non ascii tags
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&out).to_string()
+}
+
+pub struct TagPage;
+
+impl TagPage {
+
+    pub fn tag_detail_page(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let name = {
+            let params = get_path_params!(req);
+            percent_decode(t_param!(params, "name"))
+        };
+        let params = get_query_params!(req);
+        let current_page = t_param_parse_default!(params, "current_page", i64, 1);
+
+        let tag = match normalize_tag(&name).ok_or(()).and_then(|name| Tag::get_by_name(&name).map_err(|_| ())) {
+            Ok(tag) => tag,
+            Err(_) => return res_400!("no this tag"),
+        };
+
+        let mut is_admin = false;
+        let mut is_login = false;
+        match get_ext!(req, AppUser) {
+            Some(user) => {
+                if user.role >= 9 {
+                    is_admin = true;
+                }
+
+                is_login = true;
+                web.insert("is_login", &is_login);
+                web.insert("user", &user);
+            },
+            None => {}
+        }
+
+        let napp = envconfig::get_int_item("NUMBER_ARTICLE_PER_PAGE");
+        let total_item = Tag::get_articles_count(tag.id);
+        let total_page = ((total_item - 1) / napp) as i64 + 1;
+
+        let articles = Tag::get_articles_paging(tag.id, current_page, napp);
+
+        web.insert("tag", &tag);
+        web.insert("is_admin", &is_admin);
+        web.insert("total_item", &total_item);
+        web.insert("total_page", &total_page);
+        web.insert("current_page", &current_page);
+        web.insert("articles", &articles);
+
+        res_html!("forum/tag.html", web)
+    }
+
+}
+
+
+impl SapperModule for TagPage {
+    fn before(&self, _req: &mut Request) -> SapperResult<()> {
+        Ok(())
+    }
+
+    fn router(&self, router: &mut SapperRouter) -> SapperResult<()> {
+        router.get("/tag/:name", Self::tag_detail_page);
+
+        Ok(())
+    }
+}
@@ -7,6 +7,7 @@ use sapper::{
     Router as SapperRouter};
 use sapper_std::*;
 use uuid::Uuid;
+use serde_json::json;
 
 use crate::db;
 use crate::github_utils::{
@@ -17,12 +18,19 @@ use crate::github_utils::{
 use crate::envconfig;
 use crate::util::make_pwd_encode;
 use crate::util::random_string;
-use crate::middleware::permission_need_login;
+use crate::middleware::{
+    permission_need_login,
+    totp_required,
+};
+use crate::totp;
+use crate::mailer::queue_email_confirmation;
+use crate::rate_limit::{self, RateAction};
 
 // introduce macros
 use crate::{
     AppWebContext,
-    AppUser
+    AppUser,
+    PendingTotpLogins
 };
 
 use crate::dataservice::user::{
@@ -34,9 +42,66 @@ use crate::dataservice::user::{
     UserChangePassword,
 };
 
-use crate::dataservice::article::{
-    Article
+use crate::model::notification::{
+    NotificationPref,
+    NotificationPrefUpdate
+};
+use crate::dataservice::section::Section;
+use crate::model::listing::Listing;
+use crate::model::email_pref::{
+    EmailPref,
+    EmailPrefUpdate,
+    EmailAddressUpdate,
+    DIGEST_OFF
+};
+use crate::model::follow::{
+    SectionFollow,
+    SectionFollowUpdate
+};
+use crate::model::draft::Draft;
+use crate::model::bookmark::Bookmark;
+use crate::model::api_token::{
+    ApiToken,
+    ApiTokenCreate,
+    ALL_SCOPES,
 };
+use crate::model::user_totp::{
+    UserTotp,
+    UserTotpCreate,
+    UserTotpEnable,
+};
+
+fn login_response(cookie: String, redirect_to: &str) -> SapperResult<Response> {
+    let mut response = Response::new();
+    let _ = set_cookie(
+        &mut response,
+        "rusoda_session".to_string(),
+        cookie,
+        None,
+        Some("/".to_string()),
+        None,
+        Some(60*24*3600),
+    );
+
+    set_response_redirect!(response, redirect_to);
+
+    Ok(response)
+}
+
+// the password or github step passed, the session waits for the code
+fn totp_login_step(req: &Request, user_id: Uuid) -> SapperResult<Response> {
+    let token = Uuid::new_v4().to_string();
+    let pending = get_ext!(req, PendingTotpLogins).unwrap();
+    {
+        let mut pending = pending.lock().unwrap();
+        pending.retain(|_, p| !p.is_expired());
+        pending.insert(token.clone(), totp::PendingLogin::new(user_id));
+    }
+
+    let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+    web.insert("token", &token);
+    res_html!("forum/login_totp.html", web)
+}
 
 pub struct UserPage;
 
@@ -61,7 +126,28 @@ impl UserPage {
         let mut web = get_ext_owned!(req, AppWebContext).unwrap();
         match get_ext!(req, AppUser) {
             Some(user) => {
+                let notification_prefs = NotificationPref::get_by_user(user.id);
+                let email_pref = EmailPref::get_by_user(user.id).ok();
+                let followed = SectionFollow::sections_of(user.id);
+                let sections: Vec<serde_json::Value> = Section::forum_sections().iter()
+                    .map(|section| json!({
+                        "id": section.id,
+                        "title": section.title,
+                        "followed": followed.contains(&section.id),
+                    }))
+                    .collect();
+
                 web.insert("user", &user);
+                web.insert("notification_prefs", &notification_prefs);
+                web.insert("email_pref", &email_pref);
+                // accounts made with an email address suggest it, to be confirmed still
+                let email = match email_pref {
+                    Some(ref pref) if pref.email != "" => pref.email.clone(),
+                    _ if user.account.contains('@') => user.account.clone(),
+                    _ => "".to_string(),
+                };
+                web.insert("email", &email);
+                web.insert("sections", &sections);
                 return res_html!("forum/account.html", web);
             },
             None => {
@@ -99,6 +185,21 @@ impl UserPage {
         let account = t_param!(params, "account").to_owned();
         let password = t_param!(params, "password").to_owned();
 
+        // check password before any session is made, a totp step may follow
+        let user = match Ruser::get_user_by_account(&account) {
+            Ok(user) => user,
+            Err(_) => {
+                return res_redirect!("/login_with_admin");
+            }
+        };
+        if user.password != make_pwd_encode(&password, &user.salt) {
+            return res_redirect!("/login_with_admin");
+        }
+
+        if UserTotp::is_enabled_for(user.id) {
+            return totp_login_step(req, user.id);
+        }
+
         let user_login = UserLogin {
             account,
             password
@@ -111,21 +212,70 @@ impl UserPage {
         }
         let cookie = cookie_r.unwrap();
 
-        let mut response = Response::new();
-        let _ = set_cookie(
-            &mut response,
-            "rusoda_session".to_string(),
-            cookie,
-            None,
-            Some("/".to_string()),
-            None,
-            Some(60*24*3600),
-        );
+        // redirect to index, or to enrollment when this role must use 2fa
+        if totp_required(&user) {
+            login_response(cookie, "/p/user/totp?required=1")
+        }
+        else {
+            login_response(cookie, "/")
+        }
+    }
 
-        // redirect to index
-        set_response_redirect!(response, "/");
+    pub fn user_login_totp(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let params = get_form_params!(req);
+        let token = t_param!(params, "token").to_owned();
+        let code = t_param!(params, "code").to_owned();
+
+        let pending = get_ext!(req, PendingTotpLogins).unwrap();
+        let user_id = {
+            let mut pending = pending.lock().unwrap();
+            match pending.get_mut(&token) {
+                Some(p) if !p.is_expired() => {
+                    p.attempts += 1;
+                    p.user_id
+                },
+                _ => {
+                    pending.remove(&token);
+                    return res_redirect!("/login_with_admin");
+                }
+            }
+        };
+
+        let user = match Ruser::get_user_by_id(user_id) {
+            Ok(user) => user,
+            Err(_) => {
+                return res_redirect!("/login_with_admin");
+            }
+        };
+        let user_totp = match UserTotp::get_by_user_id(user.id) {
+            Ok(user_totp) => user_totp,
+            Err(_) => {
+                return res_redirect!("/login_with_admin");
+            }
+        };
 
-        Ok(response)
+        // a 6 digits code, or one of the recovery codes
+        let passed = totp::verify_code(&user_totp.secret, &code)
+            || user_totp.consume_recovery_code(&code, &user.salt);
+        if !passed {
+            web.insert("token", &token);
+            web.insert("error", "Wrong authentication code.");
+            return res_html!("forum/login_totp.html", web);
+        }
+
+        pending.lock().unwrap().remove(&token);
+
+        let user_login = UserLogin {
+            account: user.account.clone(),
+            password: user.password.clone()
+        };
+        let cookie_r = user_login.verify_login_with_rawpwd();
+        if cookie_r.is_err() {
+            return res_redirect!("/login_with_admin");
+        }
+
+        login_response(cookie_r.unwrap(), "/")
     }
 
     pub fn user_login_with_github(req: &mut Request) -> SapperResult<Response> {
@@ -150,7 +300,10 @@ impl UserPage {
 
         match Ruser::get_user_by_account(&account) {
             Ok(user) => {
-                // already exists
+                // already exists, github stands in for the password only
+                if UserTotp::is_enabled_for(user.id) {
+                    return totp_login_step(req, user.id);
+                }
                 password = user.password;
                 // next step auto login
                 let user_login = UserLogin {
@@ -178,21 +331,8 @@ impl UserPage {
             }
         }
 
-        let mut response = Response::new();
-        let _ = set_cookie(
-            &mut response,
-            "rusoda_session".to_string(),
-            cookie,
-            None,
-            Some("/".to_string()),
-            None,
-            Some(60*24*3600),
-        );
-
         // redirect to index
-        set_response_redirect!(response, "/");
-
-        Ok(response)
+        login_response(cookie, "/")
     }
 
 
@@ -287,10 +427,10 @@ impl UserPage {
         }
 
         let napp = envconfig::get_int_item("BIG_NUMBER_ARTICLE_PER_PAGE");
-        let total_item = Article::get_all_articles_count_by_author(user_id);
+        let total_item = Listing::author_count(user_id);
         let total_page = ((total_item - 1) / napp) as i64 + 1;
 
-        let articles = Article::get_latest_articles_paging_by_author(user_id, current_page-1, napp);
+        let articles = Listing::by_author(user_id, current_page, napp);
 
         web.insert("is_admin", &is_admin);
         web.insert("total_item", &total_item);
@@ -303,6 +443,346 @@ impl UserPage {
         res_html!("forum/article_list_paging.html", web)
     }
 
+    pub fn user_bookmarks_page(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let params = get_query_params!(req);
+
+        let current_page = t_param_parse_default!(params, "current_page", i64, 1);
+
+        let mut is_admin = false;
+        let mut is_login = false;
+        let mut user_id: Uuid = Default::default();
+        match get_ext!(req, AppUser) {
+            Some(user) => {
+                user_id = user.id;
+
+                if user.role >= 9 {
+                    is_admin = true;
+                }
+
+                is_login = true;
+                web.insert("is_login", &is_login);
+                web.insert("user", &user);
+            },
+            None => {}
+        }
+
+        let napp = envconfig::get_int_item("BIG_NUMBER_ARTICLE_PER_PAGE");
+        let total_item = Bookmark::count_by_user(user_id);
+        let total_page = ((total_item - 1) / napp) as i64 + 1;
+
+        let articles = Bookmark::get_paging_by_user(user_id, current_page, napp);
+
+        web.insert("is_admin", &is_admin);
+        web.insert("total_item", &total_item);
+        web.insert("total_page", &total_page);
+        web.insert("current_page", &current_page);
+        web.insert("articles", &articles);
+        web.insert("this_page_url", "p/user/bookmarks");
+        web.insert("s_title", "My Bookmarks");
+
+        res_html!("forum/article_list_paging.html", web)
+    }
+
+    pub fn user_notification_prefs(req: &mut Request) -> SapperResult<Response> {
+        let params = get_form_params!(req);
+        // the form always sends a 0, so unchecking every box still posts the field
+        let enabled = t_arr_param!(params, "ntype")
+            .iter()
+            .filter_map(|ntype| ntype.parse::<i16>().ok())
+            .collect();
+        let user = get_ext!(req, AppUser).unwrap();
+
+        let pref_update = NotificationPrefUpdate {
+            user_id: user.id,
+            enabled,
+        };
+
+        match pref_update.update() {
+            Ok(_) => {
+                res_redirect!("/account")
+            },
+            Err(info) => {
+                res_500!(info)
+            }
+        }
+    }
+
+    pub fn user_email_prefs(req: &mut Request) -> SapperResult<Response> {
+        let params = get_form_params!(req);
+        let email_replies = t_has_param!(params, "email_replies");
+        let email_mentions = t_has_param!(params, "email_mentions");
+        let digest = t_param_parse_default!(params, "digest", i16, DIGEST_OFF);
+        let email = t_param_default!(params, "email", "").trim().to_owned();
+        if email != "" && (!email.contains('@') || email.contains(char::is_whitespace)) {
+            return res_400!("not an email address.");
+        }
+        // the form always sends an empty one, like the notification preferences
+        let section_ids = t_arr_param!(params, "section_id")
+            .iter()
+            .filter_map(|id| id.parse::<Uuid>().ok())
+            .collect();
+        let user_id = get_ext!(req, AppUser).unwrap().id;
+
+        let pref_update = EmailPrefUpdate {
+            user_id,
+            email_replies,
+            email_mentions,
+            digest,
+        };
+        let pref = match pref_update.update() {
+            Ok(pref) => pref,
+            Err(info) => return res_500!(info),
+        };
+
+        // a changed address is only mailed once confirmed from its mailbox
+        if email != pref.email {
+            if let Some(wait) = rate_limit::check(req, RateAction::Edit) {
+                return rate_limit::slow_down_page(req, wait);
+            }
+            let address_update = EmailAddressUpdate {
+                user_id,
+                email: email.clone(),
+            };
+            if let Err(info) = address_update.update() {
+                return res_500!(info);
+            }
+            if email != "" {
+                queue_email_confirmation(req, user_id);
+            }
+        }
+
+        let follow_update = SectionFollowUpdate {
+            user_id,
+            section_ids,
+        };
+
+        match follow_update.update() {
+            Ok(_) => {
+                res_redirect!("/account#email_prefs")
+            },
+            Err(info) => {
+                res_500!(info)
+            }
+        }
+    }
+
+    // the link in every email, works without login
+    pub fn unsubscribe_page(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let params = get_query_params!(req);
+        let token = t_param!(params, "token").to_owned();
+
+        if EmailPref::get_by_token(&token).is_err() {
+            return res_400!("no this unsubscribe link.");
+        }
+
+        web.insert("token", &token);
+        web.insert("done", &false);
+
+        res_html!("forum/unsubscribe.html", web)
+    }
+
+    // posted by the page above, or straight by mail clients (List-Unsubscribe-Post)
+    pub fn unsubscribe(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let params = get_query_params!(req);
+        let token = t_param!(params, "token").to_owned();
+
+        match EmailPref::unsubscribe(&token) {
+            Ok(_) => {
+                web.insert("token", &token);
+                web.insert("done", &true);
+
+                res_html!("forum/unsubscribe.html", web)
+            },
+            Err(info) => {
+                res_400!(info)
+            }
+        }
+    }
+
+    // the link of the confirmation mail, works without login
+    pub fn confirm_email(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let params = get_query_params!(req);
+        let token = t_param!(params, "token").to_owned();
+
+        match EmailPref::confirm(&token) {
+            Ok(pref) => {
+                web.insert("email", &pref.email);
+
+                res_html!("forum/confirm_email.html", web)
+            },
+            Err(info) => {
+                res_400!(info)
+            }
+        }
+    }
+
+    pub fn user_my_drafts_page(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let user = get_ext!(req, AppUser).unwrap();
+
+        let drafts = Draft::get_drafts_by_user(user.id);
+
+        web.insert("drafts", &drafts);
+
+        res_html!("forum/my_drafts.html", web)
+    }
+
+    pub fn user_api_tokens_page(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let user = get_ext!(req, AppUser).unwrap();
+
+        let tokens = ApiToken::get_tokens_by_user(user.id);
+
+        web.insert("tokens", &tokens);
+        web.insert("all_scopes", &ALL_SCOPES);
+
+        res_html!("forum/user_api_tokens_page.html", web)
+    }
+
+    pub fn user_api_token_create(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let params = get_form_params!(req);
+        let name = t_param!(params, "name").to_owned();
+        let scopes: Vec<String> = if t_has_param!(params, "scopes") {
+            t_arr_param!(params, "scopes").iter().map(|s| s.to_string()).collect()
+        }
+        else {
+            Vec::new()
+        };
+        let user = get_ext!(req, AppUser).unwrap();
+
+        let api_token_create = ApiTokenCreate {
+            user_id: user.id,
+            name,
+            scopes,
+        };
+
+        match api_token_create.insert() {
+            Ok((_, raw_token)) => {
+                let tokens = ApiToken::get_tokens_by_user(user.id);
+
+                // the raw token can only be seen here, once
+                web.insert("new_token", &raw_token);
+                web.insert("tokens", &tokens);
+                web.insert("all_scopes", &ALL_SCOPES);
+                res_html!("forum/user_api_tokens_page.html", web)
+            },
+            Err(info) => {
+                res_400!(info)
+            }
+        }
+    }
+
+    pub fn user_api_token_revoke(req: &mut Request) -> SapperResult<Response> {
+        let params = get_form_params!(req);
+        let id = t_param_parse!(params, "id", Uuid);
+        let user = get_ext!(req, AppUser).unwrap();
+
+        match ApiToken::revoke(id, user.id) {
+            Ok(_) => {
+                res_redirect!("/p/user/api_tokens")
+            },
+            Err(info) => {
+                res_400!(info)
+            }
+        }
+    }
+
+    pub fn user_totp_page(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let params = get_query_params!(req);
+        let required = t_param_parse_default!(params, "required", i64, 0);
+        let user = get_ext!(req, AppUser).unwrap();
+
+        web.insert("required", &(required == 1 || totp_required(user)));
+
+        if UserTotp::is_enabled_for(user.id) {
+            web.insert("enabled", &true);
+            return res_html!("forum/user_totp_page.html", web);
+        }
+
+        // every visit starts a fresh enrollment, until one code is confirmed
+        let user_totp_create = UserTotpCreate {
+            user_id: user.id,
+            secret: totp::generate_secret(),
+        };
+        match user_totp_create.insert() {
+            Ok(user_totp) => {
+                let issuer = envconfig::get_str_item("TOTP_ISSUER");
+                let uri = totp::otpauth_uri(&issuer, &user.account, &user_totp.secret);
+
+                web.insert("enabled", &false);
+                web.insert("secret", &user_totp.secret);
+                web.insert("qrcode", &totp::qrcode_svg(&uri));
+                res_html!("forum/user_totp_page.html", web)
+            },
+            Err(info) => {
+                res_500!(info)
+            }
+        }
+    }
+
+    pub fn user_totp_enable(req: &mut Request) -> SapperResult<Response> {
+        let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+        let params = get_form_params!(req);
+        let code = t_param!(params, "code");
+        let user = get_ext!(req, AppUser).unwrap();
+
+        let user_totp = match UserTotp::get_by_user_id(user.id) {
+            Ok(user_totp) => user_totp,
+            Err(info) => {
+                return res_400!(info);
+            }
+        };
+        if !totp::verify_code(&user_totp.secret, code) {
+            return res_400!("wrong authentication code, please scan the qr code again.");
+        }
+
+        // show the plain codes only once, keep the hashed ones
+        let recovery_codes = totp::generate_recovery_codes();
+        let user_totp_enable = UserTotpEnable {
+            user_id: user.id,
+            recovery_codes: recovery_codes.iter().map(|c| make_pwd_encode(c, &user.salt)).collect(),
+        };
+        match user_totp_enable.update() {
+            Ok(_) => {
+                web.insert("recovery_codes", &recovery_codes);
+                res_html!("forum/user_totp_recovery_codes.html", web)
+            },
+            Err(info) => {
+                res_500!(info)
+            }
+        }
+    }
+
+    pub fn user_totp_disable(req: &mut Request) -> SapperResult<Response> {
+        let params = get_form_params!(req);
+        let code = t_param!(params, "code");
+        let user = get_ext!(req, AppUser).unwrap();
+
+        if totp_required(user) {
+            return res_400!("two factor authentication is required for your role.");
+        }
+
+        let user_totp = match UserTotp::get_by_user_id(user.id) {
+            Ok(user_totp) => user_totp,
+            Err(info) => {
+                return res_400!(info);
+            }
+        };
+        if !totp::verify_code(&user_totp.secret, code)
+            && !user_totp.consume_recovery_code(code, &user.salt) {
+            return res_400!("wrong authentication code.");
+        }
+
+        let _ = UserTotp::delete_by_user_id(user.id);
+
+        res_redirect!("/account")
+    }
 
 }
 
@@ -323,6 +803,7 @@ impl SapperModule for UserPage {
 
         router.post("/register", Self::user_register);
         router.post("/login", Self::user_login);
+        router.post("/login/totp", Self::user_login_totp);
 
         router.get("/p/user/modifynickname", Self::user_modifynickname_page);
         router.post("/s/user/modifynickname", Self::user_modifynickname);
@@ -331,6 +812,21 @@ impl SapperModule for UserPage {
         router.post("/s/user/changepassword", Self::user_changepassword);
 
         router.get("/p/user/my_articles", Self::user_my_articles_page);
+        router.get("/p/user/bookmarks", Self::user_bookmarks_page);
+        router.get("/p/user/drafts", Self::user_my_drafts_page);
+        router.post("/s/user/notification_prefs", Self::user_notification_prefs);
+        router.post("/s/user/email_prefs", Self::user_email_prefs);
+        router.get("/unsubscribe", Self::unsubscribe_page);
+        router.post("/unsubscribe", Self::unsubscribe);
+        router.get("/confirm_email", Self::confirm_email);
+
+        router.get("/p/user/api_tokens", Self::user_api_tokens_page);
+        router.post("/s/user/api_token/create", Self::user_api_token_create);
+        router.post("/s/user/api_token/revoke", Self::user_api_token_revoke);
+
+        router.get("/p/user/totp", Self::user_totp_page);
+        router.post("/s/user/totp/enable", Self::user_totp_enable);
+        router.post("/s/user/totp/disable", Self::user_totp_disable);
 
 
         // this url will be called by remote github oauth2 server
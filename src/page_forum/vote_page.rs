@@ -0,0 +1,113 @@
+use sapper::{
+    Request,
+    Response,
+    Result as SapperResult,
+    Error as SapperError,
+    Module as SapperModule,
+    Router as SapperRouter};
+use sapper_std::*;
+use uuid::Uuid;
+use serde_json::json;
+
+use crate::AppUser;
+use crate::model::trash::{
+    ArticleRef,
+    CommentRef
+};
+use crate::model::vote::{
+    Vote,
+    VoteCast
+};
+use crate::page_cache::{self, Change};
+use crate::middleware::permission_need_login;
+
+pub struct VotePage;
+
+impl VotePage {
+
+    // called by vote.js, answers the new score
+    pub fn vote(req: &mut Request) -> SapperResult<Response> {
+        let params = get_form_params!(req);
+        let kind = t_param!(params, "kind").to_owned();
+        let id = t_param_parse!(params, "id", Uuid);
+        let value = t_param_parse!(params, "value", i16);
+        let user = get_ext!(req, AppUser).unwrap();
+
+        // the article page and, for articles, the section listing show the score
+        let (author_id, article_id, section_id) = match kind.as_str() {
+            "article" => {
+                match ArticleRef::get_by_id(id) {
+                    Ok(article_ref) => (article_ref.author_id, article_ref.id, Some(article_ref.section_id)),
+                    Err(info) => return res_400!(info),
+                }
+            },
+            "comment" => {
+                match CommentRef::get_by_id(id) {
+                    Ok(comment_ref) => (comment_ref.author_id, comment_ref.article_id, None),
+                    Err(info) => return res_400!(info),
+                }
+            },
+            _ => {
+                return res_400!("unknown vote target.");
+            }
+        };
+        if author_id == user.id {
+            return res_400!("can't vote on your own post.");
+        }
+
+        let vote_cast = VoteCast {
+            user_id: user.id,
+            kind,
+            target_id: id,
+            value,
+        };
+
+        match vote_cast.cast() {
+            Ok(score) => {
+                // an article vote shows in the listings, a comment vote only on the article
+                let change = match section_id {
+                    Some(section_id) => Change::ArticleScore { article_id, section_id },
+                    None => Change::CommentScore { article_id },
+                };
+                page_cache::invalidate(req, change);
+
+                res_json!(json!({
+                    "score": score,
+                    "value": value,
+                }))
+            },
+            Err(info) => {
+                res_400!(info)
+            }
+        }
+    }
+
+    // the cached article page is the same for everybody, so the arrows
+    // of the current user are lit afterwards from this
+    pub fn my_votes(req: &mut Request) -> SapperResult<Response> {
+        let params = get_query_params!(req);
+        let article_id = t_param_parse!(params, "article_id", Uuid);
+        let user = get_ext!(req, AppUser).unwrap();
+
+        let votes = Vote::my_votes_in_article(user.id, article_id);
+
+        res_json!(json!({ "votes": votes }))
+    }
+
+}
+
+
+impl SapperModule for VotePage {
+    fn before(&self, req: &mut Request) -> SapperResult<()> {
+        permission_need_login(req)?;
+
+        Ok(())
+    }
+
+    fn router(&self, router: &mut SapperRouter) -> SapperResult<()> {
+        router.post("/s/vote", Self::vote);
+        router.get("/p/vote/mine", Self::my_votes);
+
+        Ok(())
+    }
+}
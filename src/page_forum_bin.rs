@@ -13,6 +13,7 @@ use std::sync::{
     Arc,
     Mutex,
 };
+use std::collections::HashMap;
 
 //#[macro_use] extern crate sapper_std;
 use sapper::{
@@ -24,27 +25,42 @@ use sapper::{
     Key
 };
 use sapper_std::*;
+use sapper::header::{Authorization, Bearer};
 
 use rusoda::envconfig;
 use rusoda::db;
-use rusoda::cache;
 use rusoda::dataservice;
 use rusoda::util;
 use rusoda::github_utils;
 use rusoda::i18n;
 use rusoda::web_filters;
-use rusoda::rss;
 
 mod middleware;
 mod tantivy_index;
+mod totp;
+mod model;
+mod feed;
+mod text_diff;
+mod mention;
+mod rate_limit;
+mod mailer;
+mod webhook;
+mod seo;
+mod page_cache;
+mod conditional;
+mod compress;
 
 // include page modules
 mod page_forum;
 
 use self::dataservice::user::Ruser;
+use self::model::api_token::ApiToken;
+use self::model::notification::Notification;
 use self::tantivy_index::{
     DocFromIndexOuter, TanAction, Doc2Index,
 };
+use self::mailer::MailJob;
+use self::webhook::WebhookWake;
 
 
 
@@ -58,6 +74,33 @@ impl Key for AppUser {
     type Value = Ruser;
 }
 
+// present when the request is authenticated by a personal api token
+pub struct AppApiToken;
+impl Key for AppApiToken {
+    type Value = ApiToken;
+}
+
+// the static files, compressed at startup
+pub struct StaticAssets;
+impl Key for StaticAssets {
+    type Value = Arc<HashMap<String, compress::Precompressed>>;
+}
+
+pub struct RedisClient;
+impl Key for RedisClient {
+    type Value = redis::Client;
+}
+
+pub struct MailTx;
+impl Key for MailTx {
+   type Value = channel::Sender<MailJob>;
+}
+
+pub struct WebhookTx;
+impl Key for WebhookTx {
+   type Value = channel::Sender<WebhookWake>;
+}
+
 pub struct TanIndexTx;
 impl Key for TanIndexTx {
    type Value = channel::Sender<(TanAction, String, Option<Doc2Index>)>;
@@ -67,6 +110,12 @@ impl Key for TanQueryRx {
    type Value = channel::Receiver<Vec<DocFromIndexOuter>>;
 }
 
+// logins which passed the password check and wait for the totp code
+pub struct PendingTotpLogins;
+impl Key for PendingTotpLogins {
+   type Value = Arc<Mutex<HashMap<String, totp::PendingLogin>>>;
+}
+
 
 // define global smock
 struct PageForum;
@@ -85,6 +134,7 @@ impl SapperArmor for PageForum {
                     Ok(user) => {
                         if user.status == 0 {
                             web.insert("user", &user);
+                            web.insert("unread_notifications", &Notification::unread_count(user.id));
                             req.ext_mut().insert::<AppUser>(user);
                         }
                     },
@@ -94,6 +144,22 @@ impl SapperArmor for PageForum {
             None => {}
         }
 
+        // api calls can use a personal token instead of the session cookie
+        let (path, _) = req.uri();
+        if path.starts_with("/api/") && req.ext().get::<AppUser>().is_none() {
+            let raw_token = req.headers().get::<Authorization<Bearer>>().map(|auth| auth.0.token.clone());
+            if let Some(raw_token) = raw_token {
+                if let Ok(token) = ApiToken::get_by_raw_token(&raw_token) {
+                    if let Ok(user) = Ruser::get_user_by_id(token.user_id) {
+                        if user.status == 0 {
+                            req.ext_mut().insert::<AppUser>(user);
+                            req.ext_mut().insert::<AppApiToken>(token);
+                        }
+                    }
+                }
+            }
+        }
+
         // insert it to req
         req.ext_mut().insert::<AppWebContext>(web);
 
@@ -101,6 +167,9 @@ impl SapperArmor for PageForum {
     }
 
     fn after(&self, req: &Request, res: &mut Response) -> SapperResult<()> {
+        // static files included, so before the request is logged
+        compress::finish(req, res);
+        conditional::finish(req, res);
         sapper_std::finish(req, res)?;
         Ok(())
     }
@@ -119,6 +188,18 @@ fn main () {
 
     tantivy_index::run_tantivy(tan_index_rx, tan_query_tx);
 
+    let (mail_tx, mail_rx) = channel::unbounded::<MailJob>();
+    mailer::run_mailer(mail_rx);
+
+    let (webhook_tx, webhook_rx) = channel::unbounded::<WebhookWake>();
+    webhook::run_webhook_worker(webhook_rx);
+
+    let pending_totp_logins = Arc::new(Mutex::new(HashMap::new()));
+    let static_assets = Arc::new(compress::precompress_static("static"));
+
+    let redis_url = env::var("REDISURL").expect("REDISURL must be set");
+    let redis_client = redis::Client::open(redis_url.as_str()).expect("REDISURL is not valid");
+
     let addr = env::var("BINDADDR").expect("DBURL must be set");
     let port = env::var("BINDPORT").expect("REDISURL must be set").parse::<u32>().unwrap();
     let mut app = SapperApp::new();
@@ -127,6 +208,11 @@ fn main () {
         .init_global(Box::new(move |req: &mut Request| {
             req.ext_mut().insert::<TanIndexTx>(tan_index_tx.clone());
             req.ext_mut().insert::<TanQueryRx>(tan_query_rx.clone());
+            req.ext_mut().insert::<PendingTotpLogins>(pending_totp_logins.clone());
+            req.ext_mut().insert::<StaticAssets>(static_assets.clone());
+            req.ext_mut().insert::<RedisClient>(redis_client.clone());
+            req.ext_mut().insert::<MailTx>(mail_tx.clone());
+            req.ext_mut().insert::<WebhookTx>(webhook_tx.clone());
 
             Ok(())
         }))
@@ -136,6 +222,16 @@ fn main () {
         .add_module(Box::new(page_forum::section_page::SectionPage))
         .add_module(Box::new(page_forum::article_page::ArticlePage))
         .add_module(Box::new(page_forum::comment_page::CommentPage))
+        .add_module(Box::new(page_forum::api_page::ApiPage))
+        .add_module(Box::new(page_forum::admin_page::AdminPage))
+        .add_module(Box::new(page_forum::report_page::ReportPage))
+        .add_module(Box::new(page_forum::tag_page::TagPage))
+        .add_module(Box::new(page_forum::notification_page::NotificationPage))
+        .add_module(Box::new(page_forum::vote_page::VotePage))
+        .add_module(Box::new(page_forum::bookmark_page::BookmarkPage))
+        .add_module(Box::new(page_forum::follow_page::FollowPage))
+        .add_module(Box::new(page_forum::feed_page::FeedPage))
+        .add_module(Box::new(page_forum::seo_page::SeoPage))
         .static_file_service(true);
 
     println!("Start listen on http://{}:{}", addr, port);
@@ -0,0 +1,183 @@
+// token bucket flood control for posting, kept in redis beside the page cache
+use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::Utc;
+use sapper::{
+    status,
+    Request,
+    Response,
+    Result as SapperResult
+};
+use sapper_std::*;
+use log::warn;
+
+use crate::envconfig;
+use crate::{AppWebContext, AppUser, RedisClient};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateAction {
+    Article,
+    Comment,
+    Edit,
+}
+
+impl RateAction {
+    fn name(&self) -> &'static str {
+        match self {
+            RateAction::Article => "article",
+            RateAction::Comment => "comment",
+            RateAction::Edit => "edit",
+        }
+    }
+
+    fn config_key(&self) -> &'static str {
+        match self {
+            RateAction::Article => "RATE_LIMIT_ARTICLE",
+            RateAction::Comment => "RATE_LIMIT_COMMENT",
+            RateAction::Edit => "RATE_LIMIT_EDIT",
+        }
+    }
+}
+
+/// A bucket of `capacity` tokens, refilled completely in `window` seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketRule {
+    pub capacity: f64,
+    pub window: u64,
+}
+
+impl BucketRule {
+    /// Parse `"5/3600"`: 5 posts per 3600 seconds.
+    pub fn parse(s: &str) -> Option<BucketRule> {
+        let mut parts = s.trim().splitn(2, '/');
+        let capacity = parts.next()?.trim().parse::<f64>().ok()?;
+        let window = parts.next()?.trim().parse::<u64>().ok()?;
+        if capacity <= 0.0 || window == 0 {
+            return None;
+        }
+
+        Some(BucketRule { capacity, window })
+    }
+
+    pub fn scaled(&self, factor: f64) -> BucketRule {
+        BucketRule {
+            capacity: (self.capacity * factor).max(1.0),
+            window: self.window,
+        }
+    }
+}
+
+// refill every bucket, then take one token from each only if all of them
+// have one, so a denied post drains none; returns {allowed, seconds to wait}
+// ARGV is now, then capacity and window of each key in turn
+const TOKEN_BUCKET_LUA: &str = r#"
+local now = tonumber(ARGV[1])
+local tokens = {}
+local wait = 0
+for i, key in ipairs(KEYS) do
+    local capacity = tonumber(ARGV[i * 2])
+    local window = tonumber(ARGV[i * 2 + 1])
+    local rate = capacity / window
+    local data = redis.call('HMGET', key, 'tokens', 'ts')
+    local t = tonumber(data[1]) or capacity
+    local ts = tonumber(data[2]) or now
+    t = math.min(capacity, t + math.max(0, now - ts) * rate)
+    if t < 1 then
+        wait = math.max(wait, math.ceil((1 - t) / rate))
+    end
+    tokens[i] = t
+end
+local allowed = 0
+if wait == 0 then
+    allowed = 1
+end
+for i, key in ipairs(KEYS) do
+    local t = tokens[i]
+    if allowed == 1 then
+        t = t - 1
+    end
+    redis.call('HMSET', key, 'tokens', tostring(t), 'ts', tostring(now))
+    redis.call('EXPIRE', key, tonumber(ARGV[i * 2 + 1]))
+end
+return {allowed, wait}
+"#;
+
+fn take_tokens(req: &Request, buckets: &[(String, BucketRule)]) -> Result<u64, redis::RedisError> {
+    let client = get_ext!(req, RedisClient).unwrap();
+    let mut con = client.get_connection()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+    let script = redis::Script::new(TOKEN_BUCKET_LUA);
+    let mut invocation = script.prepare_invoke();
+    invocation.arg(now);
+    for (key, rule) in buckets {
+        invocation.key(key).arg(rule.capacity).arg(rule.window);
+    }
+    let (allowed, wait): (i64, i64) = invocation.invoke(&mut con)?;
+
+    if allowed == 1 {
+        Ok(0)
+    }
+    else {
+        Ok(wait.max(1) as u64)
+    }
+}
+
+/// The address the request came from, behind a trusted proxy it is X-Real-IP.
+pub fn client_ip(req: &Request) -> String {
+    if envconfig::get_int_item("TRUST_PROXY") == 1 {
+        if let Some(ip) = req.headers().get_raw("X-Real-IP").and_then(|v| v.first()) {
+            return String::from_utf8_lossy(ip).trim().to_string();
+        }
+    }
+
+    req.remote_addr().ip().to_string()
+}
+
+/// Check the user and ip buckets of this action.
+/// Returns None when the post may go on, or the seconds to wait.
+pub fn check(req: &Request, action: RateAction) -> Option<u64> {
+    let rule = match BucketRule::parse(&envconfig::get_str_item(action.config_key())) {
+        Some(rule) => rule,
+        // no (valid) rule configured, no limit
+        None => return None,
+    };
+
+    let mut keys: Vec<(String, BucketRule)> = Vec::new();
+    if let Some(user) = get_ext!(req, AppUser) {
+        // fresh accounts get a smaller bucket
+        let new_days = envconfig::get_int_item("RATE_LIMIT_NEW_ACCOUNT_DAYS");
+        let is_new = (Utc::now() - user.signup_time).num_days() < new_days;
+        let user_rule = if is_new { rule.scaled(0.5) } else { rule };
+        keys.push((format!("ratelimit:{}:user:{}", action.name(), user.id), user_rule));
+    }
+    // several people can share one address
+    let ip_factor = envconfig::get_int_item("RATE_LIMIT_IP_FACTOR").max(1) as f64;
+    keys.push((format!("ratelimit:{}:ip:{}", action.name(), client_ip(req)), rule.scaled(ip_factor)));
+
+    // the user and ip buckets in one call, both or neither give a token
+    let wait = match take_tokens(req, &keys) {
+        Ok(wait) => wait,
+        Err(e) => {
+            // never block posting because redis is away
+            warn!("rate limit check on {} failed: {:?}", action.name(), e);
+            0
+        }
+    };
+
+    if wait > 0 {
+        Some(wait)
+    }
+    else {
+        None
+    }
+}
+
+pub fn slow_down_page(req: &mut Request, wait: u64) -> SapperResult<Response> {
+    let mut web = get_ext_owned!(req, AppWebContext).unwrap();
+    web.insert("wait", &wait);
+
+    res_html!("forum/slow_down.html", web).map(|mut response| {
+        response.set_status(status::TooManyRequests);
+        response
+    })
+}
@@ -0,0 +1,67 @@
+// canonical urls and the metadata crawlers read from a page
+use uuid::Uuid;
+
+use crate::envconfig;
+
+// longer titles are cut in the slug, the id is what finds the article
+const SLUG_MAX_CHARS: usize = 60;
+const DESCRIPTION_MAX_CHARS: usize = 200;
+
+// "Hello, Rust 2018!" -> "hello-rust-2018", non ascii titles get no slug
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        }
+        else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= SLUG_MAX_CHARS {
+            break;
+        }
+    }
+
+    slug.trim_end_matches('-').to_owned()
+}
+
+// `/article/:id/:slug`, the url the other ones point at with rel=canonical
+pub fn article_path(id: Uuid, title: &str) -> String {
+    let slug = slugify(title);
+    // "/article/:id/rss" and "/article/:id/atom" are its comments feeds
+    if slug.is_empty() || slug == "rss" || slug == "atom" {
+        format!("/article/{}", id)
+    }
+    else {
+        format!("/article/{}/{}", id, slug)
+    }
+}
+
+pub fn absolute_url(path: &str) -> String {
+    format!("{}{}", envconfig::get_str_item("HOST_DOMAIN"), path)
+}
+
+// the first paragraph of the markdown, as plain text
+pub fn description_of(raw: &str) -> String {
+    // textareas post \r\n
+    let raw = raw.replace("\r\n", "\n");
+    let paragraph = raw.split("\n\n")
+        .map(|p| p.trim())
+        .find(|p| !p.is_empty() && !p.starts_with("```"))
+        .unwrap_or("");
+
+    let text: String = paragraph.lines()
+        .map(|line| line.trim().trim_start_matches(|c| c == '#' || c == '>' || c == '-' || c == '*').trim())
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .chars()
+        .filter(|c| *c != '*' && *c != '`' && *c != '_')
+        .collect();
+
+    let mut description: String = text.chars().take(DESCRIPTION_MAX_CHARS).collect();
+    if text.chars().count() > DESCRIPTION_MAX_CHARS {
+        description.push_str("...");
+    }
+
+    description
+}
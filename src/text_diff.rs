@@ -0,0 +1,157 @@
+// line level diff of two texts, for the article revision history
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffLine {
+    // "same", "add" or "del"
+    pub kind: &'static str,
+    pub text: String,
+}
+
+// cells of the lcs table, past this the middle is shown as replaced whole
+const MAX_TABLE_CELLS: usize = 4_000_000;
+
+fn push_all(lines: &mut Vec<DiffLine>, kind: &'static str, texts: &[&str]) {
+    for text in texts {
+        lines.push(DiffLine { kind, text: text.to_string() });
+    }
+}
+
+/// Diff `old` against `new` line by line, on their longest common subsequence.
+///
+/// The common head and tail are matched first, so the table only covers the
+/// changed middle; a middle too big for it comes out as deleted then added.
+pub fn line_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+
+    let mut lines = Vec::with_capacity(a.len().max(b.len()));
+    push_all(&mut lines, "same", &a[..prefix]);
+    if (a_mid.len() + 1).saturating_mul(b_mid.len() + 1) > MAX_TABLE_CELLS {
+        push_all(&mut lines, "del", a_mid);
+        push_all(&mut lines, "add", b_mid);
+    }
+    else {
+        lcs_diff(a_mid, b_mid, &mut lines);
+    }
+    push_all(&mut lines, "same", &a[a.len() - suffix..]);
+
+    lines
+}
+
+fn lcs_diff(a: &[&str], b: &[&str], lines: &mut Vec<DiffLine>) {
+    let (n, m) = (a.len(), b.len());
+
+    // lcs[i][j]: length of the lcs of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            }
+            else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            lines.push(DiffLine { kind: "same", text: a[i].to_string() });
+            i += 1;
+            j += 1;
+        }
+        else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            lines.push(DiffLine { kind: "del", text: a[i].to_string() });
+            i += 1;
+        }
+        else {
+            lines.push(DiffLine { kind: "add", text: b[j].to_string() });
+            j += 1;
+        }
+    }
+    push_all(lines, "del", &a[i..]);
+    push_all(lines, "add", &b[j..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(lines: &[DiffLine]) -> Vec<(&'static str, &str)> {
+        lines.iter().map(|line| (line.kind, line.text.as_str())).collect()
+    }
+
+    #[test]
+    fn equal_texts_are_all_same() {
+        let diff = line_diff("a\nb\nc", "a\nb\nc");
+        assert_eq!(kinds(&diff), vec![("same", "a"), ("same", "b"), ("same", "c")]);
+    }
+
+    #[test]
+    fn empty_texts() {
+        assert!(line_diff("", "").is_empty());
+        assert_eq!(kinds(&line_diff("", "a\nb")), vec![("add", "a"), ("add", "b")]);
+        assert_eq!(kinds(&line_diff("a\nb", "")), vec![("del", "a"), ("del", "b")]);
+    }
+
+    #[test]
+    fn change_in_the_middle() {
+        let diff = line_diff("a\nb\nc\nd", "a\nx\nc\nd");
+        assert_eq!(kinds(&diff), vec![("same", "a"), ("del", "b"), ("add", "x"), ("same", "c"), ("same", "d")]);
+    }
+
+    #[test]
+    fn insert_and_delete_at_the_ends() {
+        let diff = line_diff("b\nc", "a\nb\nc\nd");
+        assert_eq!(kinds(&diff), vec![("add", "a"), ("same", "b"), ("same", "c"), ("add", "d")]);
+
+        let diff = line_diff("a\nb\nc\nd", "b\nc");
+        assert_eq!(kinds(&diff), vec![("del", "a"), ("same", "b"), ("same", "c"), ("del", "d")]);
+    }
+
+    #[test]
+    fn prefix_and_suffix_do_not_overlap() {
+        // "a" is both a common head and a common tail of these
+        let diff = line_diff("a\na", "a");
+        assert_eq!(kinds(&diff), vec![("same", "a"), ("del", "a")]);
+
+        let diff = line_diff("a", "a\na");
+        assert_eq!(kinds(&diff), vec![("same", "a"), ("add", "a")]);
+    }
+
+    #[test]
+    fn common_lines_in_the_middle_are_kept() {
+        let diff = line_diff("h\n1\nk\n2\nt", "h\n3\nk\n4\nt");
+        assert_eq!(kinds(&diff), vec![
+            ("same", "h"),
+            ("del", "1"), ("add", "3"),
+            ("same", "k"),
+            ("del", "2"), ("add", "4"),
+            ("same", "t"),
+        ]);
+    }
+
+    #[test]
+    fn huge_middle_is_replaced_whole() {
+        let old: Vec<String> = (0..3000).map(|i| format!("old {}", i)).collect();
+        let new: Vec<String> = (0..3000).map(|i| format!("new {}", i)).collect();
+        let old = format!("head\n{}\ntail", old.join("\n"));
+        let new = format!("head\n{}\ntail", new.join("\n"));
+
+        let diff = line_diff(&old, &new);
+        assert_eq!(diff.len(), 6002);
+        assert_eq!(kinds(&diff[..2]), vec![("same", "head"), ("del", "old 0")]);
+        assert!(diff[1..3001].iter().all(|line| line.kind == "del"));
+        assert!(diff[3001..6001].iter().all(|line| line.kind == "add"));
+        assert_eq!(kinds(&diff[6001..]), vec![("same", "tail")]);
+    }
+}
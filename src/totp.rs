@@ -0,0 +1,230 @@
+// RFC 6238 time based one time passwords, used as the second login step
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use uuid::Uuid;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type HmacSha1 = Hmac<Sha1>;
+
+pub const TOTP_DIGITS: u32 = 6;
+pub const TOTP_STEP: u64 = 30;
+// how many steps before and after now we accept, to tolerate clock drift
+pub const TOTP_SKEW: u64 = 1;
+pub const RECOVERY_CODE_COUNT: usize = 8;
+
+/// Make a new base32 encoded 160 bits shared secret.
+pub fn generate_secret() -> String {
+    // two v4 uuids give us enough random bytes for a sha1 sized key
+    let mut bytes: Vec<u8> = Vec::with_capacity(32);
+    bytes.extend_from_slice(Uuid::new_v4().as_bytes());
+    bytes.extend_from_slice(Uuid::new_v4().as_bytes());
+    bytes.truncate(20);
+
+    base32::encode(base32::Alphabet::RFC4648 { padding: false }, &bytes)
+}
+
+/// The `otpauth://` uri which authenticator apps read from the qr code.
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = url_encode(issuer),
+        account = url_encode(account),
+        secret = secret,
+        digits = TOTP_DIGITS,
+        period = TOTP_STEP
+    )
+}
+
+/// Render the otpauth uri as an inline svg qr code.
+pub fn qrcode_svg(uri: &str) -> String {
+    match qrcode::QrCode::new(uri.as_bytes()) {
+        Ok(code) => {
+            code.render::<qrcode::render::svg::Color>()
+                .min_dimensions(200, 200)
+                .build()
+        },
+        Err(_) => {
+            "".to_string()
+        }
+    }
+}
+
+/// HOTP value (RFC 4226) for the counter.
+pub fn hotp(key: &[u8], counter: u64) -> u32 {
+    let mut mac = HmacSha1::new_varkey(key).expect("hmac accepts keys of any size");
+    mac.input(&counter.to_be_bytes());
+    let hash = mac.result().code();
+
+    // dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = ((hash[offset] as u32 & 0x7f) << 24)
+        | ((hash[offset + 1] as u32) << 16)
+        | ((hash[offset + 2] as u32) << 8)
+        | (hash[offset + 3] as u32);
+
+    binary % 10u32.pow(TOTP_DIGITS)
+}
+
+/// TOTP value for the unix time in seconds.
+pub fn totp_at(key: &[u8], unix_time: u64) -> u32 {
+    hotp(key, unix_time / TOTP_STEP)
+}
+
+/// Check a code typed by the user against the base32 secret, now.
+pub fn verify_code(secret: &str, code: &str) -> bool {
+    verify_code_at(secret, code, now_secs())
+}
+
+pub fn verify_code_at(secret: &str, code: &str, unix_time: u64) -> bool {
+    let key = match base32::decode(base32::Alphabet::RFC4648 { padding: false }, secret) {
+        Some(key) => key,
+        None => return false,
+    };
+
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    let code = match code.parse::<u32>() {
+        Ok(code) => code,
+        Err(_) => return false,
+    };
+
+    let counter = unix_time / TOTP_STEP;
+    let start = counter.saturating_sub(TOTP_SKEW);
+    (start..=counter + TOTP_SKEW).any(|c| hotp(&key, c) == code)
+}
+
+/// Make a batch of one time recovery codes, in `xxxxx-xxxxx` form.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let s = Uuid::new_v4().simple().to_string();
+            format!("{}-{}", &s[0..5], &s[5..10])
+        })
+        .collect()
+}
+
+fn url_encode(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'@' => {
+                out.push(b as char);
+            },
+            _ => {
+                out.push_str(&format!("%{:02X}", b));
+            }
+        }
+    }
+    out
+}
+
+// a login between the password step and the totp step
+pub const PENDING_LOGIN_TTL: u64 = 300;
+pub const PENDING_LOGIN_MAX_ATTEMPTS: u32 = 5;
+
+#[derive(Debug, Clone)]
+pub struct PendingLogin {
+    pub user_id: Uuid,
+    pub since: u64,
+    pub attempts: u32,
+}
+
+impl PendingLogin {
+    pub fn new(user_id: Uuid) -> PendingLogin {
+        PendingLogin {
+            user_id,
+            since: now_secs(),
+            attempts: 0,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        now_secs() > self.since + PENDING_LOGIN_TTL || self.attempts >= PENDING_LOGIN_MAX_ATTEMPTS
+    }
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the rfc 6238 sha1 seed, "12345678901234567890"
+    const SEED: &[u8] = b"12345678901234567890";
+    const SEED_BASE32: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn hotp_rfc4226_vectors() {
+        let expected = [
+            755224, 287082, 359152, 969429, 338314,
+            254676, 287922, 162583, 399871, 520489,
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(hotp(SEED, counter as u64), *code, "counter {}", counter);
+        }
+    }
+
+    // the rfc lists 8 digits, we keep the last 6
+    #[test]
+    fn totp_rfc6238_sha1_vectors() {
+        let expected = [
+            (59, 287082),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+            (20000000000, 353130),
+        ];
+        for (time, code) in expected.iter() {
+            assert_eq!(totp_at(SEED, *time), *code, "time {}", time);
+        }
+    }
+
+    #[test]
+    fn verify_keeps_leading_zeros() {
+        assert!(verify_code_at(SEED_BASE32, "081804", 1111111109));
+        assert!(verify_code_at(SEED_BASE32, "005924", 1234567890));
+        assert!(!verify_code_at(SEED_BASE32, "5924", 1234567890));
+    }
+
+    #[test]
+    fn verify_window_edges() {
+        // the code of step 1, seconds 30 to 59
+        let code = "287082";
+        assert!(verify_code_at(SEED_BASE32, code, 30));
+        // one step of skew on each side
+        assert!(verify_code_at(SEED_BASE32, code, 0));
+        assert!(verify_code_at(SEED_BASE32, code, 89));
+        // two steps away
+        assert!(!verify_code_at(SEED_BASE32, code, 90));
+
+        // step 0 near the epoch, where the window can't go below 0
+        assert!(verify_code_at(SEED_BASE32, "755224", 0));
+        assert!(verify_code_at(SEED_BASE32, "755224", 59));
+        assert!(!verify_code_at(SEED_BASE32, "755224", 60));
+    }
+
+    #[test]
+    fn verify_rejects_malformed() {
+        assert!(verify_code_at(SEED_BASE32, " 287082 ", 59));
+        assert!(!verify_code_at(SEED_BASE32, "28708", 59));
+        assert!(!verify_code_at(SEED_BASE32, "2870820", 59));
+        assert!(!verify_code_at(SEED_BASE32, "28708a", 59));
+        assert!(!verify_code_at(SEED_BASE32, "+28708", 59));
+        assert!(!verify_code_at("not base32!", "287082", 59));
+    }
+
+    #[test]
+    fn generated_secret_decodes_to_160_bits() {
+        let secret = generate_secret();
+        let key = base32::decode(base32::Alphabet::RFC4648 { padding: false }, &secret).unwrap();
+        assert_eq!(key.len(), 20);
+    }
+}
@@ -0,0 +1,261 @@
+// outgoing webhooks: events are stored as deliveries, and a worker thread
+// posts them, retrying with exponential backoff
+use std::thread;
+use std::time::Duration;
+use chrono::Utc;
+use crossbeam::channel::{Receiver, RecvTimeoutError};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use log::warn;
+use sapper::Request;
+use sapper_std::*;
+use serde_json::json;
+
+use crate::envconfig;
+use crate::WebhookTx;
+use crate::dataservice::article::Article;
+use crate::dataservice::comment::Comment;
+use crate::model::webhook::{
+    Webhook,
+    WebhookDelivery,
+    WebhookDeliveryCreate,
+    WebhookAttempt,
+    DELIVERY_OK,
+    DELIVERY_FAILED,
+    DELIVERY_PENDING
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// how long the worker sleeps when nobody wakes it, retries become due meanwhile
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// 30s, 1m, 2m, 4m, ... then give up
+pub const MAX_ATTEMPTS: i32 = 8;
+const FIRST_RETRY_SECS: i64 = 30;
+// enough of the answer to debug with
+const MAX_RESPONSE_BODY: usize = 2000;
+
+// new deliveries are waiting
+pub struct WebhookWake;
+
+/// `sha256=` and the hex hmac of the payload, sent as `X-Forustm-Signature-256`.
+pub fn sign(secret: &str, payload: &str) -> String {
+    let mut mac = HmacSha256::new_varkey(secret.as_bytes()).expect("hmac accepts keys of any size");
+    mac.input(payload.as_bytes());
+    let hex: String = mac.result().code().iter().map(|b| format!("{:02x}", b)).collect();
+
+    format!("sha256={}", hex)
+}
+
+fn retry_delay_secs(attempts: i32) -> i64 {
+    FIRST_RETRY_SECS << (attempts.max(1) - 1).min(16)
+}
+
+// the delivery status once `attempts` were made, the last one `ok` or not
+fn status_after(ok: bool, attempts: i32) -> i16 {
+    if ok {
+        DELIVERY_OK
+    }
+    else if attempts >= MAX_ATTEMPTS {
+        DELIVERY_FAILED
+    }
+    else {
+        DELIVERY_PENDING
+    }
+}
+
+pub fn article_data(article: &Article) -> serde_json::Value {
+    json!({
+        "id": article.id,
+        "title": article.title,
+        "section_id": article.section_id,
+        "author_id": article.author_id,
+        "tags": article.tags,
+        "url": format!("{}/article?id={}", envconfig::get_str_item("HOST_DOMAIN"), article.id),
+    })
+}
+
+pub fn comment_data(comment: &Comment) -> serde_json::Value {
+    json!({
+        "id": comment.id,
+        "article_id": comment.article_id,
+        "author_id": comment.author_id,
+        "raw_content": comment.raw_content,
+        "url": format!("{}/article?id={}", envconfig::get_str_item("HOST_DOMAIN"), comment.article_id),
+    })
+}
+
+fn wake_worker(req: &Request) {
+    if let Some(webhook_tx) = get_ext!(req, WebhookTx) {
+        let _ = webhook_tx.send(WebhookWake);
+    }
+}
+
+fn queue_delivery(hook: &Webhook, event: &str, data: &serde_json::Value) {
+    let delivery_create = WebhookDeliveryCreate {
+        webhook_id: hook.id,
+        event: event.to_string(),
+        payload: json!({
+            "event": event,
+            "created_time": Utc::now(),
+            "data": data,
+        }).to_string(),
+    };
+    if let Err(info) = delivery_create.insert() {
+        warn!("webhook {} event {}: {}", hook.id, event, info);
+    }
+}
+
+/// Queue the event for every hook listening to it.
+pub fn dispatch(req: &Request, event: &str, data: serde_json::Value) {
+    let hooks = Webhook::subscribed_to(event);
+    if hooks.is_empty() {
+        return;
+    }
+    for hook in hooks.iter() {
+        queue_delivery(hook, event, &data);
+    }
+
+    wake_worker(req);
+}
+
+/// Queue one event for one hook, whatever it listens to.
+pub fn dispatch_to(req: &Request, hook: &Webhook, event: &str, data: serde_json::Value) {
+    queue_delivery(hook, event, &data);
+
+    wake_worker(req);
+}
+
+/// Send a stored delivery again, as a new delivery with the same payload.
+pub fn redeliver(req: &Request, delivery: &WebhookDelivery) -> Result<WebhookDelivery, String> {
+    let delivery_create = WebhookDeliveryCreate {
+        webhook_id: delivery.webhook_id,
+        event: delivery.event.clone(),
+        payload: delivery.payload.clone(),
+    };
+    let new_delivery = delivery_create.insert()?;
+
+    wake_worker(req);
+    Ok(new_delivery)
+}
+
+fn attempt(client: &reqwest::Client, hook: &Webhook, delivery: &WebhookDelivery) -> WebhookAttempt {
+    let result = client.post(&hook.url)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "Forustm-Webhook")
+        .header("X-Forustm-Event", delivery.event.as_str())
+        .header("X-Forustm-Delivery", delivery.id.to_string())
+        .header("X-Forustm-Signature-256", sign(&hook.secret, &delivery.payload))
+        .body(delivery.payload.clone())
+        .send();
+
+    let attempts = delivery.attempts + 1;
+    let (response_status, response_body, error, ok) = match result {
+        Ok(mut response) => {
+            let status = response.status();
+            let mut body = response.text().unwrap_or_default();
+            if body.len() > MAX_RESPONSE_BODY {
+                let mut end = MAX_RESPONSE_BODY;
+                while !body.is_char_boundary(end) {
+                    end -= 1;
+                }
+                body.truncate(end);
+            }
+            (Some(status.as_u16() as i32), body, "".to_string(), status.is_success())
+        },
+        Err(e) => (None, "".to_string(), e.to_string(), false),
+    };
+
+    WebhookAttempt {
+        id: delivery.id,
+        response_status,
+        response_body,
+        error,
+        status: status_after(ok, attempts),
+        retry_in_secs: retry_delay_secs(attempts),
+    }
+}
+
+fn deliver_due(client: &reqwest::Client) {
+    for delivery in WebhookDelivery::due(50) {
+        let webhook_attempt = match Webhook::get_by_id(delivery.webhook_id) {
+            Ok(hook) => attempt(client, &hook, &delivery),
+            Err(_) => continue,
+        };
+        if let Err(info) = webhook_attempt.update() {
+            warn!("{}", info);
+        }
+    }
+}
+
+pub fn run_webhook_worker(webhook_rx: Receiver<WebhookWake>) {
+
+    let client = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+	Ok(client) => client,
+	Err(e) => {
+	    panic!("{:?}", e);
+	}
+    };
+
+    thread::spawn(move || {
+	loop {
+	    match webhook_rx.recv_timeout(POLL_INTERVAL) {
+		Ok(_) | Err(RecvTimeoutError::Timeout) => {
+		    deliver_due(&client);
+		},
+		Err(RecvTimeoutError::Disconnected) => break,
+	    }
+	}
+    });
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_is_hex_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            sign("key", "The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn retries_double_from_thirty_seconds() {
+        assert_eq!(retry_delay_secs(1), 30);
+        assert_eq!(retry_delay_secs(2), 60);
+        assert_eq!(retry_delay_secs(3), 120);
+        assert_eq!(retry_delay_secs(MAX_ATTEMPTS - 1), 30 * 64);
+        // nothing was tried yet
+        assert_eq!(retry_delay_secs(0), 30);
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        let cap = 30 << 16;
+        assert_eq!(retry_delay_secs(17), cap);
+        assert_eq!(retry_delay_secs(18), cap);
+        assert_eq!(retry_delay_secs(i32::max_value()), cap);
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        for attempts in 1..MAX_ATTEMPTS {
+            assert_eq!(status_after(false, attempts), DELIVERY_PENDING);
+        }
+        assert_eq!(status_after(false, MAX_ATTEMPTS), DELIVERY_FAILED);
+        assert_eq!(status_after(false, MAX_ATTEMPTS + 1), DELIVERY_FAILED);
+
+        // a success is kept whenever it comes
+        assert_eq!(status_after(true, 1), DELIVERY_OK);
+        assert_eq!(status_after(true, MAX_ATTEMPTS), DELIVERY_OK);
+    }
+}
@@ -0,0 +1,38 @@
+// bookmark toggle of the article page, with the opt-in to hear about new comments
+function showBookmark($bookmark, bookmarked, notify) {
+    $bookmark.find(".bookmark-toggle").text(bookmarked ? "Bookmarked" : "Bookmark").toggleClass("bookmarked", bookmarked);
+    $bookmark.find(".bookmark-notify-label").toggle(bookmarked);
+    $bookmark.find(".bookmark-notify").prop("checked", notify);
+}
+
+$(function () {
+    var $bookmark = $(".bookmark");
+    if ($bookmark.length == 0) {
+	return;
+    }
+    var articleId = $bookmark.data("article-id");
+
+    $bookmark.find(".bookmark-toggle").click(function (e) {
+	e.preventDefault();
+	$.post("/s/bookmark/toggle", { article_id: articleId }, function (res) {
+	    showBookmark($bookmark, res.bookmarked, res.notify);
+	    $bookmark.find(".bookmark-count").text(res.count);
+	}, "json").fail(function (xhr) {
+	    alert(xhr.responseText);
+	});
+    });
+
+    $bookmark.find(".bookmark-notify").change(function () {
+	var notify = $(this).is(":checked") ? 1 : 0;
+	$.post("/s/bookmark/notify", { article_id: articleId, notify: notify }, function (res) {
+	    showBookmark($bookmark, res.bookmarked, res.notify);
+	}, "json").fail(function (xhr) {
+	    alert(xhr.responseText);
+	});
+    });
+
+    // the page may come from the cache, show the bookmark of the current user
+    $.getJSON("/p/bookmark/mine", { article_id: articleId }, function (res) {
+	showBookmark($bookmark, res.bookmarked, res.notify);
+    });
+});
@@ -0,0 +1,26 @@
+// follow button of the section and blog pages
+function showFollow($follow, following) {
+    $follow.find(".follow-toggle").text(following ? "Following" : "Follow").toggleClass("following", following);
+}
+
+$(function () {
+    $(".follow").each(function () {
+	var $follow = $(this);
+	var target = { kind: $follow.data("kind"), id: $follow.data("id") };
+
+	$follow.find(".follow-toggle").click(function (e) {
+	    e.preventDefault();
+	    $.post("/s/follow", target, function (res) {
+		showFollow($follow, res.following);
+		$follow.find(".follow-count").text(res.followers);
+	    }, "json").fail(function (xhr) {
+		alert(xhr.responseText);
+	    });
+	});
+
+	// the page may come from the cache, show the state of the current user
+	$.getJSON("/p/follow/state", target, function (res) {
+	    showFollow($follow, res.following);
+	});
+    });
+});
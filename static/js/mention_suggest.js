@@ -0,0 +1,59 @@
+// offer nicknames while an @mention is being typed in a textarea
+function mentionSuggest($textarea) {
+    var $list = $("<ul>").addClass("mention-suggest").hide();
+    $textarea.after($list);
+
+    // the @prefix right before the caret, or null
+    function currentPrefix() {
+	var before = $textarea.val().slice(0, $textarea[0].selectionStart);
+	var m = before.match(/(^|[^\w@])@([\w.\-]{1,32})$/);
+	return m ? m[2] : null;
+    }
+
+    function pick(nickname) {
+	var el = $textarea[0];
+	var value = $textarea.val();
+	var caret = el.selectionStart;
+	var prefix = currentPrefix();
+	if (prefix === null) {
+	    return;
+	}
+	var start = caret - prefix.length;
+	$textarea.val(value.slice(0, start) + nickname + " " + value.slice(caret));
+	el.selectionStart = el.selectionEnd = start + nickname.length + 1;
+	$list.hide();
+	$textarea.focus();
+    }
+
+    var timer = null;
+    $textarea.on("input click", function () {
+	clearTimeout(timer);
+	timer = setTimeout(function () {
+	    var prefix = currentPrefix();
+	    if (prefix === null) {
+		$list.hide();
+		return;
+	    }
+	    $.getJSON("/api/v1/users", { q: prefix }, function (res) {
+		$list.empty();
+		$.each(res.users, function (i, user) {
+		    $("<li>").text(user.nickname).on("mousedown", function (e) {
+			e.preventDefault();
+			pick(user.nickname);
+		    }).appendTo($list);
+		});
+		$list.toggle(res.users.length > 0);
+	    });
+	}, 200);
+    });
+
+    $textarea.on("blur", function () {
+	$list.hide();
+    });
+}
+
+$(function () {
+    $("textarea[name=raw_content]").each(function () {
+	mentionSuggest($(this));
+    });
+});
@@ -0,0 +1,33 @@
+// autocomplete the last tag being typed in a comma separated tags input
+function tagSuggest($input) {
+    var listId = "tag_suggestions";
+    var $list = $("<datalist>").attr("id", listId);
+    $input.attr("list", listId).attr("autocomplete", "off").after($list);
+
+    var timer = null;
+    $input.on("input", function () {
+	clearTimeout(timer);
+	timer = setTimeout(function () {
+	    var value = $input.val();
+	    var parts = value.split(",");
+	    var last = $.trim(parts.pop());
+	    if (last == "") {
+		$list.empty();
+		return;
+	    }
+	    var head = parts.length > 0 ? parts.join(",") + ", " : "";
+	    $.getJSON("/api/v1/tags", { q: last }, function (res) {
+		$list.empty();
+		$.each(res.tags, function (i, tag) {
+		    $list.append($("<option>").attr("value", head + tag.name));
+		});
+	    });
+	}, 200);
+    });
+}
+
+$(function () {
+    $("input[name=tags]").each(function () {
+	tagSuggest($(this));
+    });
+});
@@ -0,0 +1,43 @@
+// up and down arrows of the article page, voting again the same way takes the vote back
+function voteOn($vote, value) {
+    if ($vote.data("value") == value) {
+	value = 0;
+    }
+    $.post("/s/vote", { kind: $vote.data("kind"), id: $vote.data("id"), value: value }, function (res) {
+	showVote($vote, res.value);
+	$vote.find(".score").text(res.score);
+    }, "json").fail(function (xhr) {
+	alert(xhr.responseText);
+    });
+}
+
+function showVote($vote, value) {
+    $vote.data("value", value);
+    $vote.find(".vote-up").toggleClass("voted", value == 1);
+    $vote.find(".vote-down").toggleClass("voted", value == -1);
+}
+
+$(function () {
+    $(".vote").each(function () {
+	var $vote = $(this);
+	$vote.data("value", 0);
+	$vote.find(".vote-up").click(function (e) {
+	    e.preventDefault();
+	    voteOn($vote, 1);
+	});
+	$vote.find(".vote-down").click(function (e) {
+	    e.preventDefault();
+	    voteOn($vote, -1);
+	});
+    });
+
+    // the page may come from the cache, light the arrows of the current user
+    var articleId = $("[data-vote-article]").data("vote-article");
+    if (articleId) {
+	$.getJSON("/p/vote/mine", { article_id: articleId }, function (res) {
+	    $.each(res.votes, function (i, vote) {
+		showVote($(".vote[data-id=" + vote.target_id + "]"), vote.value);
+	    });
+	});
+    }
+});